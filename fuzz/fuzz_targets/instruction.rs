@@ -0,0 +1,58 @@
+//! One arbitrary opcode run in an arbitrary state. States that `load_state`
+//! rejects are skipped, so its checks are fuzzed as well.
+
+#![no_main]
+
+use chip8::invariants;
+use chip8::{ColourAttributes, CPU};
+use libfuzzer_sys::arbitrary::{self, Arbitrary};
+use libfuzzer_sys::fuzz_target;
+
+#[derive(Arbitrary, Debug)]
+struct Input {
+    platform: u8,
+    quirks: [bool; 10],
+    pc: u16,
+    v: [u8; 16],
+    i: u32,
+    stack: Vec<u16>,
+    timers: (u8, u8),
+    key_wait: Option<(u8, Option<u8>)>,
+    // The size of the screen and of the colours, and the background.
+    size: Option<(u8, u8)>,
+    colours: Option<(u8, u8)>,
+    backgrounds: u8,
+    opcode: u16,
+    memory: Vec<u8>,
+}
+
+fuzz_target!(|input: Input| {
+    let platform = chip8_fuzz::platform(input.platform);
+    let mut state = CPU::with_platform(platform).save_state().unwrap();
+    state.quirks = chip8_fuzz::quirks(input.quirks);
+    state.pc = input.pc;
+    state.v = input.v;
+    state.i = input.i;
+    state.stack = input.stack;
+    (state.delay_timer, state.sound_timer) = input.timers;
+    state.key_wait = input.key_wait;
+    state.memory = input.memory;
+    if let Some((width, height)) = input.size {
+        (state.width, state.height) = (width as usize, height as usize);
+        state.pixels = vec![0; (state.width * state.height).div_ceil(8)];
+    }
+    let (width, height) = input.colours.map_or((state.width, state.height), |(width, height)| (width as usize, height as usize));
+    state.colours = ColourAttributes::new(width, height);
+    for _ in 0..input.backgrounds % 4 {
+        state.colours.cycle_background();
+    }
+
+    let mut cpu = CPU::new();
+    if cpu.load_state(&state).is_err() {
+        return;
+    }
+    cpu.execute(input.opcode);
+    if let Err(err) = invariants::check(&cpu).and_then(|_| invariants::check_round_trip(&mut cpu)) {
+        panic!("{}", err);
+    }
+});
@@ -0,0 +1,45 @@
+//! Arbitrary ROMs with arbitrary quirks and key presses, checking the
+//! invariants after every instruction and save states every frame.
+
+#![no_main]
+
+use chip8::differential::{Config, KeyEvent};
+use chip8::invariants::Run;
+use chip8::Engine;
+use libfuzzer_sys::arbitrary::{self, Arbitrary};
+use libfuzzer_sys::fuzz_target;
+
+// MEGA-CHIP's 16 MiB make every frame slow, so runs are short.
+const FRAMES: usize = 10;
+
+#[derive(Arbitrary, Debug)]
+struct Input {
+    platform: u8,
+    cached_blocks: bool,
+    quirks: [bool; 10],
+    cycles: u8,
+    // Frame, key and whether it is pressed.
+    keys: Vec<(u8, u8, bool)>,
+    program: Vec<u8>,
+}
+
+fuzz_target!(|input: Input| {
+    let platform = chip8_fuzz::platform(input.platform);
+    let engine = if input.cached_blocks { Engine::CachedBlocks } else { Engine::Interpreter };
+    let run = Run {
+        program: input.program,
+        platform,
+        config: Config { engine, quirks: chip8_fuzz::quirks(input.quirks) },
+        seed: 0,
+        cycles: input.cycles as usize % 32 + 1,
+        frames: FRAMES,
+        input: input
+            .keys
+            .iter()
+            .map(|&(frame, key, pressed)| KeyEvent { frame: frame as usize % FRAMES, key: key & 0xF, pressed })
+            .collect(),
+    };
+    if let Err(err) = run.check() {
+        panic!("{}", err);
+    }
+});
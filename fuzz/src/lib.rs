@@ -0,0 +1,23 @@
+//! What the fuzz targets share: turning arbitrary bytes into a platform and
+//! quirks.
+
+use chip8::{Platform, Quirks};
+
+pub fn platform(byte: u8) -> Platform {
+    Platform::ALL[byte as usize % Platform::ALL.len()]
+}
+
+pub fn quirks(bits: [bool; 10]) -> Quirks {
+    Quirks {
+        wrap_sprites: bits[0],
+        count_collided_rows: bits[1],
+        key_wait_release: bits[2],
+        hires: bits[3],
+        shift: bits[4],
+        memory_leave_i_unchanged: bits[5],
+        memory_increment_by_x: bits[6],
+        jump: bits[7],
+        logic: bits[8],
+        vblank: bits[9],
+    }
+}
@@ -0,0 +1,367 @@
+//! Static control-flow analysis of a ROM: basic blocks, the edges between
+//! them, subroutines, computed jumps, unreachable bytes and instructions that
+//! write over code.
+//!
+//! Code is found by following every path from the entry point, so data is
+//! never decoded. BNNN jump targets can't be known without running the program;
+//! when NNN points at a table of jumps, as it usually does, each jump in the
+//! table is taken as a target.
+
+use crate::decoder::{self, Instruction};
+use crate::platform::Platform;
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt::Write;
+use std::ops::Range;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum EdgeKind {
+    /// On to the next instruction, including when a skip doesn't skip.
+    Next,
+    Jump,
+    Call,
+    /// From a block ending in 00EE to the instruction after a call.
+    Return,
+    /// Over the next instruction.
+    Skip,
+    /// A possible BNNN target.
+    Computed,
+}
+
+/// An edge between the blocks starting at `from` and `to`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Edge {
+    pub from: u16,
+    pub to: u16,
+    pub kind: EdgeKind,
+}
+
+/// Instructions that always run one after the other.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Block {
+    pub start: u16,
+    pub instructions: Vec<(u16, Instruction)>,
+}
+
+impl Block {
+    /// Address after the last instruction.
+    pub fn end(&self) -> u16 {
+        let (addr, instruction) = self.instructions.last().unwrap();
+        addr + instruction.size() as u16
+    }
+
+    pub fn last(&self) -> Instruction {
+        self.instructions.last().unwrap().1
+    }
+}
+
+/// An FX33 or FX55 writing to `target`, where there is code, with I set by
+/// an ANNN in the same block.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct SelfModification {
+    pub at: u16,
+    pub target: u16,
+}
+
+pub struct Cfg {
+    pub entry: u16,
+    pub blocks: BTreeMap<u16, Block>,
+    pub edges: BTreeSet<Edge>,
+    /// The blocks of each subroutine, by entry point. Blocks shared by
+    /// several subroutines belong to each.
+    pub subroutines: BTreeMap<u16, BTreeSet<u16>>,
+    /// Addresses of the BNNN instructions.
+    pub computed_jumps: BTreeSet<u16>,
+    pub self_modifying: Vec<SelfModification>,
+    /// Bytes of the program no path reaches: data, or dead code.
+    pub unreachable: Vec<Range<u16>>,
+}
+
+// Whether the instruction is the last of its block.
+fn ends_block(instruction: Instruction) -> bool {
+    use Instruction::*;
+    instruction.is_skip() || matches!(instruction, Jump(_) | Call(_) | Return | JumpOffset(_))
+}
+
+// Where execution can go after `instruction` at `addr`. Calls go on after the
+// call as well, once the subroutine returns.
+fn successors(memory: &[u8], addr: u16, instruction: Instruction, platform: Platform) -> Vec<(u16, EdgeKind)> {
+    use Instruction::*;
+    let next = addr.wrapping_add(instruction.size() as u16);
+    match instruction {
+        Jump(target) => vec![(target, EdgeKind::Jump)],
+        Call(target) => vec![(target, EdgeKind::Call), (next, EdgeKind::Next)],
+        Return => Vec::new(),
+        JumpOffset(table) => (table as usize..memory.len())
+            .step_by(2)
+            .take_while(|&entry| matches!(decoder::decode_at(memory, entry, platform), Jump(_)))
+            .map(|entry| (entry as u16, EdgeKind::Computed))
+            .collect(),
+        _ if instruction.is_skip() => vec![(next, EdgeKind::Next), (next.wrapping_add(2), EdgeKind::Skip)],
+        _ => vec![(next, EdgeKind::Next)],
+    }
+}
+
+/// Builds the control-flow graph of `program` as loaded on `platform`.
+pub fn analyze(program: &[u8], platform: Platform) -> Cfg {
+    let start = platform.load_address();
+    let mut memory = vec![0; start as usize];
+    memory.extend_from_slice(program);
+    let code = start as usize..memory.len();
+    let entry = platform.entry_point(program);
+
+    // Every reachable instruction, and the addresses that start a block.
+    let mut instructions = BTreeMap::new();
+    let mut leaders = BTreeSet::from([entry]);
+    let mut calls = BTreeSet::new();
+    let mut pending = vec![entry];
+    while let Some(addr) = pending.pop() {
+        if !code.contains(&(addr as usize)) || instructions.contains_key(&addr) {
+            continue;
+        }
+        let instruction = decoder::decode_at(&memory, addr as usize, platform);
+        instructions.insert(addr, instruction);
+        let successors = successors(&memory, addr, instruction, platform);
+        if let Instruction::Call(target) = instruction {
+            calls.insert((addr, target));
+        }
+        for &(target, _) in successors.iter() {
+            if ends_block(instruction) {
+                leaders.insert(target);
+            }
+            pending.push(target);
+        }
+    }
+
+    let mut cfg = Cfg {
+        entry,
+        blocks: BTreeMap::new(),
+        edges: BTreeSet::new(),
+        subroutines: BTreeMap::new(),
+        computed_jumps: BTreeSet::new(),
+        self_modifying: Vec::new(),
+        unreachable: Vec::new(),
+    };
+
+    for &leader in leaders.iter().filter(|leader| instructions.contains_key(leader)) {
+        let mut block = Block { start: leader, instructions: Vec::new() };
+        let mut addr = leader;
+        while let Some(&instruction) = instructions.get(&addr) {
+            block.instructions.push((addr, instruction));
+            addr = addr.wrapping_add(instruction.size() as u16);
+            if ends_block(instruction) || leaders.contains(&addr) {
+                break;
+            }
+        }
+        let (last_addr, last) = *block.instructions.last().unwrap();
+        if let Instruction::JumpOffset(_) = last {
+            cfg.computed_jumps.insert(last_addr);
+        }
+        for (target, kind) in successors(&memory, last_addr, last, platform) {
+            // The instruction after a call is reached through Return edges.
+            if !matches!((last, kind), (Instruction::Call(_), EdgeKind::Next)) {
+                cfg.edges.insert(Edge { from: leader, to: target, kind });
+            }
+        }
+        cfg.blocks.insert(leader, block);
+    }
+
+    for &(_, target) in calls.iter() {
+        let blocks = cfg.subroutine_blocks(target);
+        cfg.subroutines.insert(target, blocks);
+    }
+    for &(site, target) in calls.iter() {
+        let after = site + 2;
+        for &block in cfg.subroutines[&target].iter() {
+            if cfg.blocks[&block].last() == Instruction::Return {
+                cfg.edges.insert(Edge { from: block, to: after, kind: EdgeKind::Return });
+            }
+        }
+    }
+
+    let mut code_bytes = vec![false; memory.len()];
+    for (&addr, instruction) in instructions.iter() {
+        let end = (addr as usize + instruction.size()).min(memory.len());
+        code_bytes[addr as usize..end].iter_mut().for_each(|byte| *byte = true);
+    }
+    cfg.self_modifying = self_modifications(&cfg.blocks, &code_bytes);
+    let mut addr = code.start;
+    while addr < code.end {
+        let len = code_bytes[addr..code.end].iter().take_while(|&&byte| !byte).count();
+        if len > 0 {
+            cfg.unreachable.push(addr as u16..(addr + len) as u16);
+        }
+        addr += len.max(1);
+    }
+    cfg
+}
+
+// FX33 and FX55 writing over code with I set earlier in the same block.
+fn self_modifications(blocks: &BTreeMap<u16, Block>, code_bytes: &[bool]) -> Vec<SelfModification> {
+    use Instruction::*;
+    let mut modifications = Vec::new();
+    for block in blocks.values() {
+        let mut i = None;
+        for &(addr, instruction) in block.instructions.iter() {
+            let written = match instruction {
+                Bcd(_) => 3,
+                Store(x) => x as usize + 1,
+                _ => 0,
+            };
+            if let (Some(i), true) = (i, written > 0) {
+                let target = (i..i + written).find(|&addr| code_bytes.get(addr).copied().unwrap_or(false));
+                if let Some(target) = target {
+                    modifications.push(SelfModification { at: addr, target: target as u16 });
+                }
+            }
+            i = match instruction {
+                LoadI(nnn) => Some(nnn as usize),
+                LoadLongI(addr) => Some(addr as usize),
+                // FX55 and FX65 may move I, depending on the quirks.
+                AddI(_) | Font(_) | Store(_) | Load(_) => None,
+                _ => i,
+            };
+        }
+    }
+    modifications
+}
+
+impl Cfg {
+    // Blocks reachable from `entry` without entering other subroutines.
+    fn subroutine_blocks(&self, entry: u16) -> BTreeSet<u16> {
+        let mut blocks = BTreeSet::new();
+        let mut pending = vec![entry];
+        while let Some(start) = pending.pop() {
+            let block = match self.blocks.get(&start) {
+                Some(block) if blocks.insert(start) => block,
+                _ => continue,
+            };
+            if let Instruction::Call(_) = block.last() {
+                pending.push(block.end());
+            }
+            let edges = self.edges.range(Edge { from: start, to: 0, kind: EdgeKind::Next }..);
+            for edge in edges.take_while(|edge| edge.from == start) {
+                if edge.kind != EdgeKind::Call && edge.kind != EdgeKind::Return {
+                    pending.push(edge.to);
+                }
+            }
+        }
+        blocks
+    }
+
+    /// The graph in Graphviz DOT format, one node per block.
+    pub fn to_dot(&self) -> String {
+        let mut dot = String::new();
+        writeln!(dot, "digraph cfg {{").unwrap();
+        writeln!(dot, "    node [shape=box fontname=monospace];").unwrap();
+        for block in self.blocks.values() {
+            let mut label = String::new();
+            for (addr, instruction) in block.instructions.iter() {
+                write!(label, "{:#05X}  {}\\l", addr, instruction).unwrap();
+            }
+            let style = if block.start == self.entry {
+                " style=bold"
+            } else if self.subroutines.contains_key(&block.start) {
+                " peripheries=2"
+            } else {
+                ""
+            };
+            writeln!(dot, "    \"{:#05X}\" [label=\"{}\"{}];", block.start, label, style).unwrap();
+        }
+        for edge in self.edges.iter() {
+            let style = match edge.kind {
+                EdgeKind::Next | EdgeKind::Jump => "",
+                EdgeKind::Call => " [style=dashed label=call]",
+                EdgeKind::Return => " [style=dotted label=return]",
+                EdgeKind::Skip => " [label=skip]",
+                EdgeKind::Computed => " [color=red label=computed]",
+            };
+            writeln!(dot, "    \"{:#05X}\" -> \"{:#05X}\"{};", edge.from, edge.to, style).unwrap();
+        }
+        writeln!(dot, "}}").unwrap();
+        dot
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    fn edge(from: u16, to: u16, kind: EdgeKind) -> Edge {
+        Edge { from, to, kind }
+    }
+
+    #[test]
+    fn test_blocks_and_edges() {
+        let program = [
+            0x22, 0x08, // 0x200: call 0x208
+            0x30, 0x01, // 0x202: skip if V0 == 1
+            0x12, 0x00, // 0x204: jump 0x200
+            0x12, 0x06, // 0x206: jump 0x206
+            0x60, 0x01, // 0x208: V0 = 1
+            0x00, 0xEE, // 0x20A: return
+            0xFF, 0xFF, // 0x20C: data
+        ];
+        let cfg = analyze(&program, Platform::Chip8);
+        assert_eq!(cfg.blocks.keys().copied().collect::<Vec<_>>(), vec![0x200, 0x202, 0x204, 0x206, 0x208]);
+        assert_eq!(cfg.blocks[&0x208].end(), 0x20C);
+        assert_eq!(
+            cfg.edges.iter().copied().collect::<Vec<_>>(),
+            vec![
+                edge(0x200, 0x208, EdgeKind::Call),
+                edge(0x202, 0x204, EdgeKind::Next),
+                edge(0x202, 0x206, EdgeKind::Skip),
+                edge(0x204, 0x200, EdgeKind::Jump),
+                edge(0x206, 0x206, EdgeKind::Jump),
+                edge(0x208, 0x202, EdgeKind::Return),
+            ]
+        );
+        assert_eq!(cfg.subroutines[&0x208], BTreeSet::from([0x208]));
+        assert_eq!(cfg.unreachable, vec![0x20C..0x20E]);
+        assert!(cfg.to_dot().contains("\"0x208\" -> \"0x202\" [style=dotted label=return];"));
+    }
+
+    #[test]
+    fn test_computed_jump_table() {
+        let program = [
+            0xB2, 0x04, // 0x200: jump to 0x204 + V0
+            0x00, 0x00, // 0x202: never reached
+            0x12, 0x0A, // 0x204: table: jump 0x20A
+            0x12, 0x0A, // 0x206: jump 0x20A
+            0xFF, 0xFF, // 0x208: end of the table
+            0x12, 0x0A, // 0x20A: jump 0x20A
+        ];
+        let cfg = analyze(&program, Platform::Chip8);
+        assert_eq!(cfg.computed_jumps, BTreeSet::from([0x200]));
+        let targets: Vec<_> = cfg.edges.iter().filter(|edge| edge.kind == EdgeKind::Computed).map(|edge| edge.to).collect();
+        assert_eq!(targets, vec![0x204, 0x206]);
+        assert_eq!(cfg.unreachable, vec![0x202..0x204, 0x208..0x20A]);
+    }
+
+    #[test]
+    fn test_self_modifying() {
+        let program = [
+            0xA2, 0x06, // 0x200: I = 0x206
+            0xF1, 0x55, // 0x202: store V0 and V1 at I
+            0xA3, 0x00, // 0x204: I = 0x300
+            0xF0, 0x33, // 0x206: BCD of V0 at I
+            0x12, 0x06, // 0x208: jump 0x206
+        ];
+        let cfg = analyze(&program, Platform::Chip8);
+        assert_eq!(cfg.self_modifying, vec![SelfModification { at: 0x202, target: 0x206 }]);
+    }
+
+    #[test]
+    fn test_analyze_games() {
+        let games = fs::read_dir(concat!(env!("CARGO_MANIFEST_DIR"), "/resources/games")).unwrap();
+        for game in games {
+            let path = game.unwrap().path();
+            let program = fs::read(&path).unwrap();
+            let cfg = analyze(&program, Platform::Chip8);
+            assert!(cfg.blocks.contains_key(&cfg.entry), "{:?}", path);
+            for edge in cfg.edges.iter() {
+                assert!(cfg.blocks.contains_key(&edge.from), "{:?} {:?}", path, edge);
+            }
+        }
+    }
+}
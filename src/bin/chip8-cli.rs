@@ -0,0 +1,281 @@
+//! Headless runner for the emulator.
+//!
+//! ```text
+//! chip8-cli list
+//! chip8-cli run <rom file or builtin name> [frames]
+//! chip8-cli profile <rom file or builtin name> [frames]
+//! chip8-cli coverage <rom file or builtin name> [frames] [symbols file]
+//! chip8-cli lcov <rom file or builtin name> <symbols file> [frames]
+//! chip8-cli cfg <rom file or builtin name> [dot]
+//! chip8-cli recompile <rom file or builtin name>
+//! chip8-cli bench <rom file or builtin name> [frames]
+//! chip8-cli diff <rom file or builtin name> [frames] [seed]
+//! chip8-cli suite <directory> [update]
+//! chip8-cli gym <rom file or builtin name> [steps] [seed]
+//! ```
+
+use chip8::differential::{self, Config};
+use chip8::gym;
+use chip8::suite::Outcome;
+use chip8::{Chip8, Engine, Symbols};
+use std::env;
+use std::fs;
+use std::path::Path;
+use std::process;
+use std::time::Instant;
+
+const DEFAULT_FRAMES: usize = 600;
+const DEFAULT_STEPS: usize = 10_000;
+// Rows in each table of the profile report.
+const PROFILE_TOP: usize = 10;
+
+fn main() {
+    let args: Vec<String> = env::args().skip(1).collect();
+    let result = match args.first().map(String::as_str) {
+        Some("list") => list(),
+        Some("run") if args.len() > 1 => run(&args[1], args.get(2)),
+        Some("profile") if args.len() > 1 => profile(&args[1], args.get(2)),
+        Some("coverage") if args.len() > 1 => coverage(&args[1], args.get(2), args.get(3), false),
+        Some("lcov") if args.len() > 2 => coverage(&args[1], args.get(3), args.get(2), true),
+        Some("cfg") if args.len() > 1 => cfg(&args[1], args.get(2).map(String::as_str)),
+        Some("recompile") if args.len() > 1 => recompile(&args[1]),
+        Some("bench") if args.len() > 1 => bench(&args[1], args.get(2)),
+        Some("diff") if args.len() > 1 => diff(&args[1], args.get(2), args.get(3)),
+        Some("suite") if args.len() > 1 => suite(&args[1], args.get(2).map(String::as_str)),
+        Some("gym") if args.len() > 1 => gym(&args[1], args.get(2), args.get(3)),
+        _ => Err(usage()),
+    };
+    if let Err(message) = result {
+        eprintln!("{}", message);
+        process::exit(1);
+    }
+}
+
+fn usage() -> String {
+    "usage: chip8-cli list\n       chip8-cli run <rom file or builtin name> [frames]\n       \
+     chip8-cli profile <rom file or builtin name> [frames]\n       \
+     chip8-cli coverage <rom file or builtin name> [frames] [symbols file]\n       \
+     chip8-cli lcov <rom file or builtin name> <symbols file> [frames]\n       \
+     chip8-cli cfg <rom file or builtin name> [dot]\n       \
+     chip8-cli recompile <rom file or builtin name>\n       \
+     chip8-cli bench <rom file or builtin name> [frames]\n       \
+     chip8-cli diff <rom file or builtin name> [frames] [seed]\n       \
+     chip8-cli suite <directory> [update]\n       \
+     chip8-cli gym <rom file or builtin name> [steps] [seed]"
+        .to_string()
+}
+
+#[cfg(feature = "builtin-roms")]
+fn list() -> Result<(), String> {
+    for rom in chip8::library::BUILTIN_ROMS.iter() {
+        println!("{:<10} {}", rom.name, rom.controls);
+    }
+    Ok(())
+}
+
+#[cfg(not(feature = "builtin-roms"))]
+fn list() -> Result<(), String> {
+    Err("built without the builtin-roms feature".to_string())
+}
+
+/// Loads a ROM from a file, or from the bundled games when no such file exists.
+fn load(chip8: &mut Chip8, rom: &str) -> Result<(), String> {
+    if let Ok(data) = fs::read(rom) {
+        return chip8.open_rom(rom, &data).map_err(|err| format!("{}: {}", rom, err));
+    }
+    #[cfg(feature = "builtin-roms")]
+    {
+        if chip8.load_builtin(rom) {
+            return Ok(());
+        }
+    }
+    Err(format!("no ROM file or builtin game named {}", rom))
+}
+
+fn parse_frames(frames: Option<&String>) -> Result<usize, String> {
+    match frames {
+        Some(frames) => frames.parse().map_err(|_| usage()),
+        None => Ok(DEFAULT_FRAMES),
+    }
+}
+
+fn run(rom: &str, frames: Option<&String>) -> Result<(), String> {
+    let frames = parse_frames(frames)?;
+    let mut chip8 = Chip8::new();
+    load(&mut chip8, rom)?;
+    if let Some(info) = chip8.rom_info() {
+        println!("{}", info);
+    }
+    for _ in 0..frames {
+        chip8.run_frame(chip8.tick_rate());
+    }
+    print!("{}", chip8.screen_text());
+    Ok(())
+}
+
+/// Runs a ROM with profiling on and prints where it spent its time.
+fn profile(rom: &str, frames: Option<&String>) -> Result<(), String> {
+    let frames = parse_frames(frames)?;
+    let mut chip8 = Chip8::new();
+    load(&mut chip8, rom)?;
+    chip8.set_profiling(true);
+    for _ in 0..frames {
+        chip8.run_frame(chip8.tick_rate());
+    }
+    print!("{}", chip8.profile_report(PROFILE_TOP).unwrap());
+    Ok(())
+}
+
+/// Runs a ROM recording coverage, then prints the annotated disassembly or,
+/// with `lcov`, a tracefile of the source lines in the symbols.
+fn coverage(rom: &str, frames: Option<&String>, symbols: Option<&String>, lcov: bool) -> Result<(), String> {
+    let frames = parse_frames(frames)?;
+    let mut chip8 = Chip8::new();
+    load(&mut chip8, rom)?;
+    if let Some(symbols) = symbols {
+        let text = fs::read_to_string(symbols).map_err(|err| format!("{}: {}", symbols, err))?;
+        chip8.set_symbols(Symbols::parse(&text).map_err(|err| format!("{}: {}", symbols, err))?);
+    }
+    chip8.set_coverage(true);
+    for _ in 0..frames {
+        chip8.run_frame(chip8.tick_rate());
+    }
+    let report = if lcov { chip8.coverage_lcov() } else { chip8.coverage_listing() };
+    print!("{}", report.unwrap());
+    Ok(())
+}
+
+/// Prints what static analysis finds in a ROM, or its control-flow graph in
+/// DOT format.
+fn cfg(rom: &str, format: Option<&str>) -> Result<(), String> {
+    let mut chip8 = Chip8::new();
+    load(&mut chip8, rom)?;
+    let cfg = chip8.analyze();
+    match format {
+        Some("dot") => print!("{}", cfg.to_dot()),
+        Some(_) => return Err(usage()),
+        None => {
+            println!("{} blocks, {} edges, entry {:#05X}", cfg.blocks.len(), cfg.edges.len(), cfg.entry);
+            for (entry, blocks) in cfg.subroutines.iter() {
+                println!("subroutine {:#05X}: {} blocks", entry, blocks.len());
+            }
+            for addr in cfg.computed_jumps.iter() {
+                println!("computed jump at {:#05X}", addr);
+            }
+            for modification in cfg.self_modifying.iter() {
+                println!("{:#05X} writes over code at {:#05X}", modification.at, modification.target);
+            }
+            for range in cfg.unreachable.iter() {
+                println!("unreachable {:#05X}-{:#05X} ({} bytes)", range.start, range.end - 1, range.len());
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Prints a ROM recompiled to a Rust module.
+fn recompile(rom: &str) -> Result<(), String> {
+    let mut chip8 = Chip8::new();
+    load(&mut chip8, rom)?;
+    let name = Path::new(rom).file_name().map_or(rom.into(), |name| name.to_string_lossy());
+    print!("{}", chip8.recompile(&name));
+    Ok(())
+}
+
+/// Times a ROM with each engine, with the same random numbers, and checks
+/// that they end on the same screen.
+fn bench(rom: &str, frames: Option<&String>) -> Result<(), String> {
+    let frames = parse_frames(frames)?;
+    let mut screens = Vec::new();
+    for engine in [Engine::Interpreter, Engine::CachedBlocks] {
+        let mut chip8 = Chip8::new();
+        load(&mut chip8, rom)?;
+        chip8.set_engine(engine);
+        chip8.seed_random(0);
+        let start = Instant::now();
+        for _ in 0..frames {
+            chip8.run_frame(chip8.tick_rate());
+        }
+        let seconds = start.elapsed().as_secs_f64();
+        println!("{:<14} {:>8.3}s {:>10.0} frames/s", format!("{:?}", engine), seconds, frames as f64 / seconds);
+        screens.push(chip8.screen_text());
+    }
+    if screens[0] != screens[1] {
+        return Err("the engines ended on different screens".to_string());
+    }
+    Ok(())
+}
+
+/// Runs a ROM with random input on the interpreter and on the cached blocks,
+/// and prints a repro dump if they diverge.
+fn diff(rom: &str, frames: Option<&String>, seed: Option<&String>) -> Result<(), String> {
+    let frames = parse_frames(frames)?;
+    let seed = match seed {
+        Some(seed) => seed.parse().map_err(|_| usage())?,
+        None => 0,
+    };
+    let mut chip8 = Chip8::new();
+    load(&mut chip8, rom)?;
+    let (mut test, config) = chip8.differential();
+    test.frames = frames;
+    test.seed = seed;
+    test.input = differential::random_input(seed, frames);
+    let reference = Config { engine: Engine::Interpreter, ..config };
+    let cached = Config { engine: Engine::CachedBlocks, ..config };
+    test.run(reference, cached).map_err(|divergence| divergence.to_string())?;
+    println!("no divergence in {} frames", frames);
+    Ok(())
+}
+
+/// Runs a directory of test ROMs and prints how each did, or records their
+/// screens as the golden ones with `update`.
+fn suite(dir: &str, mode: Option<&str>) -> Result<(), String> {
+    let update = match mode {
+        Some("update") => true,
+        Some(_) => return Err(usage()),
+        None => false,
+    };
+    let results = chip8::suite::run_suite(Path::new(dir), update)?;
+    for (name, outcome) in results.iter() {
+        println!("{:<16} {}", name, outcome);
+    }
+    let failed = results.iter().filter(|(_, outcome)| *outcome != Outcome::Pass).count();
+    if failed > 0 {
+        return Err(format!("{} of {} tests failed", failed, results.len()));
+    }
+    Ok(())
+}
+
+/// Plays a bundled game with random keys in the Gym environment and prints
+/// the rewards it gets.
+fn gym(rom: &str, steps: Option<&String>, seed: Option<&String>) -> Result<(), String> {
+    let steps = match steps {
+        Some(steps) => steps.parse().map_err(|_| usage())?,
+        None => DEFAULT_STEPS,
+    };
+    let seed = match seed {
+        Some(seed) => seed.parse().map_err(|_| usage())?,
+        None => 0,
+    };
+    let mut chip8 = Chip8::new();
+    load(&mut chip8, rom)?;
+    let name = Path::new(rom).file_name().map_or(rom.into(), |name| name.to_string_lossy());
+    let game = gym::for_game(&name).ok_or_else(|| format!("no rewards known for {}", name))?;
+    let mut env = chip8.env(game).map_err(|err| err.to_string())?;
+    env.reset(seed);
+    let mut score = 0;
+    for _ in 0..steps {
+        let keys = env.sample();
+        let step = env.step(&keys);
+        if step.reward != 0.0 {
+            println!("frame {:>6} reward {:>3} score {}", step.info.frame, step.reward, step.info.score);
+        }
+        if step.done {
+            println!("game over at frame {} with score {}", step.info.frame, step.info.score);
+            return Ok(());
+        }
+        score = step.info.score;
+    }
+    println!("{} steps played, score {}", steps, score);
+    Ok(())
+}
@@ -0,0 +1,273 @@
+//! Memory as seen by the CPU. Every instruction fetch, I-relative load and
+//! store, and sprite read goes through a `Bus`, so accesses can be observed or
+//! vetoed without touching the instructions themselves.
+
+use serde::Serialize;
+use std::any::Any;
+use std::collections::{BTreeSet, VecDeque};
+use std::ops::Range;
+
+/// Why the CPU touches an address.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+pub enum Access {
+    /// Reading an instruction at PC.
+    Fetch,
+    /// Reading data: sprites, FX65, the word after MEGA-CHIP's 01NN.
+    Read,
+    /// FX33 and FX55.
+    Write,
+}
+
+pub trait Bus {
+    /// Number of addressable bytes. Addresses past the end wrap around.
+    fn size(&self) -> usize;
+
+    fn read(&mut self, addr: usize, access: Access) -> u8;
+
+    fn write(&mut self, addr: usize, value: u8);
+
+    /// Zeroes the memory and resizes it to `size` bytes, the platform's memory
+    /// size. Buses for machines with other sizes, such as XO-CHIP's 64K, may
+    /// keep theirs.
+    fn reset(&mut self, size: usize);
+
+    /// Copies `data` to `addr` without going through the hooks. Used to load
+    /// the font and the program.
+    fn load(&mut self, addr: usize, data: &[u8]);
+
+    /// The whole memory, for reading without counting as an access.
+    fn bytes(&self) -> &[u8];
+}
+
+/// Observes the accesses of a `Memory`.
+pub trait MemoryHook: Any {
+    fn on_read(&mut self, _addr: usize, _value: u8, _access: Access) {}
+
+    /// Called before a write. Returning false drops it.
+    fn on_write(&mut self, _addr: usize, _old: u8, _value: u8) -> bool {
+        true
+    }
+}
+
+/// Plain RAM, the default `Bus`, with any number of hooks.
+pub struct Memory {
+    bytes: Vec<u8>,
+    hooks: Vec<Box<dyn MemoryHook>>,
+}
+
+impl Default for Memory {
+    fn default() -> Memory {
+        Memory::new(4096)
+    }
+}
+
+impl Memory {
+    pub fn new(size: usize) -> Memory {
+        Memory {
+            bytes: vec![0; size],
+            hooks: Vec::new(),
+        }
+    }
+
+    pub fn bytes_mut(&mut self) -> &mut [u8] {
+        &mut self.bytes
+    }
+
+    /// Hooks are called in the order they were added and survive resets.
+    pub fn add_hook<H: MemoryHook>(&mut self, hook: H) {
+        self.hooks.push(Box::new(hook));
+    }
+
+    /// The first hook of type `H`.
+    pub fn hook<H: MemoryHook>(&self) -> Option<&H> {
+        self.hooks.iter().find_map(|hook| (hook.as_ref() as &dyn Any).downcast_ref())
+    }
+
+    pub fn hook_mut<H: MemoryHook>(&mut self) -> Option<&mut H> {
+        self.hooks.iter_mut().find_map(|hook| (hook.as_mut() as &mut dyn Any).downcast_mut())
+    }
+
+    /// Removes every hook of type `H`.
+    pub fn remove_hooks<H: MemoryHook>(&mut self) {
+        self.hooks.retain(|hook| !(hook.as_ref() as &dyn Any).is::<H>());
+    }
+}
+
+impl Bus for Memory {
+    fn size(&self) -> usize {
+        self.bytes.len()
+    }
+
+    fn read(&mut self, addr: usize, access: Access) -> u8 {
+        let addr = addr % self.bytes.len();
+        let value = self.bytes[addr];
+        for hook in self.hooks.iter_mut() {
+            hook.on_read(addr, value, access);
+        }
+        value
+    }
+
+    fn write(&mut self, addr: usize, value: u8) {
+        let addr = addr % self.bytes.len();
+        let old = self.bytes[addr];
+        let mut allowed = true;
+        for hook in self.hooks.iter_mut() {
+            allowed &= hook.on_write(addr, old, value);
+        }
+        if allowed {
+            self.bytes[addr] = value;
+        }
+    }
+
+    fn reset(&mut self, size: usize) {
+        self.bytes.clear();
+        self.bytes.resize(size, 0);
+    }
+
+    fn load(&mut self, addr: usize, data: &[u8]) {
+        self.bytes[addr..addr + data.len()].copy_from_slice(data);
+    }
+
+    fn bytes(&self) -> &[u8] {
+        &self.bytes
+    }
+}
+
+/// One access to a watched address.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize)]
+pub struct WatchHit {
+    pub addr: usize,
+    pub access: Access,
+    pub value: u8,
+}
+
+/// Records accesses to a set of addresses.
+#[derive(Default)]
+pub struct Watchpoints {
+    pub addresses: BTreeSet<usize>,
+    pub hits: Vec<WatchHit>,
+}
+
+impl MemoryHook for Watchpoints {
+    fn on_read(&mut self, addr: usize, value: u8, access: Access) {
+        if self.addresses.contains(&addr) {
+            self.hits.push(WatchHit { addr, access, value });
+        }
+    }
+
+    fn on_write(&mut self, addr: usize, _old: u8, value: u8) -> bool {
+        if self.addresses.contains(&addr) {
+            self.hits.push(WatchHit { addr, access: Access::Write, value });
+        }
+        true
+    }
+}
+
+// How many dropped writes `WriteProtection` keeps, so a program writing
+// protected memory every frame doesn't fill the heap.
+const MAX_VIOLATIONS: usize = 64;
+
+/// Drops writes to a range of addresses, counting them and keeping the
+/// latest ones.
+pub struct WriteProtection {
+    pub range: Range<usize>,
+    /// How many writes were dropped.
+    pub count: usize,
+    // Addresses and values of the last dropped writes, oldest first.
+    violations: VecDeque<(usize, u8)>,
+}
+
+impl WriteProtection {
+    /// Protects 0x000 to 0x1FF, where the interpreter and the font live.
+    pub fn interpreter() -> WriteProtection {
+        WriteProtection {
+            range: 0..0x200,
+            count: 0,
+            violations: VecDeque::new(),
+        }
+    }
+
+    /// Addresses and values of the writes dropped since the last call, up to
+    /// the latest 64.
+    pub fn take_violations(&mut self) -> Vec<(usize, u8)> {
+        self.violations.drain(..).collect()
+    }
+}
+
+impl MemoryHook for WriteProtection {
+    fn on_write(&mut self, addr: usize, _old: u8, value: u8) -> bool {
+        if self.range.contains(&addr) {
+            self.count += 1;
+            if self.violations.len() == MAX_VIOLATIONS {
+                self.violations.pop_front();
+            }
+            self.violations.push_back((addr, value));
+            return false;
+        }
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_read_write_wraps() {
+        let mut memory = Memory::new(16);
+        memory.write(17, 3);
+        assert_eq!(memory.read(1, Access::Read), 3);
+        memory.reset(32);
+        assert_eq!(memory.size(), 32);
+        assert_eq!(memory.read(1, Access::Read), 0);
+    }
+
+    #[test]
+    fn test_watchpoints() {
+        let mut memory = Memory::new(4096);
+        let mut watchpoints = Watchpoints::default();
+        watchpoints.addresses.insert(0x300);
+        memory.add_hook(watchpoints);
+
+        memory.write(0x300, 7);
+        memory.write(0x301, 7);
+        memory.read(0x300, Access::Fetch);
+        memory.load(0x300, &[1]);
+        assert_eq!(memory.hook::<Watchpoints>().unwrap().hits, vec![
+            WatchHit { addr: 0x300, access: Access::Write, value: 7 },
+            WatchHit { addr: 0x300, access: Access::Fetch, value: 7 },
+        ]);
+
+        memory.remove_hooks::<Watchpoints>();
+        assert!(memory.hook::<Watchpoints>().is_none());
+    }
+
+    #[test]
+    fn test_write_protection() {
+        let mut memory = Memory::new(4096);
+        memory.add_hook(WriteProtection::interpreter());
+        memory.write(0x1FF, 1);
+        memory.write(0x200, 2);
+        assert_eq!(memory.bytes()[0x1FF], 0);
+        assert_eq!(memory.bytes()[0x200], 2);
+        let protection = memory.hook_mut::<WriteProtection>().unwrap();
+        assert_eq!(protection.count, 1);
+        assert_eq!(protection.take_violations(), vec![(0x1FF, 1)]);
+        assert_eq!(protection.take_violations(), vec![]);
+    }
+
+    #[test]
+    fn test_write_protection_keeps_the_latest_violations() {
+        let mut memory = Memory::new(4096);
+        memory.add_hook(WriteProtection::interpreter());
+        for addr in 0..0x100 {
+            memory.write(addr, 1);
+        }
+        let protection = memory.hook_mut::<WriteProtection>().unwrap();
+        assert_eq!(protection.count, 0x100);
+        let violations = protection.take_violations();
+        assert_eq!(violations.len(), MAX_VIOLATIONS);
+        assert_eq!(violations[0], (0x100 - MAX_VIOLATIONS, 1));
+        assert_eq!(violations[MAX_VIOLATIONS - 1], (0xFF, 1));
+    }
+}
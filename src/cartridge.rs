@@ -0,0 +1,241 @@
+//! Loader for Octo "cartridges": GIF images carrying a program and its
+//! settings in the low bits of the pixels.
+//!
+//! The pixels of every frame, taken in order, hold two bits each in the low
+//! bits of their colour index, most significant pair first, so four pixels
+//! make a byte. The bytes start with a big-endian 32-bit length followed by
+//! that many bytes of UTF-8 JSON: `{"options": {...}, "program": "..."}`.
+//!
+//! The program is Octo source. There's no Octo assembler here, so only
+//! programs made of byte literals, as Octo imports ROMs, come with a ROM.
+
+use crate::database::parse_color;
+use crate::platform::{Platform, Quirks};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+#[derive(Debug, PartialEq)]
+pub enum CartridgeError {
+    /// The file isn't a GIF image we can decode.
+    Gif(String),
+    /// The image holds fewer bytes than the payload length announces.
+    Truncated,
+    /// The payload isn't valid JSON.
+    Json(String),
+    /// The options ask for a platform we don't emulate.
+    UnsupportedPlatform(String),
+}
+
+impl fmt::Display for CartridgeError {
+    fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            CartridgeError::Gif(err) => write!(formatter, "invalid cartridge image: {}", err),
+            CartridgeError::Truncated => write!(formatter, "cartridge payload is truncated"),
+            CartridgeError::Json(err) => write!(formatter, "invalid cartridge payload: {}", err),
+            CartridgeError::UnsupportedPlatform(platform) => write!(formatter, "{} cartridges aren't supported", platform),
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct Payload {
+    #[serde(default)]
+    options: Options,
+    program: String,
+}
+
+/// Octo's emulator options. Unknown ones are ignored.
+#[derive(Clone, Debug, Default, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Options {
+    pub tickrate: Option<usize>,
+    pub fill_color: Option<String>,
+    pub background_color: Option<String>,
+    pub buzz_color: Option<String>,
+    pub quiet_color: Option<String>,
+    pub shift_quirks: Option<bool>,
+    pub load_store_quirks: Option<bool>,
+    pub clip_quirks: Option<bool>,
+    pub v_blank_quirks: Option<bool>,
+    pub jump_quirks: Option<bool>,
+    pub logic_quirks: Option<bool>,
+    pub max_size: Option<usize>,
+}
+
+pub struct Cartridge {
+    pub options: Options,
+    pub platform: Platform,
+    pub source: String,
+    /// The program as bytes, when the source is only byte literals and
+    /// labels. Anything else needs the Octo assembler.
+    pub rom: Option<Vec<u8>>,
+}
+
+impl Options {
+    /// The platform Octo targets for the program's size limit: SUPER-CHIP for
+    /// programs allowed past the CHIP-8 memory size, XO-CHIP past SUPER-CHIP's.
+    pub fn platform(&self) -> Result<Platform, CartridgeError> {
+        match self.max_size {
+            Some(size) if size > 3583 => Err(CartridgeError::UnsupportedPlatform("XO-CHIP".to_string())),
+            Some(size) if size > 3216 => Ok(Platform::SuperChip),
+            _ => Ok(Platform::Chip8),
+        }
+    }
+
+    pub fn quirks(&self, mut quirks: Quirks) -> Quirks {
+        let fields = [
+            (self.shift_quirks, &mut quirks.shift),
+            (self.load_store_quirks, &mut quirks.memory_leave_i_unchanged),
+            (self.v_blank_quirks, &mut quirks.vblank),
+            (self.jump_quirks, &mut quirks.jump),
+            (self.logic_quirks, &mut quirks.logic),
+        ];
+        for (value, quirk) in fields {
+            if let Some(value) = value {
+                *quirk = value;
+            }
+        }
+        if let Some(clip) = self.clip_quirks {
+            quirks.wrap_sprites = !clip;
+        }
+        quirks
+    }
+
+    /// Background and fill colours as `0xRRGGBBAA`.
+    pub fn palette(&self) -> Option<(u32, u32)> {
+        let off = parse_color(self.background_color.as_ref()?)?;
+        let on = parse_color(self.fill_color.as_ref()?)?;
+        Some((off, on))
+    }
+}
+
+pub fn load(gif: &[u8]) -> Result<Cartridge, CartridgeError> {
+    let bytes = decode_bytes(gif)?;
+    if bytes.len() < 4 {
+        return Err(CartridgeError::Truncated);
+    }
+    let size = u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]) as usize;
+    // The length comes from the file, so it can be anything.
+    let end = size.checked_add(4).ok_or(CartridgeError::Truncated)?;
+    let json = bytes.get(4..end).ok_or(CartridgeError::Truncated)?;
+    let payload: Payload = serde_json::from_slice(json).map_err(|err| CartridgeError::Json(err.to_string()))?;
+    let platform = payload.options.platform()?;
+    let rom = assemble_data(&payload.program);
+    Ok(Cartridge {
+        options: payload.options,
+        platform,
+        source: payload.program,
+        rom,
+    })
+}
+
+fn decode_bytes(gif: &[u8]) -> Result<Vec<u8>, CartridgeError> {
+    let mut decoder = gif::DecodeOptions::new()
+        .read_info(gif)
+        .map_err(|err| CartridgeError::Gif(err.to_string()))?;
+    let mut bytes = Vec::new();
+    let mut byte = 0u8;
+    let mut pairs = 0;
+    while let Some(frame) = decoder.read_next_frame().map_err(|err| CartridgeError::Gif(err.to_string()))? {
+        for &pixel in frame.buffer.iter() {
+            byte = (byte << 2) | (pixel & 0x3);
+            pairs += 1;
+            if pairs == 4 {
+                bytes.push(byte);
+                pairs = 0;
+            }
+        }
+    }
+    Ok(bytes)
+}
+
+// Octo stores source text rather than binaries. ROMs imported into Octo
+// become plain lists of byte literals, which we can load without an assembler.
+fn assemble_data(source: &str) -> Option<Vec<u8>> {
+    let mut rom = Vec::new();
+    let mut tokens = source
+        .lines()
+        .map(|line| line.split('#').next().unwrap_or(""))
+        .flat_map(str::split_whitespace);
+    while let Some(token) = tokens.next() {
+        if token == ":" {
+            tokens.next();
+            continue;
+        }
+        let value = if let Some(hex) = token.strip_prefix("0x") {
+            u8::from_str_radix(hex, 16)
+        } else if let Some(binary) = token.strip_prefix("0b") {
+            u8::from_str_radix(binary, 2)
+        } else {
+            token.parse()
+        };
+        rom.push(value.ok()?);
+    }
+    Some(rom)
+}
+
+// A cartridge image holding `payload`, for tests.
+#[cfg(test)]
+pub(crate) fn encode(payload: &str) -> Vec<u8> {
+    let mut bytes = (payload.len() as u32).to_be_bytes().to_vec();
+    bytes.extend_from_slice(payload.as_bytes());
+    let mut pixels: Vec<u8> = bytes
+        .iter()
+        .flat_map(|&byte| vec![byte >> 6, (byte >> 4) & 3, (byte >> 2) & 3, byte & 3])
+        // label colours live in the upper bits
+        .map(|pair| pair | 0x4)
+        .collect();
+    let width = 32;
+    pixels.resize(pixels.len().div_ceil(width) * width, 0);
+
+    let mut gif = Vec::new();
+    {
+        let palette: Vec<u8> = (0..8).flat_map(|idx| vec![idx * 30; 3]).collect();
+        let mut encoder = gif::Encoder::new(&mut gif, width as u16, (pixels.len() / width) as u16, &palette).unwrap();
+        let frame = gif::Frame {
+            width: width as u16,
+            height: (pixels.len() / width) as u16,
+            buffer: pixels.into(),
+            ..Default::default()
+        };
+        encoder.write_frame(&frame).unwrap();
+    }
+    gif
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_load() {
+        let gif = encode(r##"{"options":{"tickrate":20,"fillColor":"#FF8000","backgroundColor":"#000000","shiftQuirks":true,"clipQuirks":false,"maxSize":3583},"program":": main\n  0x00 0xE0 # clear\n  0b00010010 2\n"}"##);
+        let cartridge = load(&gif).unwrap();
+        assert_eq!(cartridge.rom, Some(vec![0x00, 0xE0, 0x12, 0x02]));
+        assert_eq!(cartridge.options.tickrate, Some(20));
+        assert_eq!(cartridge.options.palette(), Some((0x000000FF, 0xFF8000FF)));
+        assert_eq!(cartridge.platform, Platform::SuperChip);
+
+        let quirks = cartridge.options.quirks(Quirks { shift: false, ..Quirks::default() });
+        assert!(quirks.shift);
+        assert!(quirks.wrap_sprites);
+    }
+
+    #[test]
+    fn test_load_source() {
+        let cartridge = load(&encode(r#"{"program":": main\n  loop again"}"#)).unwrap();
+        assert_eq!(cartridge.source, ": main\n  loop again");
+        assert_eq!(cartridge.rom, None);
+        assert_eq!(cartridge.platform, Platform::Chip8);
+    }
+
+    #[test]
+    fn test_load_errors() {
+        assert!(matches!(load(b"not a gif").err(), Some(CartridgeError::Gif(_))));
+        assert_eq!(
+            load(&encode(r#"{"options":{"maxSize":65024},"program":"0x00"}"#)).err(),
+            Some(CartridgeError::UnsupportedPlatform("XO-CHIP".to_string()))
+        );
+        assert!(matches!(load(&encode("{")).err(), Some(CartridgeError::Json(_))));
+    }
+}
@@ -1,4 +1,24 @@
+use crate::analysis::{self, Cfg};
+use crate::bus::{Bus, Watchpoints, WriteProtection};
+use crate::cartridge::{self, Cartridge};
 use crate::cpu::CPU;
+use crate::database::{self, RomInfo};
+use crate::differential::{Config, Differential};
+use crate::engine::Engine;
+use crate::framebuffer::FrameBuffer;
+use crate::gamepad::{Gamepad, GamepadProfile};
+use crate::gym::{Env, Game};
+use crate::keymap::{Keymap, Layout};
+#[cfg(feature = "megachip")]
+use crate::megachip;
+use crate::persistence::{Persistence, PersistenceMode};
+use crate::platform::{Platform, Quirks};
+use crate::profiler::AccessCounts;
+use crate::recompiler;
+use crate::rom::{self, RomError};
+use crate::symbols::Symbols;
+use crate::screen::Rect;
+use crate::state::State;
 use crate::utils::set_panic_hook;
 use wasm_bindgen::prelude::*;
 
@@ -6,26 +26,279 @@ use wasm_bindgen::prelude::*;
 #[global_allocator]
 static ALLOC: wee_alloc::WeeAlloc = wee_alloc::WeeAlloc::INIT;
 
+pub(crate) const DEFAULT_TICK_RATE: usize = 8;
+
 #[wasm_bindgen]
 pub struct Chip8 {
     cpu: CPU,
+    framebuffer: FrameBuffer,
+    persistence: Persistence,
+    keymap: Keymap,
+    gamepad: Gamepad,
+    // Keys held down through `key_down`, which the gamepad mustn't release.
+    keys_held: [bool; 16],
+    platform: Platform,
+    tick_rate: usize,
+    rom_info: Option<RomInfo>,
+    program_size: usize,
+    symbols: Option<Symbols>,
+}
+
+impl Default for Chip8 {
+    fn default() -> Chip8 {
+        Chip8::new()
+    }
 }
 
 #[wasm_bindgen]
 impl Chip8 {
     pub fn new() -> Chip8 {
+        Chip8::with_platform(Platform::Chip8)
+    }
+
+    /// Creates a machine for `platform`, which decides the load address, the
+    /// initial PC and the screen size of ROMs the database doesn't know.
+    pub fn with_platform(platform: Platform) -> Chip8 {
         set_panic_hook();
         Chip8 {
-            cpu : CPU::new()
+            cpu : CPU::with_platform(platform),
+            framebuffer: FrameBuffer::new(),
+            persistence: Persistence::new(),
+            keymap: Keymap::new(Layout::default()),
+            gamepad: Gamepad::new(),
+            keys_held: [false; 16],
+            platform,
+            tick_rate: DEFAULT_TICK_RATE,
+            rom_info: None,
+            program_size: 0,
+            symbols: None,
         }
     }
 
+    /// Platform of the loaded ROM, which the ROM database may have changed
+    /// from the one `Chip8` was created with.
+    pub fn platform(&self) -> Platform {
+        self.cpu.platform()
+    }
+
+    /// Overrides whether sprites drawn past the screen edge wrap around
+    /// instead of being clipped.
+    pub fn set_sprite_wrapping(&mut self, wrap: bool) {
+        let quirks = self.cpu.quirks();
+        self.cpu.set_quirks(Quirks { wrap_sprites: wrap, ..quirks });
+    }
+
     pub fn reset(&mut self) {
         self.cpu.reset();
+        self.keys_held = [false; 16];
+        self.gamepad.clear();
+    }
+
+    /// Loads a ROM, configuring platform, quirks, tick rate, keys and colours
+    /// from the ROM database when the ROM is known.
+    pub fn load_program(&mut self, program: &[u8]) -> Result<(), JsValue> {
+        self.load(program, self.platform).map_err(|err| JsValue::from_str(&err.to_string()))
+    }
+
+    /// Resets the machine and loads a ROM file: a raw `.ch8`, `.sc8` or `.xo8`
+    /// program, a zip archive containing one, or an Octo cartridge GIF whose
+    /// program is made of byte literals.
+    pub fn load_rom(&mut self, name: &str, data: &[u8]) -> Result<(), JsValue> {
+        if name.to_lowercase().ends_with(".gif") {
+            let cartridge = cartridge::load(data).map_err(|err| JsValue::from_str(&err.to_string()))?;
+            if !self.load_cartridge(&cartridge)? {
+                return Err(JsValue::from_str("octo source needs to be assembled"));
+            }
+            return Ok(());
+        }
+        self.open_rom(name, data).map_err(|err| JsValue::from_str(&err.to_string()))
+    }
+
+    /// Reads an Octo cartridge GIF and returns its source and options as JSON:
+    /// `{"source": "...", "options": {...}, "loaded": true}`.
+    ///
+    /// Cartridges hold Octo source, not a ROM, and there's no Octo assembler
+    /// here. When the program is only byte literals, as Octo imports ROMs, the
+    /// machine is reset, loaded and configured with the cartridge's tick rate,
+    /// colours and quirks, ready to run, and `loaded` is true. Otherwise the
+    /// machine is left alone and `loaded` is false, for the caller to assemble
+    /// the source and load it with `load_program`.
+    pub fn load_octo_cartridge(&mut self, gif: &[u8]) -> Result<String, JsValue> {
+        let cartridge = cartridge::load(gif).map_err(|err| JsValue::from_str(&err.to_string()))?;
+        let loaded = self.load_cartridge(&cartridge)?;
+        let json = serde_json::json!({
+            "source": cartridge.source,
+            "options": cartridge.options,
+            "loaded": loaded,
+        });
+        Ok(json.to_string())
+    }
+
+    // Loads the cartridge's ROM, applying its tick rate, colours and quirks on
+    // top of what `load_program` configures. False when it has no ROM.
+    fn load_cartridge(&mut self, cartridge: &Cartridge) -> Result<bool, JsValue> {
+        let rom = match &cartridge.rom {
+            Some(rom) => rom,
+            None => return Ok(false),
+        };
+        let options = &cartridge.options;
+        self.reset();
+        self.load_program(rom)?;
+        let platform = cartridge.platform;
+        let quirks = if platform == self.cpu.platform() { self.cpu.quirks() } else { platform.quirks() };
+        self.cpu.set_platform(platform, options.quirks(quirks));
+        if let Some(tick_rate) = options.tickrate {
+            self.tick_rate = tick_rate;
+        }
+        if let Some((off, on)) = options.palette() {
+            self.framebuffer.set_palette(off, on);
+        }
+        Ok(true)
+    }
+
+    /// The Octo source embedded in a cartridge GIF.
+    pub fn octo_cartridge_source(gif: &[u8]) -> Result<String, JsValue> {
+        let cartridge = cartridge::load(gif).map_err(|err| JsValue::from_str(&err.to_string()))?;
+        Ok(cartridge.source)
+    }
+
+    /// Records every access to `addr` until the watchpoints are cleared.
+    pub fn add_watchpoint(&mut self, addr: usize) {
+        if self.cpu.bus().hook::<Watchpoints>().is_none() {
+            self.cpu.add_hook(Watchpoints::default());
+        }
+        self.cpu.hook_mut::<Watchpoints>().unwrap().addresses.insert(addr);
+    }
+
+    pub fn clear_watchpoints(&mut self) {
+        self.cpu.remove_hooks::<Watchpoints>();
+    }
+
+    /// Accesses to watched addresses since the last call, as JSON
+    /// `[{"addr", "access", "value"}, ...]`.
+    pub fn take_watch_hits_json(&mut self) -> String {
+        let hits = match self.cpu.hook_mut::<Watchpoints>() {
+            Some(watchpoints) => std::mem::take(&mut watchpoints.hits),
+            None => Vec::new(),
+        };
+        serde_json::to_string(&hits).unwrap()
+    }
+
+    /// Drops writes to 0x000-0x1FF, where the font lives, instead of letting
+    /// buggy programs overwrite it.
+    pub fn set_interpreter_protection(&mut self, enabled: bool) {
+        self.cpu.remove_hooks::<WriteProtection>();
+        if enabled {
+            self.cpu.add_hook(WriteProtection::interpreter());
+        }
+    }
+
+    /// Number of writes dropped by the interpreter protection.
+    pub fn protection_violations(&self) -> usize {
+        self.cpu.bus().hook::<WriteProtection>().map_or(0, |protection| protection.count)
+    }
+
+    /// The latest writes dropped by the interpreter protection since the last
+    /// call, as JSON `[[addr, value], ...]`.
+    pub fn take_protection_violations_json(&mut self) -> String {
+        let violations = match self.cpu.hook_mut::<WriteProtection>() {
+            Some(protection) => protection.take_violations(),
+            None => Vec::new(),
+        };
+        serde_json::to_string(&violations).unwrap()
+    }
+
+    /// Starts profiling from scratch, or stops: counting memory accesses per
+    /// address and cycles per instruction and subroutine.
+    pub fn set_profiling(&mut self, enabled: bool) {
+        self.cpu.set_profiling(enabled);
+        self.cpu.remove_hooks::<AccessCounts>();
+        if enabled {
+            let size = self.cpu.bus().size();
+            self.cpu.add_hook(AccessCounts::new(size));
+        }
+    }
+
+    /// Instruction fetches per address, for a heatmap of the memory. Empty
+    /// when not profiling.
+    pub fn profile_fetches(&self) -> Vec<u32> {
+        self.access_counts(|counts| &counts.fetches)
+    }
+
+    /// Data reads per address: sprites, FX65 and the like.
+    pub fn profile_reads(&self) -> Vec<u32> {
+        self.access_counts(|counts| &counts.reads)
+    }
+
+    /// Writes per address by FX33 and FX55.
+    pub fn profile_writes(&self) -> Vec<u32> {
+        self.access_counts(|counts| &counts.writes)
+    }
+
+    /// Count and cycles of each kind of instruction as JSON,
+    /// `{"8XY4": {"count", "cycles"}, ...}`.
+    pub fn profile_opcodes_json(&self) -> Option<String> {
+        self.cpu.profiler().map(|profiler| serde_json::to_string(profiler.opcodes()).unwrap())
+    }
+
+    /// Subroutines by decreasing inclusive time as JSON,
+    /// `[{"addr", "calls", "inclusive_cycles"}, ...]`.
+    pub fn profile_subroutines_json(&self) -> Option<String> {
+        self.cpu.profiler().map(|profiler| serde_json::to_string(&profiler.subroutines()).unwrap())
     }
 
-    pub fn load_program(&mut self, program: &[u8]) {
-        self.cpu.load_program(program);
+    /// The `top` hottest addresses, instructions and subroutines as text.
+    pub fn profile_report(&self, top: usize) -> Option<String> {
+        let counts = self.cpu.bus().hook::<AccessCounts>();
+        self.cpu.profiler().map(|profiler| profiler.report(counts, top))
+    }
+
+    /// Starts recording which instructions run and which way skips go, from
+    /// scratch, or stops.
+    pub fn set_coverage(&mut self, enabled: bool) {
+        self.cpu.set_coverage(enabled);
+    }
+
+    /// Labels and source lines of the loaded ROM for the coverage reports, in
+    /// the format described in `symbols.rs`. Loading a ROM drops them.
+    pub fn load_symbols(&mut self, text: &str) -> Result<(), JsValue> {
+        self.set_symbols(Symbols::parse(text).map_err(|err| JsValue::from_str(&err))?);
+        Ok(())
+    }
+
+    /// Disassembly of the loaded ROM with how often each instruction ran.
+    pub fn coverage_listing(&self) -> Option<String> {
+        let start = self.cpu.platform().load_address() as usize;
+        let memory = self.cpu.bus().bytes();
+        self.cpu.coverage().map(|coverage| {
+            coverage.listing(memory, start..start + self.program_size, self.cpu.platform(), self.symbols.as_ref())
+        })
+    }
+
+    /// The coverage as an lcov tracefile of the source lines, which needs symbols.
+    pub fn coverage_lcov(&self) -> Option<String> {
+        let memory = self.cpu.bus().bytes();
+        let symbols = self.symbols.as_ref()?;
+        self.cpu.coverage().map(|coverage| coverage.lcov(memory, self.cpu.platform(), symbols))
+    }
+
+    /// Control-flow graph of the loaded ROM in Graphviz DOT format.
+    pub fn cfg_dot(&self) -> String {
+        self.analyze().to_dot()
+    }
+
+    /// Database entry of the loaded ROM as JSON, if it is known.
+    pub fn rom_info(&self) -> Option<String> {
+        self.rom_info.as_ref().map(RomInfo::to_json)
+    }
+
+    /// Instructions per frame, as recommended for the loaded ROM.
+    pub fn tick_rate(&self) -> usize {
+        self.tick_rate
+    }
+
+    pub fn set_tick_rate(&mut self, tick_rate: usize) {
+        self.tick_rate = tick_rate;
     }
 
     pub fn execute_next(&mut self) {
@@ -36,6 +309,54 @@ impl Chip8 {
         self.cpu.update_timer();
     }
 
+    /// Runs `cycles` instructions and ticks the timers once. Returns early
+    /// while the program is waiting for a key.
+    pub fn run_frame(&mut self, cycles: usize) {
+        self.cpu.run_frame(cycles);
+    }
+
+    pub fn engine(&self) -> Engine {
+        self.cpu.engine()
+    }
+
+    /// Switches between the reference interpreter and caching decoded blocks,
+    /// which is faster but bypasses profiling and coverage.
+    pub fn set_engine(&mut self, engine: Engine) {
+        self.cpu.set_engine(engine);
+    }
+
+    /// Makes random numbers repeat from `seed`, so that runs can be compared.
+    pub fn seed_random(&mut self, seed: u64) {
+        self.cpu.seed_random(seed);
+    }
+
+    /// The machine's state as JSON, except in MEGA-CHIP's 256x192 mode.
+    pub fn save_state(&self) -> Option<String> {
+        self.cpu.save_state().map(|state| state.to_json())
+    }
+
+    /// Restores a state from `save_state`, which must be for the platform of
+    /// the loaded ROM.
+    pub fn load_state(&mut self, json: &str) -> Result<(), JsValue> {
+        let state = State::from_json(json).map_err(|err| JsValue::from_str(&err))?;
+        if state.platform != self.cpu.platform() {
+            let message = format!("the state is for {:?}, not {:?}", state.platform, self.cpu.platform());
+            return Err(JsValue::from_str(&message));
+        }
+        self.cpu.load_state(&state).map_err(|err| JsValue::from_str(&err))
+    }
+
+    pub fn is_waiting_for_key(&self) -> bool {
+        self.cpu.is_waiting_for_key()
+    }
+
+    /// Overrides whether FX0A waits for the key to be released (COSMAC VIP)
+    /// or completes as soon as it is pressed.
+    pub fn set_key_wait_release(&mut self, release: bool) {
+        let quirks = self.cpu.quirks();
+        self.cpu.set_quirks(Quirks { key_wait_release: release, ..quirks });
+    }
+
     pub fn width(&mut self) -> usize {
         self.cpu.get_screen().width()
     }
@@ -48,11 +369,353 @@ impl Chip8 {
         self.cpu.get_screen().get_screen_memory()
     }
 
+    /// The screen as text, one line per row with `#` for set pixels.
+    pub fn screen_text(&mut self) -> String {
+        self.cpu.get_screen().to_string()
+    }
+
+    /// Whether anything was drawn since the dirty rectangles were last taken.
+    /// Renderers can skip the frame entirely when this is false.
+    pub fn is_dirty(&mut self) -> bool {
+        self.cpu.get_screen().is_dirty()
+    }
+
+    /// Bounding box of the changes since the last `take_dirty_rects`, as
+    /// `[x, y, width, height]` in screen pixels, or empty when nothing changed.
+    pub fn dirty_frame_rect(&mut self) -> Vec<u32> {
+        flatten_rects(self.cpu.get_screen().frame_rect().iter())
+    }
+
+    /// Drains the rectangles touched by each draw call, flattened as
+    /// `[x, y, width, height, ...]` in screen pixels.
+    pub fn take_dirty_rects(&mut self) -> Vec<u32> {
+        flatten_rects(self.cpu.get_screen().take_dirty_rects().iter())
+    }
+
+    /// Re-renders the RGBA framebuffer if the screen changed since the last call.
+    /// Returns whether the framebuffer needs to be blitted again.
+    /// With a persistence mode enabled this advances the effect by one frame,
+    /// so it keeps returning true while pixels are still fading.
+    pub fn update_framebuffer(&mut self) -> bool {
+        #[cfg(feature = "megachip")]
+        {
+            let megachip = self.cpu.megachip();
+            if megachip.is_enabled() {
+                let (pixels, generation) = (megachip.pixels(), megachip.generation());
+                return self.framebuffer.update_rgba(pixels, megachip::WIDTH, megachip::HEIGHT, generation);
+            }
+        }
+        if self.cpu.platform() == Platform::Chip8X {
+            let (screen, colours) = self.cpu.screen_and_colours();
+            return self.framebuffer.update_colours(screen, colours);
+        }
+        let screen = self.cpu.get_screen();
+        if self.persistence.mode() == PersistenceMode::Off {
+            return self.framebuffer.update(screen);
+        }
+        if !self.persistence.update(screen) {
+            return false;
+        }
+        self.framebuffer.update_levels(self.persistence.intensity(), screen.width(), screen.height());
+        true
+    }
+
+    pub fn persistence_mode(&self) -> PersistenceMode {
+        self.persistence.mode()
+    }
+
+    pub fn set_persistence_mode(&mut self, mode: PersistenceMode) {
+        self.persistence.set_mode(mode);
+    }
+
+    /// Fraction of its brightness an unlit pixel keeps every frame in `Decay` mode.
+    pub fn set_persistence_decay(&mut self, decay: f32) {
+        self.persistence.set_decay(decay);
+    }
+
+    /// Number of frames OR-ed together in `Blend` mode.
+    pub fn set_persistence_frames(&mut self, frames: usize) {
+        self.persistence.set_blend_frames(frames);
+    }
+
+    /// Grayscale output of the persistence layer, one byte per screen pixel.
+    /// Only updated by `update_framebuffer` while a persistence mode is enabled.
+    pub fn intensity_ptr(&self) -> *const u8 {
+        self.persistence.intensity().as_ptr()
+    }
+
+    pub fn intensity_len(&self) -> usize {
+        self.persistence.intensity().len()
+    }
+
+    pub fn framebuffer_ptr(&self) -> *const u8 {
+        self.framebuffer.as_ptr()
+    }
+
+    pub fn framebuffer_len(&self) -> usize {
+        self.framebuffer.len()
+    }
+
+    pub fn framebuffer_width(&mut self) -> usize {
+        self.width() * self.framebuffer.scale()
+    }
+
+    pub fn framebuffer_height(&mut self) -> usize {
+        self.height() * self.framebuffer.scale()
+    }
+
+    pub fn set_scale(&mut self, scale: usize) {
+        self.framebuffer.set_scale(scale);
+    }
+
+    /// Colours are `0xRRGGBBAA` for unset and set pixels respectively.
+    pub fn set_palette(&mut self, off: u32, on: u32) {
+        self.framebuffer.set_palette(off, on);
+    }
+
+    /// The colours for unset and set pixels, from the ROM or `set_palette`.
+    pub fn palette(&self) -> Vec<u32> {
+        let (off, on) = self.framebuffer.palette();
+        vec![off, on]
+    }
+
     pub fn key_down(&mut self, key: u8) {
-        self.cpu.get_keyboard().key_down(key);
+        if key <= 0xF {
+            self.keys_held[key as usize] = true;
+            self.cpu.get_keyboard().key_down(key);
+        }
     }
 
+    /// Releases a key, unless the gamepad is holding it too.
     pub fn key_up(&mut self, key: u8) {
-        self.cpu.get_keyboard().key_up(key);
+        if key <= 0xF {
+            self.keys_held[key as usize] = false;
+            if !self.gamepad.is_pressed(key) {
+                self.cpu.get_keyboard().key_up(key);
+            }
+        }
+    }
+
+    /// Presses a key on the second keypad of the CHIP-8X.
+    pub fn key2_down(&mut self, key: u8) {
+        if key <= 0xF {
+            self.cpu.get_keyboard2().key_down(key);
+        }
+    }
+
+    pub fn key2_up(&mut self, key: u8) {
+        if key <= 0xF {
+            self.cpu.get_keyboard2().key_up(key);
+        }
+    }
+
+    /// The MEGA-CHIP sample being played as JSON `{start, length, rate, looping}`,
+    /// where `start` is an offset from `memory_ptr`.
+    #[cfg(feature = "megachip")]
+    pub fn sample_json(&self) -> Option<String> {
+        self.cpu.megachip().sample().map(|sample| serde_json::to_string(&sample).unwrap())
+    }
+
+    #[cfg(feature = "megachip")]
+    pub fn memory_ptr(&self) -> *const u8 {
+        self.cpu.memory().as_ptr()
+    }
+
+    /// Byte read from the I/O port by the CHIP-8X FXFB instruction.
+    pub fn set_port_input(&mut self, value: u8) {
+        self.cpu.set_port_input(value);
+    }
+
+    /// Byte last written to the I/O port by FXF8, if any since the previous call.
+    pub fn take_port_output(&mut self) -> Option<u8> {
+        self.cpu.take_port_output()
+    }
+
+    /// Presses the CHIP-8 key mapped to a host key (`KeyboardEvent.code`).
+    /// Returns whether the host key is mapped.
+    pub fn host_key_down(&mut self, code: &str) -> bool {
+        match self.keymap.lookup(code) {
+            Some(key) => {
+                self.key_down(key);
+                true
+            }
+            None => false,
+        }
+    }
+
+    pub fn host_key_up(&mut self, code: &str) -> bool {
+        match self.keymap.lookup(code) {
+            Some(key) => {
+                self.key_up(key);
+                true
+            }
+            None => false,
+        }
+    }
+
+    pub fn keyboard_layout(&self) -> Layout {
+        self.keymap.layout()
+    }
+
+    pub fn set_keyboard_layout(&mut self, layout: Layout) {
+        self.keymap.set_layout(layout);
+    }
+
+    /// Binds a host key to a CHIP-8 key on top of the layout, e.g. for a single ROM.
+    pub fn bind_key(&mut self, code: &str, key: u8) -> bool {
+        self.keymap.bind(code, key)
+    }
+
+    pub fn clear_key_bindings(&mut self) {
+        self.keymap.clear_overrides();
+    }
+
+    pub fn keymap_json(&self) -> String {
+        self.keymap.to_json()
+    }
+
+    pub fn load_keymap_json(&mut self, json: &str) -> Result<(), JsValue> {
+        self.keymap = Keymap::from_json(json).map_err(|err| JsValue::from_str(&err))?;
+        Ok(())
+    }
+
+    /// Applies a Gamepad API snapshot: the `value` of every button and the
+    /// position of every axis. Meant to be called once per frame.
+    pub fn update_gamepad(&mut self, buttons: &[f32], axes: &[f32]) {
+        self.gamepad.update(buttons, axes, &self.keys_held, self.cpu.get_keyboard());
+    }
+
+    /// Switches to the gamepad profile of a bundled game, or the standard one.
+    /// Returns whether a game specific profile was found.
+    pub fn set_gamepad_profile(&mut self, game: &str) -> bool {
+        let profile = GamepadProfile::for_game(game);
+        let found = profile.is_some();
+        self.gamepad.set_profile(profile.unwrap_or_else(GamepadProfile::standard));
+        found
+    }
+
+    pub fn set_gamepad_dead_zone(&mut self, dead_zone: f32) {
+        self.gamepad.set_dead_zone(dead_zone);
+    }
+
+    pub fn gamepad_profile_json(&self) -> String {
+        self.gamepad.profile().to_json()
+    }
+
+    pub fn load_gamepad_profile_json(&mut self, json: &str) -> Result<(), JsValue> {
+        let profile = GamepadProfile::from_json(json).map_err(|err| JsValue::from_str(&err))?;
+        self.gamepad.set_profile(profile);
+        Ok(())
+    }
+}
+
+impl Chip8 {
+    // One of the access counters, as large as the memory.
+    fn access_counts(&self, select: fn(&AccessCounts) -> &Vec<u32>) -> Vec<u32> {
+        let memory = self.cpu.bus();
+        match memory.hook::<AccessCounts>() {
+            Some(counts) => {
+                let mut counts = select(counts).clone();
+                counts.resize(memory.size(), 0);
+                counts
+            }
+            None => Vec::new(),
+        }
+    }
+
+    /// `load_symbols` for Rust callers.
+    pub fn set_symbols(&mut self, symbols: Symbols) {
+        self.symbols = Some(symbols);
+    }
+
+    /// The loaded ROM, as it is in memory now.
+    pub fn program(&self) -> &[u8] {
+        let start = self.cpu.platform().load_address() as usize;
+        self.cpu.bus().bytes().get(start..start + self.program_size).unwrap_or(&[])
+    }
+
+    /// Static analysis of the loaded ROM, as it is in memory now.
+    pub fn analyze(&self) -> Cfg {
+        analysis::analyze(self.program(), self.cpu.platform())
+    }
+
+    /// The loaded ROM as a Rust module, see `recompiler.rs`.
+    pub fn recompile(&self, name: &str) -> String {
+        recompiler::recompile(self.program(), self.cpu.platform(), name)
+    }
+
+    /// A differential test of the loaded ROM at its tick rate, and the config
+    /// it runs with now.
+    pub fn differential(&self) -> (Differential, Config) {
+        let mut differential = Differential::new(self.program(), self.cpu.platform());
+        differential.cycles = self.tick_rate;
+        (differential, Config { engine: self.cpu.engine(), quirks: self.cpu.quirks() })
+    }
+
+    /// A Gym environment for the loaded ROM, as it is in memory now, running
+    /// at its tick rate and with its config, scored by `game`. Fails if no
+    /// ROM is loaded.
+    pub fn env(&self, game: Box<dyn Game>) -> Result<Env, RomError> {
+        let mut env = Env::new(self.program(), self.cpu.platform(), game)?;
+        env.configure(Config { engine: self.cpu.engine(), quirks: self.cpu.quirks() }, self.tick_rate);
+        env.reset(0);
+        Ok(env)
+    }
+
+    /// `load_rom` for Rust callers, with the structured error.
+    pub fn open_rom(&mut self, name: &str, data: &[u8]) -> Result<(), RomError> {
+        let rom = rom::parse(name, data, self.platform)?;
+        self.reset();
+        self.load(&rom.program, rom.platform)
+    }
+
+    // `platform` is used unless the database knows better.
+    pub(crate) fn load(&mut self, program: &[u8], platform: Platform) -> Result<(), RomError> {
+        self.cpu.set_platform(platform, platform.quirks());
+        self.tick_rate = DEFAULT_TICK_RATE;
+        self.keymap.clear_overrides();
+        self.framebuffer.reset_palette();
+
+        self.symbols = None;
+        self.program_size = 0;
+        self.rom_info = database::lookup(program);
+        if let Some(info) = &self.rom_info {
+            self.cpu.set_platform(info.platform, info.quirks);
+            if let Some(tick_rate) = info.tick_rate {
+                self.tick_rate = tick_rate;
+            }
+            for (host_key, key) in info.key_bindings() {
+                self.keymap.bind(host_key, key);
+            }
+            if let Some((off, on)) = info.palette() {
+                self.framebuffer.set_palette(off, on);
+            }
+        }
+        self.cpu.load_program(program)?;
+        self.program_size = program.len();
+        Ok(())
+    }
+}
+
+fn flatten_rects<'a>(rects: impl Iterator<Item = &'a Rect>) -> Vec<u32> {
+    rects
+        .flat_map(|rect| vec![rect.x as u32, rect.y as u32, rect.width as u32, rect.height as u32])
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_load_resets_the_palette() {
+        let mut chip8 = Chip8::new();
+        let gif = cartridge::encode(r##"{"options":{"fillColor":"#FF8000","backgroundColor":"#000000"},"program":"0x12 0x00"}"##);
+        chip8.load_rom("loop.gif", &gif).unwrap();
+        assert_eq!(chip8.palette(), vec![0x000000FF, 0xFF8000FF]);
+
+        let pong = include_bytes!("../resources/games/PONG");
+        chip8.load_rom("PONG", pong).unwrap();
+        assert_eq!(chip8.palette(), Chip8::new().palette());
     }
 }
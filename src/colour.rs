@@ -0,0 +1,150 @@
+//! CHIP-8X colour attributes, as produced by the VP-590 colour board.
+//!
+//! Colour is kept apart from the monochrome `Screen`: every 8 pixel wide,
+//! one row tall zone has a foreground colour, and the whole screen shares a
+//! background colour.
+
+use serde::{Deserialize, Serialize};
+
+const ZONE_WIDTH: usize = 8;
+// BXY0 addresses the screen in blocks of 8x4 pixels.
+const BLOCK_HEIGHT: usize = 4;
+
+/// Foreground colours as `0xRRGGBBAA`, indexed by the 3-bit colour codes.
+pub const PALETTE: [u32; 8] = [
+    0x000000FF, // black
+    0xFF0000FF, // red
+    0x0000FFFF, // blue
+    0xFF00FFFF, // violet
+    0x00FF00FF, // green
+    0xFFFF00FF, // yellow
+    0x00FFFFFF, // aqua
+    0xFFFFFFFF, // white
+];
+
+// 02A0 steps through blue, black, green and red.
+const BACKGROUNDS: [u8; 4] = [2, 0, 4, 1];
+const DEFAULT_FOREGROUND: u8 = 1;
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct ColourAttributes {
+    zones: Vec<u8>,
+    columns: usize,
+    rows: usize,
+    background: usize,
+    generation: u64,
+}
+
+impl ColourAttributes {
+    pub fn new(width: usize, height: usize) -> ColourAttributes {
+        let columns = width.div_ceil(ZONE_WIDTH);
+        ColourAttributes {
+            zones: vec![DEFAULT_FOREGROUND; columns * height],
+            columns,
+            rows: height,
+            background: 0,
+            generation: 0,
+        }
+    }
+
+    /// Whether the zones fit a `width` x `height` screen, for attributes
+    /// loaded from a save state.
+    pub fn is_valid(&self, width: usize, height: usize) -> bool {
+        self.columns == width.div_ceil(ZONE_WIDTH)
+            && self.rows == height
+            && self.zones.len() == self.columns * self.rows
+            && self.background < BACKGROUNDS.len()
+    }
+
+    /// Fits the zones to a new screen size, back in the default foreground.
+    /// The background is kept.
+    pub fn resize(&mut self, width: usize, height: usize) {
+        let (background, generation) = (self.background, self.generation + 1);
+        *self = ColourAttributes { background, generation, ..ColourAttributes::new(width, height) };
+    }
+
+    /// Increases on every change, like `Screen::generation`.
+    pub fn generation(&self) -> u64 {
+        self.generation
+    }
+
+    pub fn background(&self) -> u8 {
+        BACKGROUNDS[self.background]
+    }
+
+    /// Colour code of the pixel at `row`, `col`.
+    pub fn foreground(&self, row: usize, col: usize) -> u8 {
+        self.zones[row * self.columns + col / ZONE_WIDTH]
+    }
+
+    /// 02A0
+    pub fn cycle_background(&mut self) {
+        self.background = (self.background + 1) % BACKGROUNDS.len();
+        self.generation += 1;
+    }
+
+    /// BXY0: `horizontal` and `vertical` hold the first 8x4 block in their low
+    /// nibble and how many more blocks to colour in their high nibble.
+    pub fn fill_blocks(&mut self, horizontal: u8, vertical: u8, colour: u8) {
+        let (left, right) = span(horizontal);
+        let (top, bottom) = span(vertical);
+        self.fill(left..right, top * BLOCK_HEIGHT..bottom * BLOCK_HEIGHT, colour);
+    }
+
+    /// BXYN: colours the 8 pixel wide zone containing `x`, for `height` rows from `y`.
+    pub fn fill_rows(&mut self, x: u8, y: u8, height: u8, colour: u8) {
+        let column = x as usize / ZONE_WIDTH;
+        let top = y as usize;
+        self.fill(column..column + 1, top..top + height as usize, colour);
+    }
+
+    fn fill(&mut self, columns: std::ops::Range<usize>, rows: std::ops::Range<usize>, colour: u8) {
+        for row in rows.start.min(self.rows)..rows.end.min(self.rows) {
+            for column in columns.start.min(self.columns)..columns.end.min(self.columns) {
+                self.zones[row * self.columns + column] = colour & 0x7;
+            }
+        }
+        self.generation += 1;
+    }
+}
+
+fn span(coordinate: u8) -> (usize, usize) {
+    let start = (coordinate & 0xF) as usize;
+    (start, start + (coordinate >> 4) as usize + 1)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_cycle_background() {
+        let mut colours = ColourAttributes::new(64, 32);
+        assert_eq!(colours.background(), 2);
+        colours.cycle_background();
+        assert_eq!(colours.background(), 0);
+        for _ in 0..3 {
+            colours.cycle_background();
+        }
+        assert_eq!(colours.background(), 2);
+    }
+
+    #[test]
+    fn test_fill_blocks() {
+        let mut colours = ColourAttributes::new(64, 32);
+        // Columns 1 and 2, rows 4 to 7.
+        colours.fill_blocks(0x11, 0x01, 4);
+        assert_eq!(colours.foreground(4, 8), 4);
+        assert_eq!(colours.foreground(7, 23), 4);
+        assert_eq!(colours.foreground(3, 8), DEFAULT_FOREGROUND);
+        assert_eq!(colours.foreground(4, 24), DEFAULT_FOREGROUND);
+    }
+
+    #[test]
+    fn test_fill_rows_clips() {
+        let mut colours = ColourAttributes::new(64, 32);
+        colours.fill_rows(60, 30, 5, 0xF);
+        assert_eq!(colours.foreground(31, 56), 7);
+        assert_eq!(colours.foreground(29, 56), DEFAULT_FOREGROUND);
+    }
+}
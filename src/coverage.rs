@@ -0,0 +1,189 @@
+//! Which instructions a program ran, and which way its skips went.
+
+use crate::decoder::{self, Instruction};
+use crate::platform::Platform;
+use crate::symbols::{SourceLine, Symbols};
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::ops::Range;
+
+/// How often a skip instruction skipped and didn't.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Branch {
+    pub taken: u64,
+    pub not_taken: u64,
+}
+
+#[derive(Default)]
+pub struct Coverage {
+    hits: BTreeMap<u16, u64>,
+    branches: BTreeMap<u16, Branch>,
+}
+
+impl Coverage {
+    pub fn new() -> Coverage {
+        Default::default()
+    }
+
+    /// Records an executed instruction at `pc`, after which execution went
+    /// on at `next_pc`.
+    pub fn record(&mut self, pc: u16, instruction: Instruction, next_pc: u16) {
+        *self.hits.entry(pc).or_default() += 1;
+        if instruction.is_skip() {
+            let branch = self.branches.entry(pc).or_default();
+            if next_pc == pc.wrapping_add(4) {
+                branch.taken += 1;
+            } else {
+                branch.not_taken += 1;
+            }
+        }
+    }
+
+    /// How often the instruction at `addr` ran.
+    pub fn hits(&self, addr: u16) -> u64 {
+        self.hits.get(&addr).copied().unwrap_or(0)
+    }
+
+    pub fn branch(&self, addr: u16) -> Option<Branch> {
+        self.branches.get(&addr).copied()
+    }
+
+    /// Addresses of the executed instructions, in order.
+    pub fn executed(&self) -> impl Iterator<Item = u16> + '_ {
+        self.hits.keys().copied()
+    }
+
+    /// Disassembles `range` of `memory` with the hit count of each instruction
+    /// and the outcome of each skip. Labels and source lines are added when
+    /// there are symbols.
+    pub fn listing(&self, memory: &[u8], range: Range<usize>, platform: Platform, symbols: Option<&Symbols>) -> String {
+        let mut listing = String::new();
+        let mut addr = range.start;
+        while addr < range.end.min(memory.len()) {
+            let pc = addr as u16;
+            if let Some(label) = symbols.and_then(|symbols| symbols.label(pc)) {
+                writeln!(listing, "{}:", label).unwrap();
+            }
+            let hits = match self.hits(pc) {
+                0 => "-".to_string(),
+                hits => hits.to_string(),
+            };
+            // A byte of data before an instruction at an odd address.
+            if self.hits(pc) == 0 && self.hits(pc.wrapping_add(1)) > 0 {
+                writeln!(listing, "{:>8}  {:#06X}  {:02X}    DB {:#04X}", hits, addr, memory[addr], memory[addr]).unwrap();
+                addr += 1;
+                continue;
+            }
+            let instruction = decoder::decode_at(memory, addr, platform);
+            let opcode = memory[addr..(addr + instruction.size()).min(memory.len())]
+                .iter()
+                .fold(String::new(), |hex, byte| hex + &format!("{:02X}", byte));
+            let mut line = format!("{:>8}  {:#06X}  {:<4}  {}", hits, addr, opcode, instruction);
+            if let Some(branch) = self.branch(pc) {
+                write!(line, "  [taken {}, not taken {}]", branch.taken, branch.not_taken).unwrap();
+            }
+            if let Some(source) = symbols.and_then(|symbols| symbols.source_line(pc)) {
+                write!(line, "  ; {}:{}", source.file, source.line).unwrap();
+            }
+            writeln!(listing, "{}", line).unwrap();
+            addr += instruction.size();
+        }
+        listing
+    }
+
+    /// An lcov tracefile for the source lines in `symbols`, with the program
+    /// in `memory`. A line's count is that of its most executed instruction,
+    /// and each skip is a branch.
+    pub fn lcov(&self, memory: &[u8], platform: Platform, symbols: &Symbols) -> String {
+        let mut files: BTreeMap<&str, BTreeMap<u32, Vec<u16>>> = BTreeMap::new();
+        for (addr, SourceLine { file, line }) in symbols.lines() {
+            files.entry(file).or_default().entry(*line).or_default().push(addr);
+        }
+
+        let mut lcov = String::new();
+        for (file, lines) in files {
+            writeln!(lcov, "TN:\nSF:{}", file).unwrap();
+            let (mut branches, mut branches_hit) = (0, 0);
+            for (line, addresses) in lines.iter() {
+                let skips = addresses.iter().filter(|&&addr| decoder::decode_at(memory, addr as usize, platform).is_skip());
+                for &addr in skips {
+                    let (taken, not_taken) = match self.branch(addr) {
+                        Some(branch) => {
+                            branches_hit += (branch.taken > 0) as usize + (branch.not_taken > 0) as usize;
+                            (branch.taken.to_string(), branch.not_taken.to_string())
+                        }
+                        None => ("-".to_string(), "-".to_string()),
+                    };
+                    writeln!(lcov, "BRDA:{},{},0,{}\nBRDA:{},{},1,{}", line, addr, taken, line, addr, not_taken).unwrap();
+                    branches += 2;
+                }
+            }
+            for (line, addresses) in lines.iter() {
+                let hits = addresses.iter().map(|&addr| self.hits(addr)).max().unwrap_or(0);
+                writeln!(lcov, "DA:{},{}", line, hits).unwrap();
+            }
+            let lines_hit = lines.values().filter(|addresses| addresses.iter().any(|&addr| self.hits(addr) > 0)).count();
+            writeln!(lcov, "BRF:{}\nBRH:{}", branches, branches_hit).unwrap();
+            writeln!(lcov, "LF:{}\nLH:{}\nend_of_record", lines.len(), lines_hit).unwrap();
+        }
+        lcov
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::decoder::decode;
+
+    // 0x200: V0 = 1; skip if V0 == 1; jump 0x200; jump 0x206
+    const PROGRAM: [u8; 8] = [0x60, 0x01, 0x30, 0x01, 0x12, 0x00, 0x12, 0x06];
+
+    fn run() -> (Coverage, Vec<u8>) {
+        let mut memory = vec![0; 0x200];
+        memory.extend_from_slice(&PROGRAM);
+        let mut coverage = Coverage::new();
+        for (pc, next_pc) in [(0x200, 0x202), (0x202, 0x206), (0x206, 0x206), (0x206, 0x206)] {
+            let opcode = (memory[pc] as u16) << 8 | memory[pc + 1] as u16;
+            coverage.record(pc as u16, decode(opcode, Platform::Chip8), next_pc);
+        }
+        (coverage, memory)
+    }
+
+    #[test]
+    fn test_record() {
+        let (coverage, _) = run();
+        assert_eq!(coverage.executed().collect::<Vec<_>>(), vec![0x200, 0x202, 0x206]);
+        assert_eq!(coverage.hits(0x206), 2);
+        assert_eq!(coverage.hits(0x204), 0);
+        assert_eq!(coverage.branch(0x202), Some(Branch { taken: 1, not_taken: 0 }));
+        assert_eq!(coverage.branch(0x200), None);
+    }
+
+    #[test]
+    fn test_listing() {
+        let (coverage, memory) = run();
+        let symbols = Symbols::parse("0x200 main\n0x206 game.8o:4").unwrap();
+        let listing = coverage.listing(&memory, 0x200..0x208, Platform::Chip8, Some(&symbols));
+        assert_eq!(
+            listing,
+            "main:\n\
+             \x20      1  0x0200  6001  LD V0, 0x01\n\
+             \x20      1  0x0202  3001  SE V0, 0x01  [taken 1, not taken 0]\n\
+             \x20      -  0x0204  1200  JP 0x200\n\
+             \x20      2  0x0206  1206  JP 0x206  ; game.8o:4\n"
+        );
+    }
+
+    #[test]
+    fn test_lcov() {
+        let (coverage, memory) = run();
+        let symbols = Symbols::parse("0x200 game.8o:1\n0x202 game.8o:2\n0x204 game.8o:3\n0x206 game.8o:3").unwrap();
+        assert_eq!(
+            coverage.lcov(&memory, Platform::Chip8, &symbols),
+            "TN:\nSF:game.8o\n\
+             BRDA:2,514,0,1\nBRDA:2,514,1,0\n\
+             DA:1,1\nDA:2,1\nDA:3,2\n\
+             BRF:2\nBRH:1\nLF:3\nLH:3\nend_of_record\n"
+        );
+    }
+}
@@ -1,72 +1,359 @@
+use crate::bus::{Access, Bus, Memory, MemoryHook};
+use crate::colour::ColourAttributes;
+use crate::coverage::Coverage;
+use crate::decoder::{self, Instruction};
+use crate::display::Display;
+use crate::engine::{BlockCache, Engine};
 use crate::screen::Screen;
 use crate::keyboard::{Keyboard, FONT_SET};
-use crate::utils::get_random_buf;
+#[cfg(feature = "megachip")]
+use crate::megachip::{self, MegaChip};
+use crate::platform::{Platform, Quirks, START_ADDR};
+use crate::profiler::Profiler;
+use crate::rom::{self, RomError};
+use crate::state::{self, State};
+use crate::utils::{get_random_buf, Rng};
 
-const START_ADDR: u16 = 0x200;
+// SUPER-CHIP's 00FF mode.
+const HIRES_RESOLUTION: (usize, usize) = (128, 64);
 
-pub struct CPU {
+// State of an FX0A instruction waiting for input.
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct KeyWait {
+    register: usize,
+    key: Option<u8>,
+}
+
+#[allow(clippy::upper_case_acronyms)]
+pub struct CPU<D: Display = Screen, B: Bus = Memory> {
     pc: u16,
     v: [u8; 16],
-    i: u16,
+    // 24 bits wide for MEGA-CHIP.
+    i: u32,
     stack: [u16; 16],
-    memory: [u8; 4096],
+    memory: B,
     sp: u8,
+    // Pushes onto a full stack and pops off an empty one since the reset.
+    stack_overflows: u32,
+    stack_underflows: u32,
     delay_timer: u8,
     sound_timer: u8,
-    screen: Screen,
+    screen: D,
     keyboard: Keyboard,
+    platform: Platform,
+    quirks: Quirks,
+    key_wait: Option<KeyWait>,
+    // CHIP-8X only: the colour board, the second keypad and the I/O port.
+    colours: ColourAttributes,
+    keyboard2: Keyboard,
+    port_input: u8,
+    port_output: Option<u8>,
+    #[cfg(feature = "megachip")]
+    megachip: MegaChip,
+    profiler: Option<Profiler>,
+    coverage: Option<Coverage>,
+    // Replaces the system's random numbers once seeded.
+    rng: Option<Rng>,
+    engine: Engine,
+    blocks: BlockCache,
 }
 
 impl Default for CPU {
     fn default() -> Self {
-        CPU {
+        CPU::new()
+    }
+}
+
+impl CPU {
+    pub fn new() -> CPU {
+        CPU::with_platform(Platform::default())
+    }
+
+    pub fn with_platform(platform: Platform) -> CPU {
+        CPU::with_display(Screen::new(), platform)
+    }
+}
+
+impl<D: Display> CPU<D> {
+    /// A CPU drawing on another display than `Screen`.
+    pub fn with_display(display: D, platform: Platform) -> CPU<D> {
+        CPU::with_bus(display, Memory::default(), platform)
+    }
+
+    /// Hooks never change what is in memory, so unlike `get_memory` these
+    /// keep the block cache.
+    pub fn add_hook<H: MemoryHook>(&mut self, hook: H) {
+        self.memory.add_hook(hook);
+    }
+
+    pub fn hook_mut<H: MemoryHook>(&mut self) -> Option<&mut H> {
+        self.memory.hook_mut()
+    }
+
+    pub fn remove_hooks<H: MemoryHook>(&mut self) {
+        self.memory.remove_hooks::<H>();
+    }
+}
+
+impl<D: Display, B: Bus> CPU<D, B> {
+    /// A CPU with another memory than the default `Memory`.
+    pub fn with_bus(display: D, bus: B, platform: Platform) -> CPU<D, B> {
+        let mut cpu = CPU {
             pc: START_ADDR,
             v: [0; 16],
             i: 0,
             stack: [0; 16],
-            memory: [0; 4096],
+            stack_overflows: 0,
+            stack_underflows: 0,
+            memory: bus,
             sp: 0,
             delay_timer: 0,
             sound_timer: 0,
-            screen: Screen::new(),
+            screen: display,
             keyboard: Keyboard::new(),
-        }
+            platform,
+            quirks: platform.quirks(),
+            key_wait: None,
+            colours: ColourAttributes::new(64, 32),
+            keyboard2: Keyboard::new(),
+            port_input: 0,
+            port_output: None,
+            #[cfg(feature = "megachip")]
+            megachip: MegaChip::new(),
+            profiler: None,
+            coverage: None,
+            rng: None,
+            engine: Engine::Interpreter,
+            blocks: BlockCache::new(),
+        };
+        cpu.reset();
+        cpu
     }
-}
 
-impl CPU {
-    pub fn new() -> CPU {
-        let mut cpu: CPU = Default::default();
-        cpu.load_fonts();
-        cpu
+    pub fn platform(&self) -> Platform {
+        self.platform
+    }
+
+    pub fn quirks(&self) -> Quirks {
+        self.quirks
+    }
+
+    pub fn set_quirks(&mut self, quirks: Quirks) {
+        self.quirks = quirks;
     }
 
-    pub fn get_screen(&mut self) -> &mut Screen {
+    pub fn set_platform(&mut self, platform: Platform, quirks: Quirks) {
+        self.platform = platform;
+        self.quirks = quirks;
+        if self.memory.size() != platform.memory_size() {
+            self.memory.reset(platform.memory_size());
+            self.load_fonts();
+        }
+        self.blocks.clear();
+        if (self.screen.width(), self.screen.height()) != platform.resolution() {
+            let (width, height) = platform.resolution();
+            self.set_resolution(width, height);
+        }
+    }
+
+    pub fn get_screen(&mut self) -> &mut D {
         &mut self.screen
     }
 
+    pub fn screen(&self) -> &D {
+        &self.screen
+    }
+
+    /// The memory, which may then be changed behind the block cache's back, so
+    /// it is emptied.
+    pub fn get_memory(&mut self) -> &mut B {
+        self.blocks.clear();
+        &mut self.memory
+    }
+
+    pub fn bus(&self) -> &B {
+        &self.memory
+    }
+
     pub fn get_keyboard(&mut self) -> &mut Keyboard {
         &mut self.keyboard
     }
 
+    /// The second keypad of the CHIP-8X, read by EXF2/EXF5.
+    pub fn get_keyboard2(&mut self) -> &mut Keyboard {
+        &mut self.keyboard2
+    }
+
+    /// The whole memory, for playing MEGA-CHIP samples from.
+    #[cfg(feature = "megachip")]
+    pub fn memory(&self) -> &[u8] {
+        self.memory.bytes()
+    }
+
+    pub fn screen_and_colours(&self) -> (&D, &ColourAttributes) {
+        (&self.screen, &self.colours)
+    }
+
+    /// Byte read by FXFB.
+    pub fn set_port_input(&mut self, value: u8) {
+        self.port_input = value;
+    }
+
+    #[cfg(feature = "megachip")]
+    pub fn megachip(&self) -> &MegaChip {
+        &self.megachip
+    }
+
+    /// Last byte written by FXF8, if any since the previous call.
+    pub fn take_port_output(&mut self) -> Option<u8> {
+        self.port_output.take()
+    }
+
+    /// Makes CXNN draw from a pseudo-random sequence starting at `seed`, so
+    /// runs can be repeated.
+    pub fn seed_random(&mut self, seed: u64) {
+        self.rng = Some(Rng::new(seed));
+    }
+
+    pub fn pc(&self) -> u16 {
+        self.pc
+    }
+
+    pub fn set_pc(&mut self, pc: u16) {
+        self.pc = pc;
+    }
+
+    /// V0 to VF.
+    pub fn registers(&self) -> &[u8; 16] {
+        &self.v
+    }
+
+    pub fn registers_mut(&mut self) -> &mut [u8; 16] {
+        &mut self.v
+    }
+
+    pub fn i(&self) -> u32 {
+        self.i
+    }
+
+    pub fn set_i(&mut self, i: u32) {
+        self.i = i;
+    }
+
+    /// The return addresses on the stack, oldest first.
+    pub fn stack(&self) -> &[u16] {
+        &self.stack[..self.sp as usize]
+    }
+
+    /// The delay and sound timers.
+    pub fn timers(&self) -> (u8, u8) {
+        (self.delay_timer, self.sound_timer)
+    }
+
+    /// Stack overflows and underflows since the last reset.
+    pub fn stack_faults(&self) -> (u32, u32) {
+        (self.stack_overflows, self.stack_underflows)
+    }
+
+    /// Pushes a return address, as 2NNN does. A full stack drops its oldest
+    /// address to make room, which counts as an overflow.
+    pub fn push(&mut self, addr: u16) {
+        if self.sp as usize == self.stack.len() {
+            self.stack.copy_within(1.., 0);
+            self.sp -= 1;
+            self.stack_overflows = self.stack_overflows.saturating_add(1);
+            if let Some(profiler) = &mut self.profiler {
+                profiler.stack_overflow();
+            }
+        }
+        self.stack[self.sp as usize] = addr;
+        self.sp += 1;
+    }
+
+    /// Pops a return address, as 00EE does. An empty stack returns to the
+    /// load address, which counts as an underflow.
+    pub fn pop(&mut self) -> u16 {
+        if self.sp == 0 {
+            self.stack_underflows = self.stack_underflows.saturating_add(1);
+            if let Some(profiler) = &mut self.profiler {
+                profiler.stack_underflow();
+            }
+            return self.platform.load_address();
+        }
+        self.sp -= 1;
+        self.stack[self.sp as usize]
+    }
+
+    /// Starts collecting instruction statistics from scratch, or stops.
+    pub fn set_profiling(&mut self, enabled: bool) {
+        self.profiler = if enabled { Some(Profiler::new()) } else { None };
+    }
+
+    pub fn profiler(&self) -> Option<&Profiler> {
+        self.profiler.as_ref()
+    }
+
+    /// Starts recording coverage from scratch, or stops.
+    pub fn set_coverage(&mut self, enabled: bool) {
+        self.coverage = if enabled { Some(Coverage::new()) } else { None };
+    }
+
+    pub fn coverage(&self) -> Option<&Coverage> {
+        self.coverage.as_ref()
+    }
+
+    pub fn engine(&self) -> Engine {
+        self.engine
+    }
+
+    pub fn set_engine(&mut self, engine: Engine) {
+        self.engine = engine;
+        self.blocks.clear();
+    }
+
+    /// How many blocks the cached block engine has decoded and kept.
+    pub fn cached_blocks(&self) -> usize {
+        self.blocks.count()
+    }
+
     pub fn reset(&mut self) {
-        self.pc = START_ADDR;
+        self.pc = self.platform.load_address();
         self.v = [0; 16];
         self.i = 0;
         self.stack = [0; 16];
-        self.memory = [0; 4096];
+        self.memory.reset(self.platform.memory_size());
         self.sp = 0;
+        self.stack_overflows = 0;
+        self.stack_underflows = 0;
         self.delay_timer = 0;
         self.sound_timer = 0;
-        self.screen.clear();
+        self.key_wait = None;
+        let (width, height) = self.platform.resolution();
+        self.screen.set_resolution(width, height);
         self.keyboard.clear();
+        self.colours = ColourAttributes::new(width, height);
+        self.keyboard2.clear();
+        self.port_input = 0;
+        self.port_output = None;
+        #[cfg(feature = "megachip")]
+        {
+            self.megachip = MegaChip::new();
+        }
+        if let Some(profiler) = &mut self.profiler {
+            profiler.reset_calls();
+        }
         self.load_fonts();
+        self.blocks.clear();
     }
 
-    pub fn load_program(&mut self, program: &[u8]) {
-        for idx in 0..program.len() {
-            self.memory[self.pc as usize + idx] = program[idx];
-        }
+    /// Copies a program to the platform's load address and points PC at it.
+    pub fn load_program(&mut self, program: &[u8]) -> Result<(), RomError> {
+        let start = self.platform.load_address() as usize;
+        rom::check_fits(program, start, self.memory.size())?;
+        self.memory.load(start, program);
+        self.blocks.clear();
+        self.pc = self.platform.entry_point(program);
+        Ok(())
     }
 
     pub fn update_timer(&mut self) {
@@ -79,18 +366,211 @@ impl CPU {
     }
 
     fn load_fonts(&mut self) {
-        for idx in 0..80 {
-            self.memory[idx] = FONT_SET[idx]
+        self.memory.load(0, &FONT_SET);
+    }
+
+    /// Runs one 60Hz frame: up to `cycles` instructions followed by a timer tick.
+    /// Stops executing early while an FX0A is waiting for input, or after a
+    /// sprite is drawn with the vblank quirk.
+    /// The cached block engine is only used while neither profiling nor
+    /// coverage is on, since they need every instruction to be fetched.
+    pub fn run_frame(&mut self, cycles: usize) {
+        self.run_cycles(cycles);
+        self.end_frame();
+    }
+
+    /// Runs up to `cycles` cycles of a frame without ending it, returning
+    /// whether the frame ended early. Running a frame in several parts is the
+    /// same as running it at once.
+    pub fn run_cycles(&mut self, cycles: usize) -> bool {
+        if self.engine == Engine::CachedBlocks && self.profiler.is_none() && self.coverage.is_none() {
+            return self.run_blocks(cycles);
         }
+        for _ in 0..cycles {
+            if self.step() {
+                return true;
+            }
+        }
+        false
+    }
+
+    // `run_cycles` with cached blocks, ending the frame on the same
+    // instructions as `step` does.
+    fn run_blocks(&mut self, mut cycles: usize) -> bool {
+        while cycles > 0 {
+            if !self.is_waiting_for_key() {
+                let block = self.blocks.get(self.pc, self.memory.bytes(), self.platform);
+                let count = block.ops.len().min(cycles);
+                if count > 0 {
+                    for &(opcode, instruction) in &block.ops[..count] {
+                        self.execute_decoded(opcode, instruction);
+                    }
+                    cycles -= count;
+                    let (opcode, _) = block.ops[count - 1];
+                    let drawing = opcode & 0xF000 == 0xD000;
+                    if self.is_waiting_for_key() || (drawing && self.quirks.vblank) {
+                        return true;
+                    }
+                    continue;
+                }
+            }
+            // Waiting for a key, or past the end of memory.
+            cycles -= 1;
+            if self.step() {
+                return true;
+            }
+        }
+        false
+    }
+
+    // Runs the instructions that are most common in blocks without matching
+    // on the opcode again.
+    fn execute_decoded(&mut self, opcode: u16, instruction: Instruction) {
+        match instruction {
+            Instruction::LoadImm(x, nn) => self.v[x as usize] = nn,
+            Instruction::AddImm(x, nn) => self.v[x as usize] = self.v[x as usize].wrapping_add(nn),
+            Instruction::LoadI(nnn) => self.i = nnn as u32,
+            Instruction::Jump(nnn) => {
+                self.pc = nnn;
+                return;
+            }
+            _ => return self.execute(opcode),
+        }
+        self.advance();
+    }
+
+    /// Runs one cycle of `run_frame`, returning whether the frame ends early.
+    pub fn step(&mut self) -> bool {
+        let drawing = self.memory.bytes().get(self.pc as usize).is_some_and(|op| op & 0xF0 == 0xD0);
+        self.execute_next();
+        self.is_waiting_for_key() || (drawing && self.quirks.vblank)
+    }
+
+    /// Ticks the timers and the keyboards at the end of a frame.
+    pub fn end_frame(&mut self) {
+        self.update_timer();
+        self.keyboard.end_frame();
+        self.keyboard2.end_frame();
+    }
+
+    pub fn is_waiting_for_key(&self) -> bool {
+        self.key_wait.is_some()
+    }
+
+    // Completes a pending FX0A once a key has been pressed, and released
+    // as well if the platform waits for the release.
+    fn poll_key_wait(&mut self) {
+        let mut wait = match self.key_wait {
+            Some(wait) => wait,
+            None => return,
+        };
+        if wait.key.is_none() {
+            wait.key = self.keyboard.newly_pressed();
+        }
+        self.key_wait = match wait.key {
+            Some(key) if !self.quirks.key_wait_release || self.keyboard.was_released(key) => {
+                self.v[wait.register] = key;
+                self.keyboard.clear_edges();
+                None
+            }
+            _ => Some(wait),
+        };
     }
 
     pub fn execute_next(&mut self) {
-        let next_op = (self.memory[self.pc as usize] as u16) << 8 | self.memory[self.pc as usize + 1] as u16;
+        if self.is_waiting_for_key() {
+            self.poll_key_wait();
+            if let Some(profiler) = &mut self.profiler {
+                profiler.wait();
+            }
+            return;
+        }
+        let pc = self.pc as usize;
+        let next_op = (self.memory.read(pc, Access::Fetch) as u16) << 8 | self.memory.read(pc + 1, Access::Fetch) as u16;
         self.execute(next_op);
+        if let Some(profiler) = &mut self.profiler {
+            profiler.instruction(next_op, self.sp as usize, self.pc);
+        }
+    }
+
+    // `len` bytes from I, such as sprite data.
+    fn read_bytes(&mut self, len: usize) -> Vec<u8> {
+        let start = self.i as usize;
+        (start..start + len).map(|addr| self.memory.read(addr, Access::Read)).collect()
+    }
+
+    // Writes a byte for FX33 and FX55, which may change cached code.
+    fn write(&mut self, addr: usize, value: u8) {
+        self.memory.write(addr, value);
+        // The bus wraps the address around the end of memory.
+        self.blocks.invalidate(addr % self.memory.size());
+    }
+
+    // Switches the screen to another resolution, with colour zones to match.
+    fn set_resolution(&mut self, width: usize, height: usize) {
+        self.screen.set_resolution(width, height);
+        self.colours.resize(width, height);
+    }
+
+    // Moves PC to the next instruction, wrapping around the end of memory
+    // like every other address.
+    fn advance(&mut self) {
+        let next = self.pc as usize + 2;
+        self.pc = if next < self.memory.size() { next } else { next % self.memory.size() } as u16;
+    }
+
+    // I wraps around at 16 bits, or 24 with MEGA-CHIP's memory.
+    fn i_mask(&self) -> u32 {
+        if self.memory.size() > 0x10000 { 0xFFFFFF } else { 0xFFFF }
+    }
+
+    fn reset_vf_after_logic(&mut self) {
+        if self.quirks.logic {
+            self.v[0xF] = 0;
+        }
+    }
+
+    fn advance_i_after_load_store(&mut self, x: usize) {
+        if self.quirks.memory_increment_by_x {
+            self.i = (self.i + x as u32) & self.i_mask();
+        } else if !self.quirks.memory_leave_i_unchanged {
+            self.i = (self.i + x as u32 + 1) & self.i_mask();
+        }
+    }
+
+    // 01NN to 080N, only decoded in the MEGA-CHIP 256x192 mode.
+    #[cfg(feature = "megachip")]
+    fn execute_megachip(&mut self, opcode: u16) {
+        let nn = (opcode & 0xFF) as u8;
+        let n = nn & 0xF;
+        match (opcode & 0x0F00) >> 8 {
+            // I = NN NNNN, the low 16 bits are in the next word
+            1 => {
+                let pc = self.pc as usize;
+                let low = (self.memory.read(pc, Access::Read) as u32) << 8 | self.memory.read(pc + 1, Access::Read) as u32;
+                self.i = (nn as u32) << 16 | low;
+                self.advance();
+            }
+            // Load NN colours from I into the palette
+            2 => {
+                let colours = self.read_bytes(nn as usize * 4);
+                self.megachip.load_palette(&colours, nn as usize);
+            }
+            3 => self.megachip.set_sprite_width(nn),
+            4 => self.megachip.set_sprite_height(nn),
+            5 => self.megachip.set_alpha(nn),
+            // Play the sample at I, looping when N is 0
+            6 => self.megachip.play(self.memory.bytes(), self.i as usize, n == 0),
+            7 => self.megachip.stop(),
+            8 => self.megachip.set_blend(n),
+            _ => (),
+        }
     }
 
-    fn execute(&mut self, opcode: u16) {
-        self.pc += 2;
+    /// Runs one instruction as if it had been fetched at PC.
+    pub fn execute(&mut self, opcode: u16) {
+        let pc = self.pc;
+        self.advance();
 
         let ops = (
             (opcode & 0xF000) >> 12,
@@ -107,123 +587,215 @@ impl CPU {
 
         match ops {
             // Clear screen
-            (0, 0, 0xE, 0) => self.screen.clear(),
-            // Returns from a subroutine
-            (0, 0, 0xE, 0xE) => {
-                self.sp -= 1;
-                self.pc = self.stack[self.sp as usize];
+            (0, 0, 0xE, 0) => {
+                self.screen.clear();
+                #[cfg(feature = "megachip")]
+                self.megachip.clear();
+            }
+            // Leave and enter the 256x192 mode (MEGA-CHIP)
+            #[cfg(feature = "megachip")]
+            (0, 0, 1, 0) if self.platform == Platform::MegaChip => {
+                let (width, height) = self.platform.resolution();
+                self.set_resolution(width, height);
+                self.megachip.set_enabled(false);
+            }
+            #[cfg(feature = "megachip")]
+            (0, 0, 1, 1) if self.platform == Platform::MegaChip => {
+                self.set_resolution(megachip::WIDTH, megachip::HEIGHT);
+                self.megachip.set_enabled(true);
             }
+            #[cfg(feature = "megachip")]
+            (0, 1..=8, _, _) if self.megachip.is_enabled() => self.execute_megachip(opcode),
+            // Clear screen (hi-res CHIP-8)
+            (0, 2, 3, 0) if self.platform == Platform::HiresChip8 => self.screen.clear(),
+            // Switch to low resolution (SUPER-CHIP)
+            (0, 0, 0xF, 0xE) if self.quirks.hires => {
+                let (width, height) = self.platform.resolution();
+                self.set_resolution(width, height);
+            }
+            // Switch to high resolution (SUPER-CHIP)
+            (0, 0, 0xF, 0xF) if self.quirks.hires => {
+                let (width, height) = HIRES_RESOLUTION;
+                self.set_resolution(width, height);
+            }
+            // Scroll down N pixels (SUPER-CHIP)
+            (0, 0, 0xC, _) if self.quirks.hires => self.screen.scroll(0, n as isize),
+            // Scroll right 4 pixels (SUPER-CHIP)
+            (0, 0, 0xF, 0xB) if self.quirks.hires => self.screen.scroll(4, 0),
+            // Scroll left 4 pixels (SUPER-CHIP)
+            (0, 0, 0xF, 0xC) if self.quirks.hires => self.screen.scroll(-4, 0),
+            // Cycle the background colour (CHIP-8X)
+            (0, 2, 0xA, 0) if self.platform == Platform::Chip8X => self.colours.cycle_background(),
+            // Returns from a subroutine
+            (0, 0, 0xE, 0xE) => self.pc = self.pop(),
             // Jump to address NNN
             (1, _, _, _) => self.pc = nnn,
             // Calls subroutine at NNN
             (2, _, _, _) => {
-                self.stack[self.sp as usize] = self.pc;
-                self.sp += 1;
+                self.push(self.pc);
                 self.pc = nnn;
             }
             // if(Vx==NN)
             (3, _, _, _) => {
                 if self.v[x] == nn {
-                    self.pc += 2
+                    self.advance();
                 }
             }
             // if(Vx!=NN)
             (4, _, _, _) => {
                 if self.v[x] != nn {
-                    self.pc += 2
+                    self.advance();
                 }
             }
             // 	if(Vx==Vy)
             (5, _, _, 0) => {
                 if self.v[x] == self.v[y] {
-                    self.pc += 2
+                    self.advance();
                 }
             }
+            // Vx += Vy, adding each nibble separately (CHIP-8X)
+            (5, _, _, 1) if self.platform == Platform::Chip8X => {
+                let high = (self.v[x] & 0xF0).wrapping_add(self.v[y] & 0xF0);
+                let low = ((self.v[x] & 0x0F) + (self.v[y] & 0x0F)) & 0x0F;
+                self.v[x] = high | low;
+            }
             // Vx = NN
             (6, _, _, _) => self.v[x] = nn,
             // Vx += NN
-            (7, _, _, _) => self.v[x] += nn,
+            (7, _, _, _) => self.v[x] = self.v[x].wrapping_add(nn),
             // Vx == Vy
             (8, _, _, 0) => self.v[x] = self.v[y],
             // Vx=Vx|Vy
-            (8, _, _, 1) => self.v[x] |= self.v[y],
+            (8, _, _, 1) => {
+                self.v[x] |= self.v[y];
+                self.reset_vf_after_logic();
+            }
             // Vx=Vx&Vy
-            (8, _, _, 2) => self.v[x] &= self.v[y],
+            (8, _, _, 2) => {
+                self.v[x] &= self.v[y];
+                self.reset_vf_after_logic();
+            }
             // Vx=Vx^Vy
-            (8, _, _, 3) => self.v[x] ^= self.v[y],
+            (8, _, _, 3) => {
+                self.v[x] ^= self.v[y];
+                self.reset_vf_after_logic();
+            }
             // Vx += Vy
+            // The flag is set after the result, so it wins when X is F.
             (8, _, _, 4) => {
                 let (res, ov) = self.v[x].overflowing_add(self.v[y]);
+                self.v[x] = res;
                 self.v[0x0F] = if ov { 1 } else { 0 };
-                self.v[x] = res as u8;
             }
             // Vx -= Vy
             (8, _, _, 5) => {
                 let (res, ov) = self.v[x].overflowing_sub(self.v[y]);
-                self.v[0x0F] = if ov { 0 } else { 1 };
                 self.v[x] = res;
+                self.v[0x0F] = if ov { 0 } else { 1 };
             }
             // Vx>>=1
             (8, _, _, 6) => {
-                self.v[0x0F] = self.v[x] & 0x1;
+                if !self.quirks.shift {
+                    self.v[x] = self.v[y];
+                }
+                let flag = self.v[x] & 0x1;
                 self.v[x] >>= 1;
+                self.v[0x0F] = flag;
             }
             // Vx=Vy-Vx
             (8, _, _, 7) => {
                 let (res, ov) = self.v[y].overflowing_sub(self.v[x]);
-                self.v[0x0F] = if ov { 0 } else { 1 };
                 self.v[x] = res;
+                self.v[0x0F] = if ov { 0 } else { 1 };
             }
             // Vx<<=1
             (8, _, _, 0xE) => {
-                self.v[0x0F] = (self.v[x] & 0x80) >> 7;
+                if !self.quirks.shift {
+                    self.v[x] = self.v[y];
+                }
+                let flag = (self.v[x] & 0x80) >> 7;
                 self.v[x] <<= 1;
+                self.v[0x0F] = flag;
             }
             // if(Vx!=Vy)
             (9, _, _, 0) => {
                 if self.v[x] != self.v[y] {
-                    self.pc += 2;
+                    self.advance();
                 }
             }
             // ANNN	MEM	I = NNN	Sets I to the address NNN.
-            (0xA, _, _, _) => { self.i = nnn as u16; }
+            (0xA, _, _, _) => { self.i = nnn as u32; }
             // BNNN	Flow	PC=V0+NNN	Jumps to the address NNN plus V0.
+            // With the jump quirk: BXNN	PC=VX+XNN
+            (0xB, _, _, _) if self.platform != Platform::Chip8X => {
+                let offset = if self.quirks.jump { self.v[x] } else { self.v[0] };
+                self.pc = ((offset as usize + nnn as usize) % self.memory.size()) as u16;
+            }
+            // Colour the 8x4 blocks given by Vx and Vx+1 with Vy (CHIP-8X)
+            (0xB, _, _, 0) => {
+                self.colours.fill_blocks(self.v[x], self.v[(x + 1) & 0xF], self.v[y]);
+            }
+            // Colour N rows of the 8 pixel zone at Vx, Vx+1 with Vy (CHIP-8X)
             (0xB, _, _, _) => {
-                self.pc = self.v[0] as u16 + nnn;
+                self.colours.fill_rows(self.v[x], self.v[(x + 1) & 0xF], n, self.v[y]);
             }
             // Vx=rand()&NN
             (0xC, _, _, _) => {
-                self.v[x] = get_random_buf().unwrap()[0] & nn;
+                let random = match &mut self.rng {
+                    Some(rng) => rng.next_byte(),
+                    None => get_random_buf().unwrap()[0],
+                };
+                self.v[x] = random & nn;
             }
             // draw(Vx,Vy,N)
+            // The starting position wraps around the screen, pixels past the edge
+            // are clipped or wrapped depending on the platform.
+            #[cfg(feature = "megachip")]
+            (0xD, _, _, _) if self.megachip.is_enabled() => {
+                let sprite = self.read_bytes(self.megachip.sprite_size());
+                let collided = self.megachip.draw(&sprite, self.v[x] as usize, self.v[y] as usize, &mut self.screen);
+                self.v[0xF] = collided as u8;
+            }
             (0xD, _, _, _) => {
-                let (width, height) = (8, n);
-                self.v[0xF] = 0;
-                for row in 0..height {
-                    let mut sprite = self.memory[self.i as usize + row as usize];
-                    for col in 0..width {
-                        if sprite & 0x80 > 0 {
-                            let c = self.v[x] as usize + col;
-                            let r = self.v[y] as usize + row as usize;
-                            if self.screen.get_pixel(r, c) {
-                                self.v[0xF] = 1;
-                            }
-                            self.screen.set_pixel(r, c);
-                        }
-                        sprite <<= 1;
-                    }
-                }
+                let wide = n == 0 && self.quirks.hires;
+                let (width, height) = if wide { (16, 16) } else { (8, n as usize) };
+                let bytes = self.read_bytes(if wide { height * 2 } else { height });
+                let rows: Vec<u16> = if wide {
+                    bytes.chunks(2).map(|word| (word[0] as u16) << 8 | word[1] as u16).collect()
+                } else {
+                    bytes.iter().map(|&byte| (byte as u16) << 8).collect()
+                };
+                let (left, top) = (self.v[x] as usize, self.v[y] as usize);
+                let result = self.screen.draw_sprite(left, top, &rows, width, self.quirks.wrap_sprites);
+                let hires = (self.screen.width(), self.screen.height()) == HIRES_RESOLUTION;
+                self.v[0xF] = if self.quirks.count_collided_rows && hires {
+                    (result.collided_rows + result.clipped_rows) as u8
+                } else {
+                    result.collided() as u8
+                };
             }
             // if(key()==Vx)
             (0xE, _, 9, 0xE) => {
                 if self.keyboard.is_key_pressed(self.v[x]) {
-                    self.pc += 2;
+                    self.advance();
                 }
             }
             // if(key()!=Vx)
             (0xE, _, 0xA, 1) => {
                 if !self.keyboard.is_key_pressed(self.v[x]) {
-                    self.pc += 2;
+                    self.advance();
+                }
+            }
+            // if(key2()==Vx) (CHIP-8X)
+            (0xE, _, 0xF, 2) if self.platform == Platform::Chip8X => {
+                if self.keyboard2.is_key_pressed(self.v[x]) {
+                    self.advance();
+                }
+            }
+            // if(key2()!=Vx) (CHIP-8X)
+            (0xE, _, 0xF, 5) if self.platform == Platform::Chip8X => {
+                if !self.keyboard2.is_key_pressed(self.v[x]) {
+                    self.advance();
                 }
             }
             // Vx = get_delay()
@@ -231,14 +803,10 @@ impl CPU {
                 self.v[x] = self.delay_timer;
             }
             // Vx = get_key()
+            // Halts until a key is pressed (and released on the VIP), see `poll_key_wait`.
             (0xF, _, 0, 0xA) => {
-                for idx in 0..self.keyboard.pressed_keys.len() {
-                    if self.keyboard.pressed_keys[idx] {
-                        self.v[x] = idx as u8;
-                        self.pc -= 2;
-                        break;
-                    }
-                }
+                self.keyboard.clear_edges();
+                self.key_wait = Some(KeyWait { register: x, key: None });
             }
             // delay_timer(Vx)	Sets the delay timer to VX.
             (0xF, _, 1, 5) => {
@@ -251,14 +819,15 @@ impl CPU {
             // FX1E	MEM	I +=Vx	Adds VX to I. VF is set to 1 when there is a range overflow
             // (I+VX>0xFFF), and to 0 when there isn't.[c]
             (0xF, _, 1, 0xE) => {
-                let (res, ov) = self.i.overflowing_add(self.v[x] as u16);
-                self.v[0xF] = if ov { 1 } else { 0 };
-                self.i = res;
+                let mask = self.i_mask();
+                let res = self.i + self.v[x] as u32;
+                self.v[0xF] = if res > mask { 1 } else { 0 };
+                self.i = res & mask;
             }
             // FX29	MEM	I=sprite_addr[Vx]	Sets I to the location of the sprite for the character
             // in VX. Characters 0-F (in hexadecimal) are represented by a 4x5 font.
             (0xF, _, 2, 9) => {
-                self.i = self.v[x] as u16 * 5;
+                self.i = self.v[x] as u32 * 5;
             }
             // FX33	BCD	set_BCD(Vx);
             // *(I+0)=BCD(3);
@@ -267,51 +836,363 @@ impl CPU {
             //
             // *(I+2)=BCD(1);
             (0xF, _, 3, 3) => {
-                self.memory[self.i as usize] = self.v[x] / 100;
-                self.memory[self.i as usize + 1] = (self.v[x] % 100) / 10;
-                self.memory[self.i as usize + 2] = self.v[x] % 10;
+                let start = self.i as usize;
+                self.write(start, self.v[x] / 100);
+                self.write(start + 1, (self.v[x] % 100) / 10);
+                self.write(start + 2, self.v[x] % 10);
             }
             // FX55	MEM	reg_dump(Vx,&I)	Stores V0 to VX (including VX) in memory starting at
             // address I. The offset from I is increased by 1 for each value written,
             // but I itself is left unmodified.[d]
             (0xF, _, 5, 5) => {
-                for idx in 0..x + 1 {
-                    self.memory[self.i as usize + idx as usize] = self.v[idx];
+                let start = self.i as usize;
+                let v = self.v;
+                for (offset, &value) in v[..=x].iter().enumerate() {
+                    self.write(start + offset, value);
                 }
+                self.advance_i_after_load_store(x);
             }
             // FX65	MEM	reg_load(Vx,&I)	Fills V0 to VX (including VX) with values from memory
             // starting at address I. The offset from I is increased by 1 for each value written,
             // but I itself is left unmodified.[d]
             (0xF, _, 6, 5) => {
-                for idx in 0..x + 1 {
-                    self.v[idx] = self.memory[self.i as usize + idx as usize];
-                }
+                let values = self.read_bytes(x + 1);
+                self.v[..=x].copy_from_slice(&values);
+                self.advance_i_after_load_store(x);
+            }
+            // out(Vx) (CHIP-8X)
+            (0xF, _, 0xF, 8) if self.platform == Platform::Chip8X => {
+                self.port_output = Some(self.v[x]);
+            }
+            // Vx = in() (CHIP-8X)
+            // There is no device to wait for, the last value given to `set_port_input` is read.
+            (0xF, _, 0xF, 0xB) if self.platform == Platform::Chip8X => {
+                self.v[x] = self.port_input;
             }
             (_, _, _, _) => ()
         }
+
+        if let Some(coverage) = &mut self.coverage {
+            coverage.record(pc, decoder::decode(opcode, self.platform), self.pc);
+        }
+    }
+}
+
+impl<B: Bus> CPU<Screen, B> {
+    /// Everything a program can observe, apart from the keyboards and the
+    /// CHIP-8X port, which belong to the host. `None` in MEGA-CHIP's 256x192
+    /// mode, whose colour layer isn't saved.
+    pub fn save_state(&self) -> Option<State> {
+        #[cfg(feature = "megachip")]
+        if self.megachip.is_enabled() {
+            return None;
+        }
+        let memory = self.memory.bytes();
+        let used = memory.iter().rposition(|&byte| byte != 0).map_or(0, |last| last + 1);
+        Some(State {
+            platform: self.platform,
+            quirks: self.quirks,
+            pc: self.pc,
+            v: self.v,
+            i: self.i,
+            stack: self.stack().to_vec(),
+            delay_timer: self.delay_timer,
+            sound_timer: self.sound_timer,
+            key_wait: self.key_wait.map(|wait| (wait.register as u8, wait.key)),
+            memory_size: memory.len(),
+            memory: memory[..used].to_vec(),
+            width: self.screen.width(),
+            height: self.screen.height(),
+            pixels: state::pack_pixels(self.screen.pixels()),
+            colours: self.colours.clone(),
+            rng: self.rng.as_ref().map(Rng::state),
+        })
+    }
+
+    /// Restores a state from `save_state`. The CPU is left alone if the state
+    /// doesn't make sense.
+    pub fn load_state(&mut self, state: &State) -> Result<(), String> {
+        let hires = state.quirks.hires || state.platform.quirks().hires;
+        let resolutions = if hires {
+            vec![state.platform.resolution(), HIRES_RESOLUTION]
+        } else {
+            vec![state.platform.resolution()]
+        };
+        if state.memory_size != state.platform.memory_size() || state.memory.len() > state.memory_size {
+            return Err("the memory doesn't match the platform".to_string());
+        }
+        if state.pc as usize >= state.memory_size || state.i > 0xFFFFFF {
+            return Err("PC or I is out of range".to_string());
+        }
+        if state.stack.len() > self.stack.len() {
+            return Err("the stack is too deep".to_string());
+        }
+        if state.stack.iter().any(|&addr| addr as usize >= state.memory_size) {
+            return Err("a return address is out of range".to_string());
+        }
+        if state.key_wait.is_some_and(|(register, key)| register > 0xF || key.is_some_and(|key| key > 0xF)) {
+            return Err("FX0A is waiting with a bad register or key".to_string());
+        }
+        let (width, height) = (state.width, state.height);
+        if !resolutions.contains(&(width, height)) || state.pixels.len() != (width * height).div_ceil(8) {
+            return Err("the screen has a bad size".to_string());
+        }
+        if !state.colours.is_valid(width, height) {
+            return Err("the colours don't fit the screen".to_string());
+        }
+
+        self.set_platform(state.platform, state.quirks);
+        self.memory.reset(state.memory_size);
+        self.memory.load(0, &state.memory);
+        self.pc = state.pc;
+        self.v = state.v;
+        self.i = state.i;
+        self.stack = [0; 16];
+        self.stack[..state.stack.len()].copy_from_slice(&state.stack);
+        self.sp = state.stack.len() as u8;
+        self.delay_timer = state.delay_timer;
+        self.sound_timer = state.sound_timer;
+        self.key_wait = state.key_wait.map(|(register, key)| KeyWait { register: register as usize, key });
+        self.screen.set_resolution(width, height);
+        for (pixel, on) in state::unpack_pixels(&state.pixels, width * height).into_iter().enumerate() {
+            if on {
+                self.screen.set_pixel(pixel / width, pixel % width);
+            }
+        }
+        self.screen.end_draw();
+        self.colours = state.colours.clone();
+        self.rng = state.rng.map(Rng::restore);
+        #[cfg(feature = "megachip")]
+        {
+            self.megachip = MegaChip::new();
+        }
+        self.blocks.clear();
+        Ok(())
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::coverage::Branch;
+    use crate::display::DrawResult;
 
     #[test]
     fn test_load_fonts_when_created() {
         let cpu = CPU::new();
-        assert_eq!(cpu.memory[0], FONT_SET[0]);
-        assert_eq!(cpu.memory[1], FONT_SET[1]);
-        assert_eq!(cpu.memory[79], FONT_SET[79]);
-        assert_eq!(cpu.memory[80], 0);
+        assert_eq!(cpu.memory.bytes()[0], FONT_SET[0]);
+        assert_eq!(cpu.memory.bytes()[1], FONT_SET[1]);
+        assert_eq!(cpu.memory.bytes()[79], FONT_SET[79]);
+        assert_eq!(cpu.memory.bytes()[80], 0);
     }
 
     #[test]
     fn test_load_program() {
         let mut cpu = CPU::new();
-        cpu.load_program(&[1, 2, 3]);
-        assert_eq!(cpu.memory[0x200], 1);
-        assert_eq!(cpu.memory[0x201], 2);
-        assert_eq!(cpu.memory[0x202], 3);
+        cpu.pc = 0x300;
+        cpu.load_program(&[1, 2, 3]).unwrap();
+        assert_eq!(cpu.memory.bytes()[0x200], 1);
+        assert_eq!(cpu.memory.bytes()[0x201], 2);
+        assert_eq!(cpu.memory.bytes()[0x202], 3);
+        assert_eq!(cpu.pc, START_ADDR);
+        assert_eq!(cpu.load_program(&[0; 3585]), Err(RomError::TooLarge { size: 3585, max: 3584 }));
+    }
+
+    #[test]
+    fn test_load_program_eti660() {
+        let mut cpu = CPU::with_platform(Platform::Eti660);
+        assert_eq!(cpu.pc, 0x600);
+        cpu.load_program(&[1, 2]).unwrap();
+        assert_eq!(cpu.memory.bytes()[0x600], 1);
+        assert_eq!(cpu.memory.bytes()[0x200], 0);
+        assert_eq!(cpu.pc, 0x600);
+    }
+
+    #[test]
+    fn test_hires_chip8() {
+        let mut cpu = CPU::with_platform(Platform::HiresChip8);
+        assert_eq!((cpu.get_screen().width(), cpu.get_screen().height()), (64, 64));
+        cpu.load_program(&[0x12, 0x60]).unwrap();
+        assert_eq!(cpu.pc, 0x2C0);
+
+        cpu.get_screen().set_pixel(63, 0);
+        cpu.execute(0x0230);
+        assert!(!cpu.get_screen().get_pixel(63, 0));
+    }
+
+    #[test]
+    fn test_chip8x() {
+        let mut cpu = CPU::with_platform(Platform::Chip8X);
+        assert_eq!(cpu.pc, 0x300);
+        cpu.execute(0x02A0);
+        assert_eq!(cpu.colours.background(), 0);
+
+        cpu.v[0] = 0x3E;
+        cpu.v[1] = 0xF3;
+        cpu.execute(0x5011);
+        assert_eq!(cpu.v[0], 0x21);
+
+        cpu.v[2] = 8;
+        cpu.v[3] = 4;
+        cpu.v[4] = 5;
+        cpu.execute(0xB242);
+        assert_eq!(cpu.colours.foreground(5, 8), 5);
+        assert_eq!(cpu.colours.foreground(6, 8), 1);
+        assert_eq!(cpu.pc, 0x306);
+    }
+
+    #[test]
+    fn test_chip8x_second_keypad_and_port() {
+        let mut cpu = CPU::with_platform(Platform::Chip8X);
+        cpu.v[0] = 3;
+        cpu.get_keyboard2().key_down(3);
+        cpu.execute(0xE0F2);
+        assert_eq!(cpu.pc, 0x304);
+        cpu.execute(0xE0F5);
+        assert_eq!(cpu.pc, 0x306);
+
+        cpu.execute(0xF0F8);
+        assert_eq!(cpu.take_port_output(), Some(3));
+        assert_eq!(cpu.take_port_output(), None);
+        cpu.set_port_input(0x42);
+        cpu.execute(0xF1FB);
+        assert_eq!(cpu.v[1], 0x42);
+    }
+
+    #[cfg(feature = "megachip")]
+    #[test]
+    fn test_megachip() {
+        let mut cpu = CPU::with_platform(Platform::MegaChip);
+        assert_eq!(cpu.memory.size(), crate::megachip::MEMORY_SIZE);
+        cpu.execute(0x0011);
+        assert_eq!(cpu.get_screen().width(), 256);
+
+        cpu.memory.bytes_mut()[0x204..0x206].copy_from_slice(&[0x00, 0x10]);
+        cpu.execute(0x0102);
+        assert_eq!(cpu.i, 0x020010);
+        assert_eq!(cpu.pc, START_ADDR + 6);
+
+        cpu.memory.bytes_mut()[0x20010..0x20014].copy_from_slice(&[0xFF, 0x00, 0xFF, 0x00]);
+        cpu.execute(0x0201);
+        cpu.execute(0x0301);
+        cpu.execute(0x0401);
+        cpu.i = 0x300;
+        cpu.memory.bytes_mut()[0x300] = 1;
+        cpu.v[0] = 200;
+        cpu.v[1] = 100;
+        cpu.execute(0xD011);
+        assert!(cpu.get_screen().get_pixel(100, 200));
+        assert_eq!(cpu.megachip.pixels()[100 * 256 + 200], 0x00FF00FF);
+        cpu.execute(0xD011);
+        assert_eq!(cpu.v[0xF], 1);
+
+        cpu.execute(0x0010);
+        assert_eq!(cpu.get_screen().width(), 64);
+    }
+
+    #[cfg(feature = "megachip")]
+    #[test]
+    fn test_enable_switches_resolution() {
+        let mut cpu = CPU::with_platform(Platform::MegaChip);
+        cpu.execute(0x0011);
+        assert!(cpu.megachip.is_enabled());
+        let size = (cpu.get_screen().width(), cpu.get_screen().height());
+        assert_eq!(size, (crate::megachip::WIDTH, crate::megachip::HEIGHT));
+        cpu.execute(0x0010);
+        assert!(!cpu.megachip.is_enabled());
+        assert_eq!((cpu.get_screen().width(), cpu.get_screen().height()), (64, 32));
+    }
+
+    // Records what the CPU asks of the display.
+    #[derive(Default)]
+    struct RecordingDisplay {
+        resolution: (usize, usize),
+        calls: Vec<String>,
+    }
+
+    impl Display for RecordingDisplay {
+        fn width(&self) -> usize {
+            self.resolution.0
+        }
+        fn height(&self) -> usize {
+            self.resolution.1
+        }
+        fn clear(&mut self) {
+            self.calls.push("clear".to_string());
+        }
+        fn draw_sprite(&mut self, x: usize, y: usize, rows: &[u16], width: usize, wrap: bool) -> DrawResult {
+            self.calls.push(format!("draw {} {} {:?} {} {}", x, y, rows, width, wrap));
+            DrawResult { collided_rows: 1, clipped_rows: 0 }
+        }
+        fn plot(&mut self, _row: usize, _col: usize) -> bool {
+            false
+        }
+        fn scroll(&mut self, dx: isize, dy: isize) {
+            self.calls.push(format!("scroll {} {}", dx, dy));
+        }
+        fn set_resolution(&mut self, width: usize, height: usize) {
+            self.resolution = (width, height);
+        }
+    }
+
+    #[test]
+    fn test_custom_display() {
+        let mut cpu = CPU::with_display(RecordingDisplay::default(), Platform::SuperChip);
+        assert_eq!(cpu.get_screen().resolution, (64, 32));
+        cpu.memory.bytes_mut()[0x300] = 0xA0;
+        cpu.i = 0x300;
+        cpu.v[1] = 5;
+        cpu.execute(0xD101);
+        cpu.execute(0x00E0);
+        cpu.execute(0x00FF);
+        cpu.execute(0x00C3);
+        cpu.execute(0x00FC);
+        assert_eq!(cpu.get_screen().calls, vec![
+            "draw 5 0 [40960] 8 false",
+            "clear",
+            "scroll 0 3",
+            "scroll -4 0",
+        ]);
+        assert_eq!(cpu.get_screen().resolution, (128, 64));
+        assert_eq!(cpu.v[0xF], 1);
+    }
+
+    #[test]
+    fn test_profiling() {
+        let mut cpu = CPU::new();
+        cpu.set_profiling(true);
+        // call 0x206, wait for a key there, return and loop
+        cpu.load_program(&[0x22, 0x06, 0x12, 0x04, 0x12, 0x04, 0xF0, 0x0A, 0x00, 0xEE]).unwrap();
+        for _ in 0..4 {
+            cpu.execute_next();
+        }
+        cpu.get_keyboard().key_down(1);
+        cpu.get_keyboard().key_up(1);
+        for _ in 0..3 {
+            cpu.execute_next();
+        }
+        let profiler = cpu.profiler().unwrap();
+        assert_eq!(profiler.cycles(), 7);
+        assert_eq!(profiler.opcodes()["FX0A"].cycles, 4);
+        let subroutines = profiler.subroutines();
+        assert_eq!(subroutines[0].addr, 0x206);
+        assert_eq!(subroutines[0].inclusive_cycles, 5);
+    }
+
+    #[test]
+    fn test_coverage() {
+        let mut cpu = CPU::new();
+        cpu.set_coverage(true);
+        // skip if V0 == 1, set V0 to 1, skip again and loop
+        cpu.load_program(&[0x30, 0x01, 0x60, 0x01, 0x30, 0x01, 0x12, 0x08, 0x12, 0x08]).unwrap();
+        for _ in 0..5 {
+            cpu.execute_next();
+        }
+        let coverage = cpu.coverage().unwrap();
+        assert_eq!(coverage.executed().collect::<Vec<_>>(), vec![0x200, 0x202, 0x204, 0x208]);
+        assert_eq!(coverage.hits(0x208), 2);
+        assert_eq!(coverage.branch(0x200), Some(Branch { taken: 0, not_taken: 1 }));
+        assert_eq!(coverage.branch(0x204), Some(Branch { taken: 1, not_taken: 0 }));
     }
 
     #[test]
@@ -331,6 +1212,42 @@ mod tests {
         assert_eq!(cpu.stack[0], 0x20 + 2);
     }
 
+    #[test]
+    fn test_stack_overflow_and_underflow() {
+        let mut cpu = CPU::new();
+        for addr in 0..17 {
+            cpu.push(addr);
+        }
+        assert_eq!(cpu.stack().len(), 16);
+        assert_eq!(cpu.stack()[0], 1);
+        assert_eq!(cpu.pop(), 16);
+        assert_eq!(cpu.stack_faults(), (1, 0));
+
+        let mut cpu = CPU::with_platform(Platform::Chip8X);
+        cpu.set_profiling(true);
+        cpu.execute(0x00EE);
+        assert_eq!(cpu.pc, 0x300);
+        assert!(cpu.stack().is_empty());
+        assert_eq!(cpu.stack_faults(), (0, 1));
+        assert_eq!(cpu.profiler().unwrap().stack_faults(), (0, 1));
+        cpu.reset();
+        assert_eq!(cpu.stack_faults(), (0, 0));
+    }
+
+    #[test]
+    fn test_pc_wraps_around_memory() {
+        let mut cpu = CPU::new();
+        cpu.pc = 0xFFE;
+        cpu.execute(0x6000);
+        assert_eq!(cpu.pc, 0);
+        cpu.pc = 0xFFE;
+        cpu.execute(0x2300);
+        assert_eq!(cpu.stack(), &[0]);
+        cpu.v[0] = 0xFF;
+        cpu.execute(0xBFFF);
+        assert_eq!(cpu.pc, 0xFE);
+    }
+
     #[test]
     fn test_execute_3xxx() {
         let mut cpu = CPU::new();
@@ -385,6 +1302,10 @@ mod tests {
         cpu.execute(0x70EF);
         assert_eq!(cpu.v[0], 1 + 0xEF);
         assert_eq!(cpu.pc, START_ADDR + 2);
+
+        cpu.execute(0x7011);
+        assert_eq!(cpu.v[0], 0x01);
+        assert_eq!(cpu.v[0xF], 0);
     }
 
     #[test]
@@ -397,7 +1318,83 @@ mod tests {
         assert_eq!(cpu.pc, START_ADDR + 2);
     }
 
-    // TODO: 8xxx cases
+    #[test]
+    fn test_execute_8xx4() {
+        let mut cpu = CPU::new();
+        cpu.v[0] = 0xF0;
+        cpu.v[1] = 0x20;
+        cpu.execute(0x8014);
+        assert_eq!((cpu.v[0], cpu.v[0xF]), (0x10, 1));
+        cpu.execute(0x8014);
+        assert_eq!((cpu.v[0], cpu.v[0xF]), (0x30, 0));
+    }
+
+    #[test]
+    fn test_execute_8xx5_and_8xx7() {
+        let mut cpu = CPU::new();
+        cpu.v[0] = 0x10;
+        cpu.v[1] = 0x20;
+        cpu.execute(0x8015);
+        assert_eq!((cpu.v[0], cpu.v[0xF]), (0xF0, 0));
+        cpu.execute(0x8015);
+        assert_eq!((cpu.v[0], cpu.v[0xF]), (0xD0, 1));
+        cpu.execute(0x8017);
+        assert_eq!((cpu.v[0], cpu.v[0xF]), (0x50, 0));
+        cpu.v[0] = 0x05;
+        cpu.execute(0x8017);
+        assert_eq!((cpu.v[0], cpu.v[0xF]), (0x1B, 1));
+    }
+
+    #[test]
+    fn test_execute_8xxe() {
+        let mut cpu = CPU::new();
+        cpu.v[0] = 0x81;
+        cpu.execute(0x800E);
+        assert_eq!((cpu.v[0], cpu.v[0xF]), (0x02, 1));
+        cpu.execute(0x800E);
+        assert_eq!((cpu.v[0], cpu.v[0xF]), (0x04, 0));
+    }
+
+    #[test]
+    fn test_execute_8xxx_flag_wins_over_vf() {
+        let mut cpu = CPU::new();
+        for (opcode, vf, vy, flag) in [(0x8F04, 0xFF, 0x01, 1), (0x8F05, 0x01, 0x02, 0), (0x8F06, 0x03, 0, 1), (0x8F07, 0x01, 0x02, 1), (0x8F0E, 0x40, 0, 0)] {
+            cpu.v[0xF] = vf;
+            cpu.v[0] = vy;
+            cpu.execute(opcode);
+            assert_eq!(cpu.v[0xF], flag, "{:04X}", opcode);
+        }
+    }
+
+    #[test]
+    fn test_execute_8xx1_logic_quirk() {
+        let mut cpu = CPU::new();
+        cpu.v[0] = 0b1010;
+        cpu.v[1] = 0b0101;
+        cpu.v[0xF] = 1;
+        cpu.execute(0x8011);
+        assert_eq!(cpu.v[0], 0b1111);
+        assert_eq!(cpu.v[0xF], 1);
+
+        cpu.set_quirks(Quirks { logic: true, ..cpu.quirks() });
+        cpu.execute(0x8011);
+        assert_eq!(cpu.v[0xF], 0);
+    }
+
+    #[test]
+    fn test_execute_8xx6_shift_quirk() {
+        let mut cpu = CPU::new();
+        cpu.v[0] = 0b100;
+        cpu.v[1] = 0b11;
+        cpu.execute(0x8016);
+        assert_eq!(cpu.v[0], 0b10);
+        assert_eq!(cpu.v[0xF], 0);
+
+        cpu.set_quirks(Quirks { shift: false, ..cpu.quirks() });
+        cpu.execute(0x8016);
+        assert_eq!(cpu.v[0], 0b1);
+        assert_eq!(cpu.v[0xF], 1);
+    }
 
     #[test]
     fn test_execute_axxx() {
@@ -410,9 +1407,20 @@ mod tests {
     fn test_execute_bxxx() {
         let mut cpu = CPU::new();
         cpu.v[0] = 2;
+        cpu.i = 0x123;
         cpu.execute(0xBEF3);
         assert_eq!(cpu.pc, 0xEF5);
-        assert_eq!(cpu.i, 0xEF3)
+        // BNNN only jumps, I is left alone.
+        assert_eq!(cpu.i, 0x123)
+    }
+
+    #[test]
+    fn test_execute_bxxx_jump_quirk() {
+        let mut cpu = CPU::with_platform(Platform::SuperChip);
+        cpu.v[0] = 2;
+        cpu.v[0xE] = 4;
+        cpu.execute(0xBEF3);
+        assert_eq!(cpu.pc, 0xEF7);
     }
 
     #[test]
@@ -425,11 +1433,12 @@ mod tests {
     }
 
     #[test]
+    #[allow(clippy::bool_assert_comparison)]
     fn test_execute_dxxx() {
         let mut cpu = CPU::new();
         cpu.i = 0;
-        cpu.memory[0] = 0b11110011;
-        cpu.memory[1] = 0b11001110;
+        cpu.memory.bytes_mut()[0] = 0b11110011;
+        cpu.memory.bytes_mut()[1] = 0b11001110;
         cpu.execute(0xD002);
         assert_eq!(true, cpu.get_screen().get_pixel(0, 0));
         assert_eq!(true, cpu.get_screen().get_pixel(0, 1));
@@ -452,7 +1461,7 @@ mod tests {
         assert_eq!(cpu.pc, START_ADDR + 2);
 
         // test collision
-        cpu.memory[0] = 0b11110100;
+        cpu.memory.bytes_mut()[0] = 0b11110100;
         cpu.execute(0xD001);
         assert_eq!(false, cpu.get_screen().get_pixel(0, 0));
         assert_eq!(false, cpu.get_screen().get_pixel(0, 1));
@@ -466,6 +1475,82 @@ mod tests {
         assert_eq!(cpu.pc, START_ADDR + 4);
     }
 
+    #[test]
+    fn test_execute_dxxx_wraps_start_position() {
+        let mut cpu = CPU::new();
+        cpu.i = 0;
+        cpu.memory.bytes_mut()[0] = 0b10000000;
+        cpu.v[0] = 64 + 3;
+        cpu.v[1] = 32 + 2;
+        cpu.execute(0xD011);
+        assert!(cpu.get_screen().get_pixel(2, 3));
+    }
+
+    #[test]
+    fn test_execute_dxxx_clips_at_edges() {
+        let mut cpu = CPU::new();
+        cpu.i = 0;
+        cpu.memory.bytes_mut()[0] = 0xFF;
+        cpu.memory.bytes_mut()[1] = 0xFF;
+        cpu.v[0] = 60;
+        cpu.v[1] = 31;
+        cpu.execute(0xD012);
+        assert!(cpu.get_screen().get_pixel(31, 63));
+        assert!(!cpu.get_screen().get_pixel(31, 0));
+        assert!(!cpu.get_screen().get_pixel(0, 60));
+        assert_eq!(cpu.v[0xF], 0);
+    }
+
+    #[test]
+    fn test_execute_dxxx_wraps_at_edges() {
+        let mut cpu = CPU::new();
+        cpu.set_quirks(Quirks { wrap_sprites: true, ..cpu.quirks() });
+        cpu.i = 0;
+        cpu.memory.bytes_mut()[0] = 0xFF;
+        cpu.memory.bytes_mut()[1] = 0xFF;
+        cpu.v[0] = 60;
+        cpu.v[1] = 31;
+        cpu.execute(0xD012);
+        assert!(cpu.get_screen().get_pixel(31, 63));
+        assert!(cpu.get_screen().get_pixel(31, 3));
+        assert!(cpu.get_screen().get_pixel(0, 60));
+        assert!(cpu.get_screen().get_pixel(0, 0));
+    }
+
+    #[test]
+    fn test_execute_dxxx_counts_rows_in_hires() {
+        let mut cpu = CPU::with_platform(Platform::SuperChip);
+        cpu.execute(0x00FF);
+        assert_eq!(cpu.get_screen().width(), 128);
+        cpu.i = 0;
+        cpu.memory.bytes_mut()[0] = 0x80;
+        cpu.memory.bytes_mut()[1] = 0x80;
+        cpu.memory.bytes_mut()[2] = 0x80;
+        cpu.v[1] = 62;
+        cpu.execute(0xD013);
+        // one row clipped at the bottom
+        assert_eq!(cpu.v[0xF], 1);
+        cpu.execute(0xD013);
+        // two rows collided, one clipped
+        assert_eq!(cpu.v[0xF], 3);
+
+        cpu.execute(0x00FE);
+        assert_eq!(cpu.get_screen().width(), 64);
+    }
+
+    #[test]
+    fn test_execute_dxy0_draws_16x16_in_hires() {
+        let mut cpu = CPU::with_platform(Platform::SuperChip);
+        cpu.execute(0x00FF);
+        cpu.i = 0x300;
+        for idx in 0..32 {
+            cpu.memory.bytes_mut()[0x300 + idx] = 0xFF;
+        }
+        cpu.execute(0xD000);
+        assert!(cpu.get_screen().get_pixel(15, 15));
+        assert!(!cpu.get_screen().get_pixel(16, 16));
+    }
+
     #[test]
     fn test_execute_ex9e() {
         let mut cpu = CPU::new();
@@ -477,6 +1562,11 @@ mod tests {
         cpu.get_keyboard().clear();
         cpu.execute(0xe09e);
         assert_eq!(cpu.pc, START_ADDR + 6);
+
+        cpu.get_keyboard().key_down(1);
+        cpu.v[0] = 0x31;
+        cpu.execute(0xe09e);
+        assert_eq!(cpu.pc, START_ADDR + 10);
     }
 
     #[test]
@@ -506,9 +1596,52 @@ mod tests {
         let mut cpu = CPU::new();
         cpu.get_keyboard().key_down(3);
         cpu.execute(0xF30A);
-        assert_eq!(cpu.keyboard.pressed_keys[3], true);
-        assert_eq!(cpu.v[3], 3);
-        assert_eq!(cpu.pc, START_ADDR);
+        assert!(cpu.is_waiting_for_key());
+        assert_eq!(cpu.pc, START_ADDR + 2);
+
+        // a key held before FX0A doesn't count
+        cpu.execute_next();
+        assert!(cpu.is_waiting_for_key());
+
+        cpu.get_keyboard().key_up(3);
+        cpu.get_keyboard().key_down(7);
+        cpu.execute_next();
+        assert!(cpu.is_waiting_for_key());
+
+        cpu.get_keyboard().key_up(7);
+        cpu.execute_next();
+        assert!(!cpu.is_waiting_for_key());
+        assert_eq!(cpu.v[3], 7);
+        assert_eq!(cpu.pc, START_ADDR + 2);
+    }
+
+    #[test]
+    fn test_execute_fx0a_on_press() {
+        let mut cpu = CPU::with_platform(Platform::SuperChip);
+        cpu.execute(0xF30A);
+        cpu.get_keyboard().key_down(7);
+        cpu.execute_next();
+        assert!(!cpu.is_waiting_for_key());
+        assert_eq!(cpu.v[3], 7);
+    }
+
+    #[test]
+    fn test_run_frame_idles_while_waiting() {
+        let mut cpu = CPU::new();
+        cpu.load_program(&[0xF0, 0x0A, 0x61, 0x01]).unwrap();
+        cpu.delay_timer = 2;
+        cpu.run_frame(10);
+        assert!(cpu.is_waiting_for_key());
+        assert_eq!(cpu.pc, START_ADDR + 2);
+        assert_eq!(cpu.delay_timer, 1);
+
+        // a tap within a single frame is still seen
+        cpu.get_keyboard().key_down(4);
+        cpu.get_keyboard().key_up(4);
+        cpu.run_frame(10);
+        assert!(!cpu.is_waiting_for_key());
+        assert_eq!(cpu.v[0], 4);
+        assert_eq!(cpu.v[1], 1);
     }
 
     #[test]
@@ -554,9 +1687,9 @@ mod tests {
         cpu.v[5] = 123;
         cpu.i = 1000;
         cpu.execute(0xf533);
-        assert_eq!(cpu.memory[1000], 1);
-        assert_eq!(cpu.memory[1001], 2);
-        assert_eq!(cpu.memory[1002], 3);
+        assert_eq!(cpu.memory.bytes()[1000], 1);
+        assert_eq!(cpu.memory.bytes()[1001], 2);
+        assert_eq!(cpu.memory.bytes()[1002], 3);
         assert_eq!(cpu.pc, START_ADDR + 2);
     }
 
@@ -566,22 +1699,170 @@ mod tests {
         cpu.i = 1000;
         cpu.execute(0xff55);
         for i in 0..16 {
-            assert_eq!(cpu.memory[1000 + i as usize], cpu.v[i]);
+            assert_eq!(cpu.memory.bytes()[1000 + i], cpu.v[i]);
         }
         assert_eq!(cpu.pc, START_ADDR + 2);
     }
 
+    #[test]
+    fn test_execute_fx55_memory_quirks() {
+        let mut cpu = CPU::new();
+        cpu.i = 1000;
+        cpu.execute(0xf255);
+        assert_eq!(cpu.i, 1000);
+
+        cpu.set_quirks(Quirks { memory_leave_i_unchanged: false, ..cpu.quirks() });
+        cpu.execute(0xf255);
+        assert_eq!(cpu.i, 1003);
+
+        cpu.set_quirks(Quirks { memory_increment_by_x: true, ..cpu.quirks() });
+        cpu.execute(0xf265);
+        assert_eq!(cpu.i, 1005);
+    }
+
+    #[test]
+    fn test_execute_fx55_write_protection() {
+        let mut cpu = CPU::new();
+        cpu.memory.add_hook(crate::bus::WriteProtection::interpreter());
+        cpu.v[0] = 0xAA;
+        cpu.v[1] = 0xBB;
+        cpu.i = 0x1FF;
+        cpu.execute(0xf155);
+        assert_eq!(cpu.memory.bytes()[0x1FF], 0);
+        assert_eq!(cpu.memory.bytes()[0x200], 0xBB);
+        // Hooks survive resets.
+        cpu.reset();
+        assert!(cpu.memory.hook::<crate::bus::WriteProtection>().is_some());
+    }
+
+    #[test]
+    fn test_run_frame_vblank_quirk() {
+        let mut cpu = CPU::new();
+        cpu.set_quirks(Quirks { vblank: true, ..cpu.quirks() });
+        cpu.load_program(&[0xD0, 0x01, 0xD0, 0x01]).unwrap();
+        cpu.run_frame(10);
+        assert_eq!(cpu.pc, START_ADDR + 2);
+        cpu.run_frame(10);
+        assert_eq!(cpu.pc, START_ADDR + 4);
+    }
+
+    #[test]
+    fn test_cached_blocks_self_modifying() {
+        // Calls 0x210, rewrites its V2 = 1 to V2 = 7 with FX55 and calls it again.
+        let program = [
+            0x22, 0x10, 0x60, 0x62, 0x61, 0x07, 0xA2, 0x10, 0xF1, 0x55, 0x22, 0x10, 0x12, 0x0C, 0x00, 0x00, 0x62,
+            0x01, 0x00, 0xEE,
+        ];
+        let mut cpu = CPU::new();
+        cpu.set_engine(Engine::CachedBlocks);
+        cpu.load_program(&program).unwrap();
+        cpu.run_frame(2);
+        assert_eq!(cpu.v[2], 1);
+        assert!(cpu.cached_blocks() > 0);
+        cpu.run_frame(20);
+        assert_eq!(cpu.v[2], 7);
+        assert_eq!(cpu.pc, 0x20C);
+    }
+
+    #[test]
+    fn test_cached_blocks_see_wrapped_writes() {
+        // Calls 0x210, which sets V2 = 1, and loops.
+        let program = [0x22, 0x10, 0x12, 0x02, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0x62, 0x01, 0x00, 0xEE];
+        let mut cpu = CPU::new();
+        cpu.set_engine(Engine::CachedBlocks);
+        cpu.load_program(&program).unwrap();
+        cpu.run_frame(3);
+        assert_eq!(cpu.v[2], 1);
+
+        // FX55 past the end of memory rewrites V2 = 1 to V2 = 7.
+        cpu.i = 0x1210;
+        cpu.v[0] = 0x62;
+        cpu.v[1] = 0x07;
+        cpu.execute(0xF155);
+        cpu.pc = 0x200;
+        cpu.run_frame(3);
+        assert_eq!(cpu.v[2], 7);
+    }
+
+    #[test]
+    fn test_hooks_keep_cached_blocks() {
+        struct Nop;
+        impl MemoryHook for Nop {}
+
+        let mut cpu = CPU::new();
+        cpu.set_engine(Engine::CachedBlocks);
+        cpu.load_program(&[0x60, 0x01, 0x12, 0x00]).unwrap();
+        cpu.run_frame(2);
+        let cached = cpu.cached_blocks();
+        assert!(cached > 0);
+        cpu.add_hook(Nop);
+        assert!(cpu.hook_mut::<Nop>().is_some());
+        cpu.remove_hooks::<Nop>();
+        assert_eq!(cpu.cached_blocks(), cached);
+    }
+
     #[test]
     fn test_execute_fx65() {
         let mut cpu = CPU::new();
-        for idx in 0..16 as usize {
-            cpu.memory[1000 + idx] = idx as u8;
+        for idx in 0..16 {
+            cpu.memory.bytes_mut()[1000 + idx] = idx as u8;
         }
         cpu.i = 1000;
         cpu.execute(0xff65);
-        for i in 0..16 as usize {
-            assert_eq!(cpu.v[i], cpu.memory[1000 + i]);
+        for i in 0..16 {
+            assert_eq!(cpu.v[i], cpu.memory.bytes()[1000 + i]);
         }
         assert_eq!(cpu.pc, START_ADDR + 2);
     }
+
+    #[test]
+    fn test_save_and_load_state() {
+        let mut cpu = CPU::with_platform(Platform::SuperChip);
+        cpu.seed_random(3);
+        cpu.load_program(&[0x00, 0xFF, 0xA0, 0x00, 0xD0, 0x05, 0x22, 0x0A, 0x12, 0x08, 0xC0, 0xFF, 0xF0, 0x0A]).unwrap();
+        cpu.run_frame(7);
+        assert!(cpu.is_waiting_for_key());
+        let state = cpu.save_state().unwrap();
+        assert_eq!(state.memory.len(), 0x20E);
+        assert_eq!((state.width, state.height), HIRES_RESOLUTION);
+
+        let mut loaded = CPU::new();
+        loaded.load_state(&State::from_json(&state.to_json()).unwrap()).unwrap();
+        assert_eq!(loaded.save_state(), Some(state.clone()));
+        assert_eq!(loaded.platform(), Platform::SuperChip);
+        assert!(loaded.get_screen().get_pixel(0, 0));
+        assert!(loaded.is_waiting_for_key());
+        assert_eq!(loaded.rng.as_ref().map(Rng::state), cpu.rng.as_ref().map(Rng::state));
+
+        let bad = State { stack: vec![0; 17], ..state.clone() };
+        assert_eq!(loaded.load_state(&bad), Err("the stack is too deep".to_string()));
+        let bad = State { width: 1 << 40, ..state };
+        assert_eq!(loaded.load_state(&bad), Err("the screen has a bad size".to_string()));
+        assert_eq!(loaded.save_state().unwrap().width, 128);
+    }
+
+    #[test]
+    fn test_load_state_checks_the_screen_against_the_platform() {
+        let mut cpu = CPU::with_platform(Platform::Chip8X);
+        let state = cpu.save_state().unwrap();
+        let bad = State { colours: ColourAttributes::new(8, 1), ..state.clone() };
+        assert_eq!(cpu.load_state(&bad), Err("the colours don't fit the screen".to_string()));
+
+        // Hi-res needs the quirk on platforms without it.
+        let (width, height) = HIRES_RESOLUTION;
+        let hires = State { width, height, pixels: vec![0; width * height / 8], ..state };
+        let hires = State { colours: ColourAttributes::new(width, height), ..hires };
+        assert_eq!(cpu.load_state(&hires), Err("the screen has a bad size".to_string()));
+        let quirks = Quirks { hires: true, ..hires.quirks };
+        assert_eq!(cpu.load_state(&State { quirks, ..hires }), Ok(()));
+    }
+
+    #[test]
+    fn test_colours_follow_the_resolution() {
+        let mut cpu = CPU::with_platform(Platform::SuperChip);
+        cpu.execute(0x00FF);
+        let (screen, colours) = cpu.screen_and_colours();
+        assert!(colours.is_valid(screen.width(), screen.height()));
+        assert!(cpu.save_state().is_some_and(|state| CPU::new().load_state(&state).is_ok()));
+    }
 }
@@ -0,0 +1,267 @@
+use crate::platform::{Platform, Quirks};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+// Same layout as `programs.json` of the community CHIP-8 database, so entries
+// can be copied over from there.
+const PROGRAMS: &str = include_str!("../resources/database/programs.json");
+
+#[derive(Deserialize)]
+struct Program {
+    title: String,
+    #[serde(default)]
+    description: Option<String>,
+    #[serde(default)]
+    authors: Vec<String>,
+    #[serde(default)]
+    release: Option<String>,
+    roms: BTreeMap<String, Rom>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Rom {
+    #[serde(default)]
+    file: Option<String>,
+    #[serde(default)]
+    platforms: Vec<String>,
+    #[serde(default)]
+    quirky_platforms: BTreeMap<String, QuirkOverrides>,
+    #[serde(default)]
+    tickrate: Option<usize>,
+    #[serde(default)]
+    keys: BTreeMap<String, u8>,
+    #[serde(default)]
+    colors: Option<Colors>,
+}
+
+#[derive(Clone, Copy, Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct QuirkOverrides {
+    shift: Option<bool>,
+    memory_increment_by_x: Option<bool>,
+    memory_leave_i_unchanged: Option<bool>,
+    wrap: Option<bool>,
+    jump: Option<bool>,
+    vblank: Option<bool>,
+    logic: Option<bool>,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct Colors {
+    /// CSS colours, the first for unset pixels and the second for set ones.
+    #[serde(default)]
+    pub pixels: Vec<String>,
+    #[serde(default)]
+    pub buzzer: Option<String>,
+    #[serde(default)]
+    pub silence: Option<String>,
+}
+
+/// What the database knows about a ROM, resolved for this emulator.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RomInfo {
+    pub sha1: String,
+    pub title: String,
+    pub description: Option<String>,
+    pub authors: Vec<String>,
+    pub release: Option<String>,
+    pub file: Option<String>,
+    /// Identifier of the platform from the database, e.g. `originalChip8`.
+    pub platform_id: String,
+    pub platform: Platform,
+    pub quirks: Quirks,
+    /// Instructions per frame.
+    pub tick_rate: Option<usize>,
+    /// CHIP-8 keys for named controls such as `up` or `player1Down`.
+    pub keys: BTreeMap<String, u8>,
+    pub colors: Option<Colors>,
+}
+
+pub fn sha1_hex(rom: &[u8]) -> String {
+    sha1_smol::Sha1::from(rom).digest().to_string()
+}
+
+/// Looks a ROM up in the embedded database by its SHA-1.
+/// Returns `None` for unknown ROMs or ROMs for platforms we can't run.
+pub fn lookup(rom: &[u8]) -> Option<RomInfo> {
+    let sha1 = sha1_hex(rom);
+    let programs: Vec<Program> = serde_json::from_str(PROGRAMS).unwrap();
+    programs.into_iter().find_map(|program| {
+        let entry = program.roms.get(&sha1)?;
+        let (platform_id, platform, mut quirks) = entry
+            .platforms
+            .iter()
+            .find_map(|id| platform_quirks(id).map(|(platform, quirks)| (id.clone(), platform, quirks)))?;
+        if let Some(overrides) = entry.quirky_platforms.get(&platform_id) {
+            overrides.apply(&mut quirks);
+        }
+        Some(RomInfo {
+            sha1: sha1.clone(),
+            title: program.title.clone(),
+            description: program.description.clone(),
+            authors: program.authors.clone(),
+            release: program.release.clone(),
+            file: entry.file.clone(),
+            platform_id,
+            platform,
+            quirks,
+            tick_rate: entry.tickrate,
+            keys: entry.keys.clone(),
+            colors: entry.colors.clone(),
+        })
+    })
+}
+
+// Default quirks of the database's platform identifiers, following its `platforms.json`.
+fn platform_quirks(id: &str) -> Option<(Platform, Quirks)> {
+    let chip8 = Platform::Chip8.quirks();
+    let superchip = Platform::SuperChip.quirks();
+    match id {
+        "originalChip8" | "hybridVIP" => Some((Platform::Chip8, Quirks {
+            shift: false,
+            memory_leave_i_unchanged: false,
+            memory_increment_by_x: false,
+            wrap_sprites: false,
+            jump: false,
+            vblank: true,
+            logic: true,
+            ..chip8
+        })),
+        "modernChip8" => Some((Platform::Chip8, Quirks {
+            shift: false,
+            memory_leave_i_unchanged: false,
+            memory_increment_by_x: false,
+            wrap_sprites: false,
+            jump: false,
+            vblank: false,
+            logic: false,
+            key_wait_release: false,
+            ..chip8
+        })),
+        "chip48" => Some((Platform::Chip8, Quirks {
+            shift: true,
+            memory_leave_i_unchanged: false,
+            memory_increment_by_x: true,
+            wrap_sprites: false,
+            jump: true,
+            vblank: false,
+            logic: false,
+            key_wait_release: false,
+            ..chip8
+        })),
+        "superchip1" | "superchip" => Some((Platform::SuperChip, superchip)),
+        "chip8x" => platform_quirks("originalChip8").map(|(_, quirks)| (Platform::Chip8X, quirks)),
+        _ => None,
+    }
+}
+
+impl QuirkOverrides {
+    fn apply(&self, quirks: &mut Quirks) {
+        let fields = [
+            (self.shift, &mut quirks.shift),
+            (self.memory_increment_by_x, &mut quirks.memory_increment_by_x),
+            (self.memory_leave_i_unchanged, &mut quirks.memory_leave_i_unchanged),
+            (self.wrap, &mut quirks.wrap_sprites),
+            (self.jump, &mut quirks.jump),
+            (self.vblank, &mut quirks.vblank),
+            (self.logic, &mut quirks.logic),
+        ];
+        for (value, quirk) in fields {
+            if let Some(value) = value {
+                *quirk = value;
+            }
+        }
+    }
+}
+
+impl RomInfo {
+    pub fn to_json(&self) -> String {
+        serde_json::to_string(self).unwrap()
+    }
+
+    /// Host keys to bind for the named controls of the database.
+    pub fn key_bindings(&self) -> Vec<(&'static str, u8)> {
+        self.keys
+            .iter()
+            .filter_map(|(control, &key)| host_key(control).map(|host_key| (host_key, key)))
+            .collect()
+    }
+
+    /// `pixels` colours as `0xRRGGBBAA`, for unset and set pixels.
+    pub fn palette(&self) -> Option<(u32, u32)> {
+        let pixels = &self.colors.as_ref()?.pixels;
+        match (pixels.first(), pixels.get(1)) {
+            (Some(off), Some(on)) => Some((parse_color(off)?, parse_color(on)?)),
+            _ => None,
+        }
+    }
+}
+
+fn host_key(control: &str) -> Option<&'static str> {
+    match control {
+        "up" | "player1Up" => Some("ArrowUp"),
+        "down" | "player1Down" => Some("ArrowDown"),
+        "left" | "player1Left" => Some("ArrowLeft"),
+        "right" | "player1Right" => Some("ArrowRight"),
+        "a" | "player1A" => Some("Space"),
+        "b" | "player1B" => Some("ShiftLeft"),
+        _ => None,
+    }
+}
+
+// Accepts `#rgb` and `#rrggbb`.
+pub fn parse_color(color: &str) -> Option<u32> {
+    let hex = color.strip_prefix('#')?;
+    let rgb = match hex.len() {
+        3 => {
+            let short = u32::from_str_radix(hex, 16).ok()?;
+            let (r, g, b) = ((short >> 8) & 0xF, (short >> 4) & 0xF, short & 0xF);
+            ((r * 0x11) << 16) | ((g * 0x11) << 8) | (b * 0x11)
+        }
+        6 => u32::from_str_radix(hex, 16).ok()?,
+        _ => return None,
+    };
+    Some((rgb << 8) | 0xFF)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_embedded_database_parses() {
+        let programs: Vec<Program> = serde_json::from_str(PROGRAMS).unwrap();
+        assert!(!programs.is_empty());
+    }
+
+    #[test]
+    fn test_lookup() {
+        let brix = include_bytes!("../resources/games/BRIX");
+        let info = lookup(brix).unwrap();
+        assert_eq!(info.sha1, "f13766c14aeb02ad8d4d103cb5eadd282d20cddc");
+        assert_eq!(info.title, "Brix");
+        assert_eq!(info.platform, Platform::Chip8);
+        assert!(info.quirks.vblank);
+        assert_eq!(info.key_bindings(), vec![("ArrowLeft", 4), ("ArrowRight", 6)]);
+
+        assert_eq!(lookup(&[0x12, 0x00]), None);
+    }
+
+    #[test]
+    fn test_quirky_platform_overrides() {
+        let blinky = include_bytes!("../resources/games/BLINKY");
+        let info = lookup(blinky).unwrap();
+        assert!(info.quirks.shift);
+        assert!(info.quirks.memory_leave_i_unchanged);
+        assert!(!info.quirks.vblank);
+    }
+
+    #[test]
+    fn test_parse_color() {
+        assert_eq!(parse_color("#ff8000"), Some(0xFF8000FF));
+        assert_eq!(parse_color("#fff"), Some(0xFFFFFFFF));
+        assert_eq!(parse_color("red"), None);
+    }
+}
@@ -0,0 +1,360 @@
+//! Decodes opcodes into instructions, for tools that look at programs rather
+//! than run them. The CPU keeps its own `match`, which also depends on quirks.
+
+use crate::platform::Platform;
+use std::fmt;
+
+/// One instruction, with the registers as `x` and `y` and its immediate
+/// operand. Decoding depends on the platform but not on the quirks: SUPER-CHIP
+/// instructions are decoded everywhere, even where the CPU ignores them.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Instruction {
+    /// 00E0, and 0230 on the hi-res CHIP-8
+    Clear,
+    /// 00EE
+    Return,
+    /// 00CN (SUPER-CHIP)
+    ScrollDown(u8),
+    /// 00FB (SUPER-CHIP)
+    ScrollRight,
+    /// 00FC (SUPER-CHIP)
+    ScrollLeft,
+    /// 00FE (SUPER-CHIP)
+    LowRes,
+    /// 00FF (SUPER-CHIP)
+    HighRes,
+    /// 02A0 (CHIP-8X)
+    CycleBackground,
+    /// 0010 (MEGA-CHIP)
+    MegaOff,
+    /// 0011 (MEGA-CHIP)
+    MegaOn,
+    /// 01NN NNNN (MEGA-CHIP), the only 4 byte instruction
+    LoadLongI(u32),
+    /// 02NN (MEGA-CHIP)
+    LoadPalette(u8),
+    /// 03NN (MEGA-CHIP)
+    SpriteWidth(u8),
+    /// 04NN (MEGA-CHIP)
+    SpriteHeight(u8),
+    /// 05NN (MEGA-CHIP)
+    Alpha(u8),
+    /// 060N (MEGA-CHIP)
+    PlaySample(u8),
+    /// 0700 (MEGA-CHIP)
+    StopSample,
+    /// 080N (MEGA-CHIP)
+    Blend(u8),
+    /// 0NNN, a machine code routine, which the CPU ignores
+    Sys(u16),
+    /// 1NNN
+    Jump(u16),
+    /// 2NNN
+    Call(u16),
+    /// 3XNN
+    SkipEqImm(u8, u8),
+    /// 4XNN
+    SkipNeImm(u8, u8),
+    /// 5XY0
+    SkipEq(u8, u8),
+    /// 5XY1 (CHIP-8X)
+    AddNibbles(u8, u8),
+    /// 6XNN
+    LoadImm(u8, u8),
+    /// 7XNN
+    AddImm(u8, u8),
+    /// 8XY0
+    Move(u8, u8),
+    /// 8XY1
+    Or(u8, u8),
+    /// 8XY2
+    And(u8, u8),
+    /// 8XY3
+    Xor(u8, u8),
+    /// 8XY4
+    Add(u8, u8),
+    /// 8XY5
+    Sub(u8, u8),
+    /// 8XY6
+    ShiftRight(u8, u8),
+    /// 8XY7
+    SubReverse(u8, u8),
+    /// 8XYE
+    ShiftLeft(u8, u8),
+    /// 9XY0
+    SkipNe(u8, u8),
+    /// ANNN
+    LoadI(u16),
+    /// BNNN, which jumps to NNN plus V0, or plus VX with the jump quirk
+    JumpOffset(u16),
+    /// BXY0 (CHIP-8X)
+    ColourBlocks(u8, u8),
+    /// BXYN (CHIP-8X)
+    ColourRows(u8, u8, u8),
+    /// CXNN
+    Random(u8, u8),
+    /// DXYN
+    Draw(u8, u8, u8),
+    /// EX9E
+    SkipKey(u8),
+    /// EXA1
+    SkipNoKey(u8),
+    /// EXF2 (CHIP-8X)
+    SkipKey2(u8),
+    /// EXF5 (CHIP-8X)
+    SkipNoKey2(u8),
+    /// FX07
+    GetDelay(u8),
+    /// FX0A
+    WaitKey(u8),
+    /// FX15
+    SetDelay(u8),
+    /// FX18
+    SetSound(u8),
+    /// FX1E
+    AddI(u8),
+    /// FX29
+    Font(u8),
+    /// FX33
+    Bcd(u8),
+    /// FX55
+    Store(u8),
+    /// FX65
+    Load(u8),
+    /// FXF8 (CHIP-8X)
+    Output(u8),
+    /// FXFB (CHIP-8X)
+    Input(u8),
+    Unknown(u16),
+}
+
+use Instruction::*;
+
+impl Instruction {
+    /// Size in bytes.
+    pub fn size(&self) -> usize {
+        match self {
+            LoadLongI(_) => 4,
+            _ => 2,
+        }
+    }
+
+    /// Whether it skips the next instruction on some condition.
+    pub fn is_skip(&self) -> bool {
+        matches!(
+            self,
+            SkipEqImm(..) | SkipNeImm(..) | SkipEq(..) | SkipNe(..) | SkipKey(_) | SkipNoKey(_) | SkipKey2(_) | SkipNoKey2(_)
+        )
+    }
+}
+
+/// Decodes the instruction at `addr`, reading the second word of MEGA-CHIP's
+/// 01NN. Bytes past the end of `memory` read as 0.
+pub fn decode_at(memory: &[u8], addr: usize, platform: Platform) -> Instruction {
+    let word = |addr: usize| {
+        let byte = |addr: usize| memory.get(addr).copied().unwrap_or(0) as u16;
+        byte(addr) << 8 | byte(addr + 1)
+    };
+    match decode(word(addr), platform) {
+        LoadLongI(high) => LoadLongI(high | word(addr + 2) as u32),
+        instruction => instruction,
+    }
+}
+
+/// Decodes a single opcode. MEGA-CHIP's 01NN comes out with only the high
+/// byte of the address, use `decode_at` to get all of it.
+pub fn decode(opcode: u16, platform: Platform) -> Instruction {
+    let x = ((opcode & 0x0F00) >> 8) as u8;
+    let y = ((opcode & 0x00F0) >> 4) as u8;
+    let n = (opcode & 0x000F) as u8;
+    let nn = (opcode & 0x00FF) as u8;
+    let nnn = opcode & 0x0FFF;
+    let mega = platform == Platform::MegaChip;
+    let chip8x = platform == Platform::Chip8X;
+
+    match opcode >> 12 {
+        0x0 => match opcode {
+            0x00E0 => Clear,
+            0x00EE => Return,
+            0x00FB => ScrollRight,
+            0x00FC => ScrollLeft,
+            0x00FE => LowRes,
+            0x00FF => HighRes,
+            0x0230 if platform == Platform::HiresChip8 => Clear,
+            0x02A0 if chip8x => CycleBackground,
+            0x0010 if mega => MegaOff,
+            0x0011 if mega => MegaOn,
+            0x0700 if mega => StopSample,
+            _ if opcode & 0xFFF0 == 0x00C0 => ScrollDown(n),
+            _ if mega => match x {
+                1 => LoadLongI((nn as u32) << 16),
+                2 => LoadPalette(nn),
+                3 => SpriteWidth(nn),
+                4 => SpriteHeight(nn),
+                5 => Alpha(nn),
+                6 if y == 0 => PlaySample(n),
+                8 if y == 0 => Blend(n),
+                _ => Sys(nnn),
+            },
+            _ => Sys(nnn),
+        },
+        0x1 => Jump(nnn),
+        0x2 => Call(nnn),
+        0x3 => SkipEqImm(x, nn),
+        0x4 => SkipNeImm(x, nn),
+        0x5 => match n {
+            0 => SkipEq(x, y),
+            1 if chip8x => AddNibbles(x, y),
+            _ => Unknown(opcode),
+        },
+        0x6 => LoadImm(x, nn),
+        0x7 => AddImm(x, nn),
+        0x8 => match n {
+            0x0 => Move(x, y),
+            0x1 => Or(x, y),
+            0x2 => And(x, y),
+            0x3 => Xor(x, y),
+            0x4 => Add(x, y),
+            0x5 => Sub(x, y),
+            0x6 => ShiftRight(x, y),
+            0x7 => SubReverse(x, y),
+            0xE => ShiftLeft(x, y),
+            _ => Unknown(opcode),
+        },
+        0x9 if n == 0 => SkipNe(x, y),
+        0xA => LoadI(nnn),
+        0xB if chip8x && n == 0 => ColourBlocks(x, y),
+        0xB if chip8x => ColourRows(x, y, n),
+        0xB => JumpOffset(nnn),
+        0xC => Random(x, nn),
+        0xD => Draw(x, y, n),
+        0xE => match nn {
+            0x9E => SkipKey(x),
+            0xA1 => SkipNoKey(x),
+            0xF2 if chip8x => SkipKey2(x),
+            0xF5 if chip8x => SkipNoKey2(x),
+            _ => Unknown(opcode),
+        },
+        0xF => match nn {
+            0x07 => GetDelay(x),
+            0x0A => WaitKey(x),
+            0x15 => SetDelay(x),
+            0x18 => SetSound(x),
+            0x1E => AddI(x),
+            0x29 => Font(x),
+            0x33 => Bcd(x),
+            0x55 => Store(x),
+            0x65 => Load(x),
+            0xF8 if chip8x => Output(x),
+            0xFB if chip8x => Input(x),
+            _ => Unknown(opcode),
+        },
+        _ => Unknown(opcode),
+    }
+}
+
+/// Cowgod's mnemonics, with those of the MEGA-CHIP documentation for its
+/// instructions.
+impl fmt::Display for Instruction {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Clear => write!(f, "CLS"),
+            Return => write!(f, "RET"),
+            ScrollDown(n) => write!(f, "SCD {}", n),
+            ScrollRight => write!(f, "SCR"),
+            ScrollLeft => write!(f, "SCL"),
+            LowRes => write!(f, "LOW"),
+            HighRes => write!(f, "HIGH"),
+            CycleBackground => write!(f, "BGC"),
+            MegaOff => write!(f, "MEGAOFF"),
+            MegaOn => write!(f, "MEGAON"),
+            LoadLongI(addr) => write!(f, "LDHI I, {:#08X}", addr),
+            LoadPalette(nn) => write!(f, "LDPAL {}", nn),
+            SpriteWidth(nn) => write!(f, "SPRW {}", nn),
+            SpriteHeight(nn) => write!(f, "SPRH {}", nn),
+            Alpha(nn) => write!(f, "ALPHA {}", nn),
+            PlaySample(n) => write!(f, "DIGISND {}", n),
+            StopSample => write!(f, "STOPSND"),
+            Blend(n) => write!(f, "BMODE {}", n),
+            Sys(nnn) => write!(f, "SYS {:#05X}", nnn),
+            Jump(nnn) => write!(f, "JP {:#05X}", nnn),
+            Call(nnn) => write!(f, "CALL {:#05X}", nnn),
+            SkipEqImm(x, nn) => write!(f, "SE V{:X}, {:#04X}", x, nn),
+            SkipNeImm(x, nn) => write!(f, "SNE V{:X}, {:#04X}", x, nn),
+            SkipEq(x, y) => write!(f, "SE V{:X}, V{:X}", x, y),
+            AddNibbles(x, y) => write!(f, "ADDN V{:X}, V{:X}", x, y),
+            LoadImm(x, nn) => write!(f, "LD V{:X}, {:#04X}", x, nn),
+            AddImm(x, nn) => write!(f, "ADD V{:X}, {:#04X}", x, nn),
+            Move(x, y) => write!(f, "LD V{:X}, V{:X}", x, y),
+            Or(x, y) => write!(f, "OR V{:X}, V{:X}", x, y),
+            And(x, y) => write!(f, "AND V{:X}, V{:X}", x, y),
+            Xor(x, y) => write!(f, "XOR V{:X}, V{:X}", x, y),
+            Add(x, y) => write!(f, "ADD V{:X}, V{:X}", x, y),
+            Sub(x, y) => write!(f, "SUB V{:X}, V{:X}", x, y),
+            ShiftRight(x, y) => write!(f, "SHR V{:X}, V{:X}", x, y),
+            SubReverse(x, y) => write!(f, "SUBN V{:X}, V{:X}", x, y),
+            ShiftLeft(x, y) => write!(f, "SHL V{:X}, V{:X}", x, y),
+            SkipNe(x, y) => write!(f, "SNE V{:X}, V{:X}", x, y),
+            LoadI(nnn) => write!(f, "LD I, {:#05X}", nnn),
+            JumpOffset(nnn) => write!(f, "JP V0, {:#05X}", nnn),
+            ColourBlocks(x, y) => write!(f, "COL V{:X}, V{:X}", x, y),
+            ColourRows(x, y, n) => write!(f, "COL V{:X}, V{:X}, {}", x, y, n),
+            Random(x, nn) => write!(f, "RND V{:X}, {:#04X}", x, nn),
+            Draw(x, y, n) => write!(f, "DRW V{:X}, V{:X}, {}", x, y, n),
+            SkipKey(x) => write!(f, "SKP V{:X}", x),
+            SkipNoKey(x) => write!(f, "SKNP V{:X}", x),
+            SkipKey2(x) => write!(f, "SKP2 V{:X}", x),
+            SkipNoKey2(x) => write!(f, "SKNP2 V{:X}", x),
+            GetDelay(x) => write!(f, "LD V{:X}, DT", x),
+            WaitKey(x) => write!(f, "LD V{:X}, K", x),
+            SetDelay(x) => write!(f, "LD DT, V{:X}", x),
+            SetSound(x) => write!(f, "LD ST, V{:X}", x),
+            AddI(x) => write!(f, "ADD I, V{:X}", x),
+            Font(x) => write!(f, "LD F, V{:X}", x),
+            Bcd(x) => write!(f, "LD B, V{:X}", x),
+            Store(x) => write!(f, "LD [I], V{:X}", x),
+            Load(x) => write!(f, "LD V{:X}, [I]", x),
+            Output(x) => write!(f, "OUT V{:X}", x),
+            Input(x) => write!(f, "IN V{:X}", x),
+            Unknown(opcode) => write!(f, "DW {:#06X}", opcode),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_decode() {
+        assert_eq!(decode(0x00E0, Platform::Chip8), Clear);
+        assert_eq!(decode(0x0230, Platform::Chip8), Sys(0x230));
+        assert_eq!(decode(0x0230, Platform::HiresChip8), Clear);
+        assert_eq!(decode(0x8AB4, Platform::Chip8), Add(0xA, 0xB));
+        assert_eq!(decode(0x8AB9, Platform::Chip8), Unknown(0x8AB9));
+        assert_eq!(decode(0xB123, Platform::Chip8), JumpOffset(0x123));
+        assert_eq!(decode(0xB123, Platform::Chip8X), ColourRows(1, 2, 3));
+        assert_eq!(decode(0x5121, Platform::Chip8), Unknown(0x5121));
+        assert!(decode(0xE19E, Platform::Chip8).is_skip());
+        assert!(!decode(0xE1F2, Platform::Chip8).is_skip());
+    }
+
+    #[test]
+    fn test_decode_at_megachip() {
+        let memory = [0x01, 0x02, 0x34, 0x56];
+        assert_eq!(decode_at(&memory, 0, Platform::MegaChip), LoadLongI(0x023456));
+        assert_eq!(decode_at(&memory, 0, Platform::MegaChip).size(), 4);
+        assert_eq!(decode_at(&memory, 0, Platform::Chip8), Sys(0x102));
+        assert_eq!(decode_at(&memory, 3, Platform::Chip8), SkipEq(6, 0));
+    }
+
+    #[test]
+    fn test_display() {
+        assert_eq!(decode(0x00C4, Platform::SuperChip).to_string(), "SCD 4");
+        assert_eq!(decode(0x1200, Platform::Chip8).to_string(), "JP 0x200");
+        assert_eq!(decode(0x3A0F, Platform::Chip8).to_string(), "SE VA, 0x0F");
+        assert_eq!(decode(0xD125, Platform::Chip8).to_string(), "DRW V1, V2, 5");
+        assert_eq!(decode(0xF355, Platform::Chip8).to_string(), "LD [I], V3");
+        assert_eq!(decode_at(&[0x01, 0x02, 0x34, 0x56], 0, Platform::MegaChip).to_string(), "LDHI I, 0x023456");
+    }
+}
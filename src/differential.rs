@@ -0,0 +1,372 @@
+//! Runs a program on two engines, or with two sets of quirks, with the same
+//! input and random numbers, and finds where they first disagree.
+
+use crate::bus::Bus;
+use crate::cpu::CPU;
+use crate::decoder;
+use crate::engine::Engine;
+use crate::platform::{Platform, Quirks};
+use crate::utils::Rng;
+use std::fmt::{self, Write};
+
+// Bytes of memory listed in a divergence before the rest are counted.
+const MAX_MEMORY_DIFFERENCES: usize = 8;
+// Instructions disassembled on each side of PC in a repro dump.
+const LISTING_CONTEXT: u16 = 4;
+
+/// One way of running programs.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Config {
+    pub engine: Engine,
+    pub quirks: Quirks,
+}
+
+impl Config {
+    /// The interpreter with the platform's quirks, to compare others against.
+    pub fn reference(platform: Platform) -> Config {
+        Config { engine: Engine::Interpreter, quirks: platform.quirks() }
+    }
+}
+
+/// A key pressed or released before `frame` runs.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct KeyEvent {
+    pub frame: usize,
+    pub key: u8,
+    pub pressed: bool,
+}
+
+/// Presses and releases of random keys, about one every 4 frames.
+pub fn random_input(seed: u64, frames: usize) -> Vec<KeyEvent> {
+    let mut rng = Rng::new(seed);
+    (0..frames)
+        .filter_map(|frame| {
+            let byte = rng.next_byte();
+            (byte < 0x40).then_some(KeyEvent { frame, key: byte & 0xF, pressed: byte & 0x10 == 0 })
+        })
+        .collect()
+}
+
+/// A program with its input, to run under two configs.
+#[derive(Clone, Debug)]
+pub struct Differential {
+    pub program: Vec<u8>,
+    pub platform: Platform,
+    /// Seeds CXNN on both sides.
+    pub seed: u64,
+    pub cycles: usize,
+    pub frames: usize,
+    pub input: Vec<KeyEvent>,
+}
+
+/// Where two configs first disagree, with what's needed to reproduce it.
+#[derive(Clone, Debug)]
+pub struct Divergence {
+    pub frame: usize,
+    /// How many instructions into `frame` the state first differs, when
+    /// running the frame one instruction at a time shows it.
+    pub instruction: Option<usize>,
+    pub differences: Vec<String>,
+    /// The smallest input found that still diverges.
+    pub input: Vec<KeyEvent>,
+    pub configs: (Config, Config),
+    pub platform: Platform,
+    pub seed: u64,
+    pub cycles: usize,
+    /// PC and the disassembly around it on the first side.
+    pub pc: u16,
+    pub listing: String,
+}
+
+impl Differential {
+    pub fn new(program: &[u8], platform: Platform) -> Differential {
+        Differential { program: program.to_vec(), platform, seed: 0, cycles: 8, frames: 600, input: Vec::new() }
+    }
+
+    /// Runs both configs frame by frame until their state differs. The
+    /// divergence is narrowed down to an instruction and the input is
+    /// shrunk to the events it needs.
+    pub fn run(&self, a: Config, b: Config) -> Result<(), Box<Divergence>> {
+        let frame = match self.first_divergent_frame(a, b) {
+            Some(frame) => frame,
+            None => return Ok(()),
+        };
+        let shrunk = self.shrink(a, b);
+        let frame = shrunk.first_divergent_frame(a, b).unwrap_or(frame);
+        Err(Box::new(shrunk.locate(a, b, frame)))
+    }
+
+    fn start(&self, config: Config) -> CPU {
+        let mut cpu = CPU::with_platform(self.platform);
+        cpu.set_quirks(config.quirks);
+        cpu.set_engine(config.engine);
+        cpu.seed_random(self.seed);
+        // A program too large for memory runs as much of it as fits on both sides.
+        let _ = cpu.load_program(&self.program);
+        cpu
+    }
+
+    fn press_keys(&self, cpu: &mut CPU, frame: usize) {
+        for event in self.input.iter().filter(|event| event.frame == frame) {
+            if event.pressed {
+                cpu.get_keyboard().key_down(event.key);
+            } else {
+                cpu.get_keyboard().key_up(event.key);
+            }
+        }
+    }
+
+    fn first_divergent_frame(&self, a: Config, b: Config) -> Option<usize> {
+        let mut cpus = [self.start(a), self.start(b)];
+        (0..self.frames).find(|&frame| {
+            for cpu in cpus.iter_mut() {
+                self.press_keys(cpu, frame);
+                cpu.run_frame(self.cycles);
+            }
+            let [a, b] = &mut cpus;
+            !differences(a, b).is_empty()
+        })
+    }
+
+    // Drops chunks of input, halving their size, as long as the configs
+    // still diverge without them.
+    fn shrink(&self, a: Config, b: Config) -> Differential {
+        let mut shrunk = self.clone();
+        if let Some(frame) = shrunk.first_divergent_frame(a, b) {
+            shrunk.input.retain(|event| event.frame <= frame);
+        }
+        let mut chunk = shrunk.input.len() / 2;
+        while chunk > 0 {
+            let mut start = 0;
+            while start < shrunk.input.len() {
+                let mut candidate = shrunk.clone();
+                candidate.input.drain(start..(start + chunk).min(shrunk.input.len()));
+                if candidate.first_divergent_frame(a, b).is_some() {
+                    shrunk = candidate;
+                } else {
+                    start += chunk;
+                }
+            }
+            chunk /= 2;
+        }
+        shrunk
+    }
+
+    // Runs up to `frame`, then through it one instruction at a time.
+    fn locate(&self, a: Config, b: Config, frame: usize) -> Divergence {
+        let mut cpus = [self.start(a), self.start(b)];
+        for frame in 0..frame {
+            for cpu in cpus.iter_mut() {
+                self.press_keys(cpu, frame);
+                cpu.run_frame(self.cycles);
+            }
+        }
+        for cpu in cpus.iter_mut() {
+            self.press_keys(cpu, frame);
+        }
+
+        let mut ended = [false; 2];
+        let mut instruction = None;
+        for cycle in 1..=self.cycles {
+            for (cpu, ended) in cpus.iter_mut().zip(ended.iter_mut()) {
+                if !*ended {
+                    *ended = cpu.run_cycles(1);
+                }
+            }
+            let [a, b] = &mut cpus;
+            if !differences(a, b).is_empty() {
+                instruction = Some(cycle);
+                break;
+            }
+        }
+        if instruction.is_none() {
+            for cpu in cpus.iter_mut() {
+                cpu.end_frame();
+            }
+        }
+
+        let [first, second] = &mut cpus;
+        Divergence {
+            frame,
+            instruction,
+            differences: differences(first, second),
+            input: self.input.clone(),
+            configs: (a, b),
+            platform: self.platform,
+            seed: self.seed,
+            cycles: self.cycles,
+            pc: first.pc(),
+            listing: listing(first),
+        }
+    }
+}
+
+/// What differs between the state of two CPUs, one line per register and
+/// memory byte, and a summary of the screens.
+pub fn differences(a: &CPU, b: &CPU) -> Vec<String> {
+    let mut differences = Vec::new();
+    let mut compare = |name: &str, a: String, b: String| {
+        if a != b {
+            differences.push(format!("{}: {} != {}", name, a, b));
+        }
+    };
+    compare("PC", format!("{:#06X}", a.pc()), format!("{:#06X}", b.pc()));
+    compare("I", format!("{:#06X}", a.i()), format!("{:#06X}", b.i()));
+    for register in 0..16 {
+        let (va, vb) = (a.registers()[register], b.registers()[register]);
+        compare(&format!("V{:X}", register), format!("{:#04X}", va), format!("{:#04X}", vb));
+    }
+    compare("stack", format!("{:X?}", a.stack()), format!("{:X?}", b.stack()));
+    compare("timers", format!("{:?}", a.timers()), format!("{:?}", b.timers()));
+    compare("waiting for key", a.is_waiting_for_key().to_string(), b.is_waiting_for_key().to_string());
+
+    let (memory_a, memory_b) = (a.bus().bytes(), b.bus().bytes());
+    let differing: Vec<usize> = if memory_a == memory_b {
+        Vec::new()
+    } else {
+        (0..memory_a.len().max(memory_b.len())).filter(|&addr| memory_a.get(addr) != memory_b.get(addr)).collect()
+    };
+    for &addr in differing.iter().take(MAX_MEMORY_DIFFERENCES) {
+        let byte = |memory: &[u8]| memory.get(addr).map_or("-".to_string(), |byte| format!("{:#04X}", byte));
+        compare(&format!("memory {:#06X}", addr), byte(memory_a), byte(memory_b));
+    }
+    if differing.len() > MAX_MEMORY_DIFFERENCES {
+        let more = differing.len() - MAX_MEMORY_DIFFERENCES;
+        differences.push(format!("memory: {} more bytes differ", more));
+    }
+
+    let (screen_a, screen_b) = (a.screen(), b.screen());
+    let size = |screen: &crate::screen::Screen| format!("{}x{}", screen.width(), screen.height());
+    if size(screen_a) != size(screen_b) {
+        differences.push(format!("screen: {} != {}", size(screen_a), size(screen_b)));
+    } else {
+        let width = screen_a.width();
+        let differing: Vec<usize> = (0..screen_a.pixels().len())
+            .filter(|&pixel| screen_a.pixels()[pixel] != screen_b.pixels()[pixel])
+            .collect();
+        if let Some(&first) = differing.first() {
+            let (x, y) = (first % width, first / width);
+            differences.push(format!("screen: {} pixels differ, the first at ({}, {})", differing.len(), x, y));
+        }
+    }
+    differences
+}
+
+// The instructions around PC.
+fn listing(cpu: &CPU) -> String {
+    let memory = cpu.bus().bytes();
+    let start = cpu.pc().saturating_sub(LISTING_CONTEXT * 2) as usize;
+    let end = (cpu.pc() as usize + LISTING_CONTEXT as usize * 2 + 2).min(memory.len());
+    let mut listing = String::new();
+    for addr in (start..end).step_by(2) {
+        let marker = if addr == cpu.pc() as usize { ">" } else { " " };
+        let instruction = decoder::decode_at(memory, addr, cpu.platform());
+        let opcode = (memory[addr] as u16) << 8 | memory.get(addr + 1).copied().unwrap_or(0) as u16;
+        writeln!(listing, "{} {:#06X}  {:04X}  {}", marker, addr, opcode, instruction).unwrap();
+    }
+    listing
+}
+
+impl fmt::Display for Divergence {
+    /// A repro dump: where it diverged, how, and how to get there again.
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "diverged in frame {}", self.frame)?;
+        match self.instruction {
+            Some(instruction) => writeln!(f, " at instruction {}", instruction)?,
+            None => writeln!(f, " at its end")?,
+        }
+        for difference in self.differences.iter() {
+            writeln!(f, "  {}", difference)?;
+        }
+        writeln!(f, "first:  {:?}", self.configs.0)?;
+        writeln!(f, "second: {:?}", self.configs.1)?;
+        writeln!(f, "platform {:?}, seed {}, {} cycles per frame", self.platform, self.seed, self.cycles)?;
+        if self.input.is_empty() {
+            writeln!(f, "no input")?;
+        }
+        for event in self.input.iter() {
+            let action = if event.pressed { "press" } else { "release" };
+            writeln!(f, "frame {}: {} {:X}", event.frame, action, event.key)?;
+        }
+        writeln!(f, "at PC {:#06X} on the first side:", self.pc)?;
+        write!(f, "{}", self.listing)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    // 0x200: V0 = 3; V0 >>= V1 (8016); jump 0x204
+    const SHIFT: [u8; 6] = [0x60, 0x03, 0x80, 0x16, 0x12, 0x04];
+
+    // Waits for key 5 to be held, then shifts as above.
+    const SHIFT_ON_KEY: [u8; 10] = [0x61, 0x05, 0xE1, 0x9E, 0x12, 0x02, 0x80, 0x16, 0x12, 0x08];
+
+    fn quirks(shift: bool) -> Config {
+        let reference = Config::reference(Platform::Chip8);
+        Config { quirks: Quirks { shift, ..reference.quirks }, ..reference }
+    }
+
+    #[test]
+    fn test_same_config_agrees() {
+        let differential = Differential::new(&SHIFT, Platform::Chip8);
+        let reference = Config::reference(Platform::Chip8);
+        assert!(differential.run(reference, reference).is_ok());
+    }
+
+    #[test]
+    fn test_divergence() {
+        let differential = Differential::new(&SHIFT, Platform::Chip8);
+        let divergence = differential.run(quirks(false), quirks(true)).unwrap_err();
+        assert_eq!(divergence.frame, 0);
+        assert_eq!(divergence.instruction, Some(2));
+        assert_eq!(divergence.differences, vec!["V0: 0x00 != 0x01", "VF: 0x00 != 0x01"]);
+        assert_eq!(divergence.pc, 0x204);
+
+        let dump = divergence.to_string();
+        assert!(dump.starts_with("diverged in frame 0 at instruction 2\n  V0: 0x00 != 0x01\n"), "{}", dump);
+        assert!(dump.contains("no input\n"), "{}", dump);
+        assert!(dump.contains("> 0x0204  1204  JP 0x204\n"), "{}", dump);
+    }
+
+    #[test]
+    fn test_shrinks_input() {
+        let mut differential = Differential::new(&SHIFT_ON_KEY, Platform::Chip8);
+        differential.input = random_input(3, differential.frames);
+        differential.input.retain(|event| event.key != 5);
+        differential.input.push(KeyEvent { frame: 300, key: 5, pressed: true });
+        differential.input.push(KeyEvent { frame: 400, key: 5, pressed: false });
+
+        let divergence = differential.run(quirks(false), quirks(true)).unwrap_err();
+        assert_eq!(divergence.frame, 300);
+        assert_eq!(divergence.input, vec![KeyEvent { frame: 300, key: 5, pressed: true }]);
+    }
+
+    #[test]
+    fn test_random_input() {
+        let input = random_input(1, 1000);
+        assert_eq!(input, random_input(1, 1000));
+        assert!(input.len() > 100 && input.len() < 500, "{}", input.len());
+        assert!(input.windows(2).all(|events| events[0].frame < events[1].frame));
+    }
+
+    // Every bundled game, with the cached blocks against the interpreter.
+    #[test]
+    fn test_engines_agree_on_games() {
+        let games = fs::read_dir(concat!(env!("CARGO_MANIFEST_DIR"), "/resources/games")).unwrap();
+        for (seed, game) in games.enumerate() {
+            let path = game.unwrap().path();
+            let mut differential = Differential::new(&fs::read(&path).unwrap(), Platform::Chip8);
+            differential.seed = seed as u64;
+            differential.frames = 3000;
+            differential.input = random_input(seed as u64, differential.frames);
+            let reference = Config::reference(Platform::Chip8);
+            let cached = Config { engine: Engine::CachedBlocks, ..reference };
+            if let Err(divergence) = differential.run(reference, cached) {
+                panic!("{:?}\n{}", path, divergence);
+            }
+        }
+    }
+}
@@ -0,0 +1,51 @@
+//! What the CPU needs from a display, so other implementations than `Screen`
+//! can be plugged in, e.g. to instrument drawing in tests or to render
+//! straight into an RGBA buffer.
+
+/// Outcome of drawing a sprite.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct DrawResult {
+    /// Rows in which a set pixel was turned off.
+    pub collided_rows: usize,
+    /// Rows not drawn because they fell past the bottom edge.
+    pub clipped_rows: usize,
+}
+
+impl DrawResult {
+    pub fn collided(&self) -> bool {
+        self.collided_rows > 0
+    }
+}
+
+pub trait Display {
+    fn width(&self) -> usize;
+
+    fn height(&self) -> usize;
+
+    fn clear(&mut self);
+
+    /// XORs a sprite onto the display at `x`, `y`, which wrap around the edges.
+    /// Each row is `width` pixels from the most significant bit. Pixels past
+    /// the right or bottom edge wrap around when `wrap` is set and are clipped
+    /// otherwise.
+    fn draw_sprite(&mut self, x: usize, y: usize, rows: &[u16], width: usize, wrap: bool) -> DrawResult;
+
+    /// Turns one pixel on without toggling it, returning whether it was on
+    /// already. MEGA-CHIP sprites are drawn this way.
+    fn plot(&mut self, row: usize, col: usize) -> bool;
+
+    /// Closes a series of `plot` calls. `draw_sprite` and `clear` close themselves.
+    fn end_draw(&mut self) {}
+
+    /// Moves the content by `dx` pixels to the right and `dy` pixels down,
+    /// negative values go left or up. Pixels moved in from the edges are off.
+    fn scroll(&mut self, dx: isize, dy: isize);
+
+    /// Switches to another resolution, clearing the display.
+    fn set_resolution(&mut self, width: usize, height: usize);
+
+    /// Selects the bit planes that drawing, clearing and scrolling act on, as
+    /// XO-CHIP's FN01 does. Displays with a single plane only use bit 0, and
+    /// those that ignore it can keep this no-op.
+    fn set_plane_mask(&mut self, _mask: u8) {}
+}
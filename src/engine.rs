@@ -0,0 +1,182 @@
+//! Decoded basic blocks cached by address, for running the CPU without
+//! fetching and decoding every instruction again.
+
+use crate::decoder::{self, Instruction};
+use crate::platform::Platform;
+use std::rc::Rc;
+use wasm_bindgen::prelude::*;
+
+/// How the CPU runs a frame.
+#[wasm_bindgen]
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum Engine {
+    /// Fetches and decodes each instruction as it runs, the reference.
+    #[default]
+    Interpreter,
+    /// Runs basic blocks decoded once and kept until their code is written to.
+    /// Memory hooks don't see the fetches of cached instructions.
+    CachedBlocks,
+}
+
+/// Straight-line instructions followed by one that may jump, skip, wait,
+/// draw or write memory.
+pub struct Block {
+    pub start: u16,
+    pub ops: Vec<(u16, Instruction)>,
+}
+
+impl Block {
+    /// Decodes the block starting at `start`. It stops at the end of memory,
+    /// so it can be empty.
+    pub fn decode(memory: &[u8], start: u16, platform: Platform) -> Block {
+        let mut ops = Vec::new();
+        let mut addr = start as usize;
+        while addr + 1 < memory.len() {
+            let opcode = (memory[addr] as u16) << 8 | memory[addr + 1] as u16;
+            let instruction = decoder::decode(opcode, platform);
+            ops.push((opcode, instruction));
+            if !is_straight_line(instruction) || addr + 2 > u16::MAX as usize {
+                break;
+            }
+            addr += 2;
+        }
+        Block { start, ops }
+    }
+
+    /// One past the last byte of the block.
+    pub fn end(&self) -> usize {
+        self.start as usize + self.ops.len() * 2
+    }
+}
+
+// Whether an instruction always goes on to the next one and leaves memory
+// alone.
+fn is_straight_line(instruction: Instruction) -> bool {
+    use Instruction::*;
+    matches!(
+        instruction,
+        LoadImm(..)
+            | AddImm(..)
+            | Move(..)
+            | Or(..)
+            | And(..)
+            | Xor(..)
+            | Add(..)
+            | Sub(..)
+            | ShiftRight(..)
+            | SubReverse(..)
+            | ShiftLeft(..)
+            | LoadI(_)
+            | Random(..)
+            | GetDelay(_)
+            | SetDelay(_)
+            | SetSound(_)
+            | AddI(_)
+            | Font(_)
+            | Load(_)
+    )
+}
+
+#[derive(Default)]
+pub struct BlockCache {
+    // Indexed by address, grown to the size of memory.
+    blocks: Vec<Option<Rc<Block>>>,
+    // The starts of the cached blocks covering each byte of memory, so a
+    // write finds the blocks to drop without looking at the others.
+    covering: Vec<Vec<u16>>,
+}
+
+impl BlockCache {
+    pub fn new() -> BlockCache {
+        Default::default()
+    }
+
+    /// The block at `pc`, decoded from `memory` unless it's cached.
+    pub fn get(&mut self, pc: u16, memory: &[u8], platform: Platform) -> Rc<Block> {
+        if let Some(Some(block)) = self.blocks.get(pc as usize) {
+            return Rc::clone(block);
+        }
+        let block = Rc::new(Block::decode(memory, pc, platform));
+        let size = memory.len().min(u16::MAX as usize + 1);
+        if self.blocks.len() < size {
+            self.blocks.resize(size, None);
+            self.covering.resize(size, Vec::new());
+        }
+        // PC can point past the end of memory, where there's nothing to cache.
+        if let Some(entry) = self.blocks.get_mut(pc as usize) {
+            *entry = Some(Rc::clone(&block));
+            for starts in &mut self.covering[block.start as usize..block.end()] {
+                starts.push(pc);
+            }
+        }
+        block
+    }
+
+    /// Drops the blocks containing `addr`, after it has been written to.
+    pub fn invalidate(&mut self, addr: usize) {
+        let starts = match self.covering.get_mut(addr) {
+            Some(starts) if !starts.is_empty() => std::mem::take(starts),
+            _ => return,
+        };
+        for start in starts {
+            if let Some(block) = self.blocks[start as usize].take() {
+                for starts in &mut self.covering[block.start as usize..block.end()] {
+                    starts.retain(|&other| other != start);
+                }
+            }
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.blocks.clear();
+        self.covering.clear();
+    }
+
+    /// How many blocks are cached.
+    pub fn count(&self) -> usize {
+        self.blocks.iter().flatten().count()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // 0x200: V0 = 1; V1 += 2; I = 0x300; skip if V0 == 1; jump 0x200
+    const PROGRAM: [u8; 10] = [0x60, 0x01, 0x71, 0x02, 0xA3, 0x00, 0x30, 0x01, 0x12, 0x00];
+
+    fn memory() -> Vec<u8> {
+        let mut memory = vec![0; 0x200];
+        memory.extend_from_slice(&PROGRAM);
+        memory
+    }
+
+    #[test]
+    fn test_decode() {
+        let block = Block::decode(&memory(), 0x200, Platform::Chip8);
+        assert_eq!(block.ops.len(), 4);
+        assert_eq!(block.ops[3], (0x3001, Instruction::SkipEqImm(0, 1)));
+        assert_eq!(block.end(), 0x208);
+        assert_eq!(Block::decode(&memory(), 0x208, Platform::Chip8).ops.len(), 1);
+        assert!(Block::decode(&memory(), 0x20A, Platform::Chip8).ops.is_empty());
+    }
+
+    #[test]
+    fn test_invalidate() {
+        let memory = memory();
+        let mut cache = BlockCache::new();
+        cache.get(0x200, &memory, Platform::Chip8);
+        cache.get(0x204, &memory, Platform::Chip8);
+        cache.get(0x208, &memory, Platform::Chip8);
+        assert_eq!(cache.count(), 3);
+
+        cache.invalidate(0x100);
+        assert_eq!(cache.count(), 3);
+        cache.invalidate(0x203);
+        assert_eq!(cache.count(), 2);
+        cache.invalidate(0x205);
+        assert_eq!(cache.count(), 1);
+        cache.invalidate(0x209);
+        assert_eq!(cache.count(), 0);
+    }
+}
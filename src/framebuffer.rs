@@ -0,0 +1,235 @@
+use crate::colour::{self, ColourAttributes};
+use crate::screen::Screen;
+
+const DEFAULT_SCALE: usize = 5;
+const DEFAULT_PALETTE: [u32; 2] = [0xFFFFFFFF, 0x000000FF];
+
+/// RGBA8 rendering of a `Screen`, laid out row by row so it can be wrapped
+/// in an `ImageData` and blitted with `putImageData`.
+pub struct FrameBuffer {
+    scale: usize,
+    palette: [[u8; 4]; 2],
+    pixels: Vec<u8>,
+    generation: u64,
+    // Set while the buffer holds a CHIP-8X or MEGA-CHIP colour rendering.
+    colour_generation: Option<u64>,
+    stale: bool,
+}
+
+impl Default for FrameBuffer {
+    fn default() -> FrameBuffer {
+        FrameBuffer {
+            scale: DEFAULT_SCALE,
+            palette: [
+                rgba(DEFAULT_PALETTE[0]),
+                rgba(DEFAULT_PALETTE[1]),
+            ],
+            pixels: Vec::new(),
+            generation: 0,
+            colour_generation: None,
+            stale: true,
+        }
+    }
+}
+
+impl FrameBuffer {
+    pub fn new() -> FrameBuffer {
+        Default::default()
+    }
+
+    pub fn scale(&self) -> usize {
+        self.scale
+    }
+
+    pub fn set_scale(&mut self, scale: usize) {
+        self.scale = scale.max(1);
+        self.stale = true;
+    }
+
+    /// Colours are given as `0xRRGGBBAA`, index 0 for unset pixels and 1 for set ones.
+    pub fn set_palette(&mut self, off: u32, on: u32) {
+        self.palette = [rgba(off), rgba(on)];
+        self.stale = true;
+    }
+
+    pub fn reset_palette(&mut self) {
+        self.set_palette(DEFAULT_PALETTE[0], DEFAULT_PALETTE[1]);
+    }
+
+    /// The unset and set pixel colours as `0xRRGGBBAA`.
+    pub fn palette(&self) -> (u32, u32) {
+        (u32::from_be_bytes(self.palette[0]), u32::from_be_bytes(self.palette[1]))
+    }
+
+    /// Re-renders the buffer if the screen differs from the last rendered frame.
+    /// Returns whether the buffer content changed.
+    pub fn update(&mut self, screen: &Screen) -> bool {
+        if !self.stale && self.generation == screen.generation() && self.colour_generation.is_none() {
+            return false;
+        }
+        self.render_screen(screen);
+        self.generation = screen.generation();
+        self.colour_generation = None;
+        self.stale = false;
+        true
+    }
+
+    /// Like `update`, but colouring the screen with CHIP-8X colour attributes
+    /// instead of the palette.
+    pub fn update_colours(&mut self, screen: &Screen, colours: &ColourAttributes) -> bool {
+        let unchanged = self.generation == screen.generation() && self.colour_generation == Some(colours.generation());
+        if !self.stale && unchanged {
+            return false;
+        }
+        let bit_map = screen.pixels();
+        let width = screen.width();
+        let background = rgba(colour::PALETTE[colours.background() as usize]);
+        self.render(width, screen.height(), |idx| {
+            if bit_map[idx] {
+                rgba(colour::PALETTE[colours.foreground(idx / width, idx % width) as usize])
+            } else {
+                background
+            }
+        });
+        self.generation = screen.generation();
+        self.colour_generation = Some(colours.generation());
+        self.stale = false;
+        true
+    }
+
+    /// Renders an RGBA layer, `0xRRGGBBAA` per pixel, such as MEGA-CHIP's, if
+    /// its `generation` differs from the last rendered one.
+    #[cfg(feature = "megachip")]
+    pub fn update_rgba(&mut self, pixels: &[u32], width: usize, height: usize, generation: u64) -> bool {
+        if !self.stale && self.colour_generation == Some(generation) {
+            return false;
+        }
+        self.render(width, height, |idx| rgba(pixels[idx]));
+        self.colour_generation = Some(generation);
+        self.stale = false;
+        true
+    }
+
+    /// Renders per-pixel intensities (0 to 255), blending between the two
+    /// palette colours. Used for post-processed output such as `Persistence`.
+    pub fn update_levels(&mut self, levels: &[u8], width: usize, height: usize) {
+        let palette = self.palette;
+        self.render(width, height, |idx| blend(&palette, levels[idx]));
+        self.stale = true;
+    }
+
+    fn render_screen(&mut self, screen: &Screen) {
+        let palette = self.palette;
+        let bit_map = screen.pixels();
+        self.render(screen.width(), screen.height(), |idx| palette[bit_map[idx] as usize]);
+    }
+
+    fn render<F: Fn(usize) -> [u8; 4]>(&mut self, width: usize, height: usize, color: F) {
+        let scaled_width = width * self.scale;
+        let scaled_height = height * self.scale;
+        self.pixels.resize(scaled_width * scaled_height * 4, 0);
+
+        for row in 0..scaled_height {
+            for col in 0..scaled_width {
+                let rgba = color((row / self.scale) * width + col / self.scale);
+                let offset = (row * scaled_width + col) * 4;
+                self.pixels[offset..offset + 4].copy_from_slice(&rgba);
+            }
+        }
+    }
+
+    pub fn as_ptr(&self) -> *const u8 {
+        self.pixels.as_ptr()
+    }
+
+    pub fn len(&self) -> usize {
+        self.pixels.len()
+    }
+}
+
+fn rgba(color: u32) -> [u8; 4] {
+    color.to_be_bytes()
+}
+
+fn blend(palette: &[[u8; 4]; 2], level: u8) -> [u8; 4] {
+    let mut rgba = [0; 4];
+    for (idx, channel) in rgba.iter_mut().enumerate() {
+        let (off, on) = (palette[0][idx] as u32, palette[1][idx] as u32);
+        *channel = ((off * (255 - level as u32) + on * level as u32) / 255) as u8;
+    }
+    rgba
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_update_renders_scaled_rgba() {
+        let mut screen = Screen::new();
+        let mut fb = FrameBuffer::new();
+        fb.set_scale(2);
+        fb.set_palette(0x00000000, 0x11223344);
+        screen.set_pixel(0, 1);
+        screen.end_draw();
+
+        assert!(fb.update(&screen));
+        assert_eq!(fb.len(), screen.width() * 2 * screen.height() * 2 * 4);
+        let row_len = screen.width() * 2 * 4;
+        assert_eq!(&fb.pixels[0..4], &[0, 0, 0, 0]);
+        assert_eq!(&fb.pixels[8..12], &[0x11, 0x22, 0x33, 0x44]);
+        assert_eq!(&fb.pixels[12..16], &[0x11, 0x22, 0x33, 0x44]);
+        assert_eq!(&fb.pixels[row_len + 8..row_len + 12], &[0x11, 0x22, 0x33, 0x44]);
+    }
+
+    #[test]
+    fn test_update_only_when_screen_changes() {
+        let mut screen = Screen::new();
+        let mut fb = FrameBuffer::new();
+        assert!(fb.update(&screen));
+        assert!(!fb.update(&screen));
+
+        screen.set_pixel(3, 3);
+        screen.end_draw();
+        assert!(fb.update(&screen));
+        assert!(!fb.update(&screen));
+
+        fb.set_palette(0xFF0000FF, 0x00FF00FF);
+        assert!(fb.update(&screen));
+        assert_eq!(fb.palette(), (0xFF0000FF, 0x00FF00FF));
+        fb.reset_palette();
+        assert!(fb.update(&screen));
+        assert_eq!(fb.palette(), (DEFAULT_PALETTE[0], DEFAULT_PALETTE[1]));
+    }
+
+    #[test]
+    fn test_update_colours() {
+        let mut screen = Screen::new();
+        let mut colours = ColourAttributes::new(64, 32);
+        let mut fb = FrameBuffer::new();
+        fb.set_scale(1);
+        screen.set_pixel(0, 0);
+        screen.end_draw();
+
+        assert!(fb.update_colours(&screen, &colours));
+        assert_eq!(&fb.pixels[0..4], &rgba(colour::PALETTE[1]));
+        assert_eq!(&fb.pixels[4..8], &rgba(colour::PALETTE[2]));
+        assert!(!fb.update_colours(&screen, &colours));
+
+        colours.cycle_background();
+        assert!(fb.update_colours(&screen, &colours));
+        assert_eq!(&fb.pixels[4..8], &rgba(colour::PALETTE[0]));
+        assert!(fb.update(&screen));
+    }
+
+    #[test]
+    fn test_update_levels_blends_palette() {
+        let mut fb = FrameBuffer::new();
+        fb.set_scale(1);
+        fb.set_palette(0x000000FF, 0xFF8000FF);
+        fb.update_levels(&[0, 255, 51], 3, 1);
+        assert_eq!(&fb.pixels[0..4], &[0, 0, 0, 255]);
+        assert_eq!(&fb.pixels[4..8], &[255, 128, 0, 255]);
+        assert_eq!(&fb.pixels[8..12], &[51, 25, 0, 255]);
+    }
+}
@@ -0,0 +1,262 @@
+use crate::keyboard::Keyboard;
+use serde::{Deserialize, Serialize};
+
+const DEFAULT_DEAD_ZONE: f32 = 0.3;
+const BUTTON_THRESHOLD: f32 = 0.5;
+
+// Indices of the W3C "standard" gamepad mapping.
+pub const BUTTON_A: u8 = 0;
+pub const BUTTON_B: u8 = 1;
+pub const BUTTON_X: u8 = 2;
+pub const BUTTON_Y: u8 = 3;
+pub const BUTTON_START: u8 = 9;
+pub const DPAD_UP: u8 = 12;
+pub const DPAD_DOWN: u8 = 13;
+pub const DPAD_LEFT: u8 = 14;
+pub const DPAD_RIGHT: u8 = 15;
+pub const LEFT_STICK_X: u8 = 0;
+pub const LEFT_STICK_Y: u8 = 1;
+
+/// A physical control on a gamepad.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub enum Control {
+    Button(u8),
+    /// An axis pushed below minus the dead zone, e.g. left or up.
+    AxisNegative(u8),
+    /// An axis pushed above the dead zone, e.g. right or down.
+    AxisPositive(u8),
+}
+
+/// Bindings from gamepad controls to CHIP-8 keys for one game.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct GamepadProfile {
+    pub name: String,
+    pub bindings: Vec<(Control, u8)>,
+}
+
+impl GamepadProfile {
+    /// Directions on 2/8/4/6 and the face buttons on 5, which most games use.
+    pub fn standard() -> GamepadProfile {
+        GamepadProfile::directional("standard", [0x2, 0x8, 0x4, 0x6], &[
+            (BUTTON_A, 0x5),
+            (BUTTON_B, 0x5),
+            (BUTTON_X, 0x0),
+            (BUTTON_Y, 0xF),
+            (BUTTON_START, 0xA),
+        ])
+    }
+
+    /// Profile for one of the bundled games, if it needs something else than `standard`.
+    pub fn for_game(name: &str) -> Option<GamepadProfile> {
+        match name.to_uppercase().as_str() {
+            // Left paddle up and down.
+            "PONG" | "PONG2" => Some(GamepadProfile::vertical("PONG", 0x1, 0x4)),
+            "BRIX" | "VBRIX" => Some(GamepadProfile::horizontal("BRIX", 0x4, 0x6)),
+            // Move left and right, shoot with A.
+            "INVADERS" => {
+                let mut profile = GamepadProfile::horizontal("INVADERS", 0x4, 0x6);
+                profile.bindings.push((Control::Button(BUTTON_A), 0x5));
+                Some(profile)
+            }
+            "STANDARD" => Some(GamepadProfile::standard()),
+            _ => None,
+        }
+    }
+
+    fn vertical(name: &str, up: u8, down: u8) -> GamepadProfile {
+        GamepadProfile {
+            name: name.to_string(),
+            bindings: vec![
+                (Control::Button(DPAD_UP), up),
+                (Control::Button(DPAD_DOWN), down),
+                (Control::AxisNegative(LEFT_STICK_Y), up),
+                (Control::AxisPositive(LEFT_STICK_Y), down),
+            ],
+        }
+    }
+
+    fn horizontal(name: &str, left: u8, right: u8) -> GamepadProfile {
+        GamepadProfile {
+            name: name.to_string(),
+            bindings: vec![
+                (Control::Button(DPAD_LEFT), left),
+                (Control::Button(DPAD_RIGHT), right),
+                (Control::AxisNegative(LEFT_STICK_X), left),
+                (Control::AxisPositive(LEFT_STICK_X), right),
+            ],
+        }
+    }
+
+    // `directions` are the keys for up, down, left and right.
+    fn directional(name: &str, directions: [u8; 4], buttons: &[(u8, u8)]) -> GamepadProfile {
+        let [up, down, left, right] = directions;
+        let mut profile = GamepadProfile::vertical(name, up, down);
+        profile.bindings.extend(GamepadProfile::horizontal(name, left, right).bindings);
+        profile.bindings.extend(buttons.iter().map(|&(button, key)| (Control::Button(button), key)));
+        profile
+    }
+
+    pub fn to_json(&self) -> String {
+        serde_json::to_string(self).unwrap()
+    }
+
+    pub fn from_json(json: &str) -> Result<GamepadProfile, String> {
+        let profile: GamepadProfile = serde_json::from_str(json).map_err(|err| err.to_string())?;
+        if let Some((control, key)) = profile.bindings.iter().find(|(_, key)| *key > 0xF) {
+            return Err(format!("invalid CHIP-8 key {} for {:?}", key, control));
+        }
+        Ok(profile)
+    }
+}
+
+/// Turns gamepad snapshots into CHIP-8 key presses. Keys are pressed and
+/// released on the `Keyboard` only when the gamepad state changes, and keys
+/// the host keyboard holds are never released, so the gamepad and the host
+/// keyboard can be used together.
+pub struct Gamepad {
+    profile: GamepadProfile,
+    dead_zone: f32,
+    pressed: [bool; 16],
+}
+
+impl Default for Gamepad {
+    fn default() -> Gamepad {
+        Gamepad {
+            profile: GamepadProfile::standard(),
+            dead_zone: DEFAULT_DEAD_ZONE,
+            pressed: [false; 16],
+        }
+    }
+}
+
+impl Gamepad {
+    pub fn new() -> Gamepad {
+        Default::default()
+    }
+
+    pub fn profile(&self) -> &GamepadProfile {
+        &self.profile
+    }
+
+    pub fn set_profile(&mut self, profile: GamepadProfile) {
+        self.profile = profile;
+    }
+
+    pub fn set_dead_zone(&mut self, dead_zone: f32) {
+        self.dead_zone = dead_zone.clamp(0.0, 1.0);
+    }
+
+    /// Whether the gamepad is holding `key` down.
+    pub fn is_pressed(&self, key: u8) -> bool {
+        self.pressed[key as usize]
+    }
+
+    /// Forgets the keys the gamepad was holding, after the keyboard was cleared.
+    pub fn clear(&mut self) {
+        self.pressed = [false; 16];
+    }
+
+    /// Applies a snapshot of button values (0 to 1) and axis positions (-1 to 1).
+    /// `held` are the keys the host keyboard holds down.
+    pub fn update(&mut self, buttons: &[f32], axes: &[f32], held: &[bool; 16], keyboard: &mut Keyboard) {
+        let mut pressed = [false; 16];
+        for &(control, key) in self.profile.bindings.iter() {
+            let active = match control {
+                Control::Button(idx) => buttons.get(idx as usize).is_some_and(|&value| value > BUTTON_THRESHOLD),
+                Control::AxisNegative(idx) => axes.get(idx as usize).is_some_and(|&value| value < -self.dead_zone),
+                Control::AxisPositive(idx) => axes.get(idx as usize).is_some_and(|&value| value > self.dead_zone),
+            };
+            pressed[key as usize] |= active;
+        }
+
+        for (key, (&now, &before)) in pressed.iter().zip(self.pressed.iter()).enumerate() {
+            if now && !before {
+                keyboard.key_down(key as u8);
+            } else if !now && before && !held[key] {
+                keyboard.key_up(key as u8);
+            }
+        }
+        self.pressed = pressed;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_buttons_and_dpad() {
+        let mut gamepad = Gamepad::new();
+        let mut keyboard = Keyboard::new();
+        let mut buttons = [0.0; 17];
+        buttons[BUTTON_A as usize] = 1.0;
+        buttons[DPAD_LEFT as usize] = 1.0;
+        gamepad.update(&buttons, &[], &[false; 16], &mut keyboard);
+        assert!(keyboard.is_key_pressed(0x5));
+        assert!(keyboard.is_key_pressed(0x4));
+
+        buttons[BUTTON_A as usize] = 0.0;
+        gamepad.update(&buttons, &[], &[false; 16], &mut keyboard);
+        assert!(!keyboard.is_key_pressed(0x5));
+        assert!(keyboard.is_key_pressed(0x4));
+    }
+
+    #[test]
+    fn test_axis_dead_zone() {
+        let mut gamepad = Gamepad::new();
+        let mut keyboard = Keyboard::new();
+        gamepad.set_profile(GamepadProfile::for_game("pong").unwrap());
+        gamepad.set_dead_zone(0.5);
+
+        gamepad.update(&[], &[0.0, -0.4], &[false; 16], &mut keyboard);
+        assert!(!keyboard.is_key_pressed(0x1));
+        gamepad.update(&[], &[0.0, -0.6], &[false; 16], &mut keyboard);
+        assert!(keyboard.is_key_pressed(0x1));
+        gamepad.update(&[], &[0.0, 0.9], &[false; 16], &mut keyboard);
+        assert!(!keyboard.is_key_pressed(0x1));
+        assert!(keyboard.is_key_pressed(0x4));
+    }
+
+    #[test]
+    fn test_keeps_keyboard_presses() {
+        let mut gamepad = Gamepad::new();
+        let mut keyboard = Keyboard::new();
+        let mut held = [false; 16];
+        keyboard.key_down(0x5);
+        held[0x5] = true;
+        gamepad.update(&[0.0; 17], &[0.0; 4], &held, &mut keyboard);
+        assert!(keyboard.is_key_pressed(0x5));
+
+        // Letting go of a button doesn't release the key the keyboard holds.
+        let mut buttons = [0.0; 17];
+        buttons[BUTTON_A as usize] = 1.0;
+        gamepad.update(&buttons, &[], &held, &mut keyboard);
+        gamepad.update(&[0.0; 17], &[], &held, &mut keyboard);
+        assert!(keyboard.is_key_pressed(0x5));
+        held[0x5] = false;
+        keyboard.key_up(0x5);
+        gamepad.update(&buttons, &[], &held, &mut keyboard);
+        gamepad.update(&[0.0; 17], &[], &held, &mut keyboard);
+        assert!(!keyboard.is_key_pressed(0x5));
+    }
+
+    #[test]
+    fn test_clear() {
+        let mut gamepad = Gamepad::new();
+        let mut keyboard = Keyboard::new();
+        let mut buttons = [0.0; 17];
+        buttons[BUTTON_A as usize] = 1.0;
+        gamepad.update(&buttons, &[], &[false; 16], &mut keyboard);
+        keyboard.clear();
+        gamepad.clear();
+        gamepad.update(&buttons, &[], &[false; 16], &mut keyboard);
+        assert!(keyboard.is_key_pressed(0x5));
+    }
+
+    #[test]
+    fn test_profile_json() {
+        let profile = GamepadProfile::for_game("BRIX").unwrap();
+        assert_eq!(GamepadProfile::from_json(&profile.to_json()), Ok(profile));
+        assert!(GamepadProfile::from_json(r#"{"name":"x","bindings":[[{"Button":0},16]]}"#).is_err());
+    }
+}
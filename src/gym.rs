@@ -0,0 +1,395 @@
+//! A Gym-style environment for training agents on games: `reset` starts an
+//! episode and `step` holds keys for a few frames, returning what the screen
+//! shows and the reward a `Game` reads out of the game's memory.
+
+use crate::bus::Bus;
+use crate::cpu::CPU;
+use crate::differential::Config;
+use crate::platform::Platform;
+use crate::rom::RomError;
+use crate::utils::Rng;
+
+/// What an agent sees of the screen.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Observation {
+    /// Every pixel, 1.0 when on.
+    Bitmap,
+    /// The share of pixels on in each block of `factor` by `factor` pixels.
+    Downsampled(usize),
+}
+
+/// Reads the score and the end of an episode out of a running game.
+/// `score` and `is_over` are called after every frame, so a game can keep
+/// count of events that don't leave a score in memory.
+pub trait Game {
+    /// The keys the game is played with.
+    fn keys(&self) -> &[u8];
+
+    /// Forgets what was counted in the last episode.
+    fn reset(&mut self) {}
+
+    fn score(&mut self, cpu: &CPU) -> i32;
+
+    fn lives(&mut self, _cpu: &CPU) -> Option<u8> {
+        None
+    }
+
+    fn is_over(&mut self, cpu: &CPU) -> bool;
+}
+
+/// The extractor for one of the bundled games.
+pub fn for_game(name: &str) -> Option<Box<dyn Game>> {
+    match name.to_uppercase().as_str() {
+        "PONG" => Some(Box::new(Pong)),
+        "BRIX" => Some(Box::new(Brix)),
+        "TETRIS" => Some(Box::new(Tetris)),
+        "INVADERS" => Some(Box::new(Invaders::default())),
+        _ => None,
+    }
+}
+
+/// How the game went after a step.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Info {
+    /// Frames run since `reset`.
+    pub frame: usize,
+    pub score: i32,
+    pub lives: Option<u8>,
+}
+
+/// What `step` returns.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Step {
+    pub observation: Vec<f32>,
+    /// How much the score changed during the step.
+    pub reward: f32,
+    pub done: bool,
+    pub info: Info,
+}
+
+/// A program played one step at a time.
+pub struct Env {
+    program: Vec<u8>,
+    platform: Platform,
+    config: Config,
+    cycles: usize,
+    /// Frames each step holds its keys for.
+    pub frame_skip: usize,
+    pub observation: Observation,
+    game: Box<dyn Game>,
+    cpu: CPU,
+    rng: Rng,
+    frame: usize,
+    score: i32,
+    done: bool,
+}
+
+impl Env {
+    /// An environment for `program` with the platform's quirks, 8 cycles a
+    /// frame and 4 frames a step, reset with seed 0. Fails if the program
+    /// doesn't fit in memory.
+    pub fn new(program: &[u8], platform: Platform, game: Box<dyn Game>) -> Result<Env, RomError> {
+        let mut env = Env {
+            program: program.to_vec(),
+            platform,
+            config: Config::reference(platform),
+            cycles: 8,
+            frame_skip: 4,
+            observation: Observation::Bitmap,
+            game,
+            cpu: CPU::with_platform(platform),
+            rng: Rng::new(0),
+            frame: 0,
+            score: 0,
+            done: false,
+        };
+        env.cpu.load_program(program)?;
+        env.reset(0);
+        Ok(env)
+    }
+
+    /// Runs the program with `config` and `cycles` a frame from the next `reset`.
+    pub fn configure(&mut self, config: Config, cycles: usize) {
+        self.config = config;
+        self.cycles = cycles;
+    }
+
+    /// Starts a new episode, with `seed` for the program's random numbers
+    /// and `sample`, and returns the first observation.
+    pub fn reset(&mut self, seed: u64) -> Vec<f32> {
+        self.cpu = CPU::with_platform(self.platform);
+        self.cpu.set_quirks(self.config.quirks);
+        self.cpu.set_engine(self.config.engine);
+        self.cpu.seed_random(seed);
+        self.cpu.load_program(&self.program).expect("the program fitted in Env::new");
+        self.rng = Rng::new(seed);
+        self.game.reset();
+        self.frame = 0;
+        self.score = self.game.score(&self.cpu);
+        self.done = false;
+        self.observe()
+    }
+
+    /// Holds `keys` down, and every other key up, for `frame_skip` frames or
+    /// until the game is over. Once it is, steps don't run anything.
+    pub fn step(&mut self, keys: &[u8]) -> Step {
+        for key in 0..16 {
+            if keys.contains(&key) {
+                self.cpu.get_keyboard().key_down(key);
+            } else {
+                self.cpu.get_keyboard().key_up(key);
+            }
+        }
+        let start = self.score;
+        for _ in 0..self.frame_skip {
+            if self.done {
+                break;
+            }
+            self.cpu.run_frame(self.cycles);
+            self.frame += 1;
+            self.score = self.game.score(&self.cpu);
+            self.done = self.game.is_over(&self.cpu);
+        }
+        Step {
+            observation: self.observe(),
+            reward: (self.score - start) as f32,
+            done: self.done,
+            info: Info { frame: self.frame, score: self.score, lives: self.game.lives(&self.cpu) },
+        }
+    }
+
+    /// A random action: one of the game's keys, or none.
+    pub fn sample(&mut self) -> Vec<u8> {
+        let keys = self.game.keys();
+        let choice = self.rng.next_byte() as usize % (keys.len() + 1);
+        keys.get(choice).map(|&key| vec![key]).unwrap_or_default()
+    }
+
+    /// The keys the game is played with.
+    pub fn keys(&self) -> &[u8] {
+        self.game.keys()
+    }
+
+    /// Width and height of the observations.
+    pub fn observation_shape(&self) -> (usize, usize) {
+        let screen = self.cpu.screen();
+        match self.observation {
+            Observation::Bitmap => (screen.width(), screen.height()),
+            Observation::Downsampled(factor) => (screen.width() / factor.max(1), screen.height() / factor.max(1)),
+        }
+    }
+
+    pub fn cpu(&mut self) -> &mut CPU {
+        &mut self.cpu
+    }
+
+    fn observe(&self) -> Vec<f32> {
+        let screen = self.cpu.screen();
+        let (width, pixels) = (screen.width(), screen.pixels());
+        match self.observation {
+            Observation::Bitmap => pixels.iter().map(|&on| if on { 1.0 } else { 0.0 }).collect(),
+            Observation::Downsampled(factor) => downsample(pixels, width, factor.max(1)),
+        }
+    }
+}
+
+// Pixels past the last whole block on the right or at the bottom are dropped.
+fn downsample(pixels: &[bool], width: usize, factor: usize) -> Vec<f32> {
+    let height = pixels.len() / width;
+    let (columns, rows) = (width / factor, height / factor);
+    let mut blocks = Vec::with_capacity(columns * rows);
+    for row in 0..rows {
+        for column in 0..columns {
+            let on = (0..factor * factor)
+                .filter(|i| pixels[(row * factor + i / factor) * width + column * factor + i % factor])
+                .count();
+            blocks.push(on as f32 / (factor * factor) as f32);
+        }
+    }
+    blocks
+}
+
+// A number written by FX33.
+fn bcd(memory: &[u8], addr: usize) -> i32 {
+    memory[addr..addr + 3].iter().fold(0, |number, &digit| number * 10 + digit as i32)
+}
+
+/// PONG, with the agent on the left paddle. The right one is a second
+/// player's, on C and D. The score is stored as one number, ten points for
+/// the left player and one for the right, so the episode ends when either
+/// has 9.
+pub struct Pong;
+
+// Where PONG's FE33 writes the score's digits.
+const PONG_SCORE: usize = 0x2F2;
+
+impl Pong {
+    fn points(cpu: &CPU) -> (i32, i32) {
+        let memory = cpu.bus().bytes();
+        (memory[PONG_SCORE + 1] as i32, memory[PONG_SCORE + 2] as i32)
+    }
+}
+
+impl Game for Pong {
+    fn keys(&self) -> &[u8] {
+        &[0x1, 0x4]
+    }
+
+    fn score(&mut self, cpu: &CPU) -> i32 {
+        let (agent, opponent) = Pong::points(cpu);
+        agent - opponent
+    }
+
+    fn is_over(&mut self, cpu: &CPU) -> bool {
+        let (agent, opponent) = Pong::points(cpu);
+        agent == 9 || opponent == 9
+    }
+}
+
+/// BRIX, scoring a point a brick. It stops in a loop at `BRIX_OVER` when the
+/// lives in VE run out or every brick is gone.
+pub struct Brix;
+
+const BRIX_SCORE: usize = 0x314;
+const BRIX_OVER: u16 = 0x2DE;
+
+impl Game for Brix {
+    fn keys(&self) -> &[u8] {
+        &[0x4, 0x6]
+    }
+
+    fn score(&mut self, cpu: &CPU) -> i32 {
+        bcd(cpu.bus().bytes(), BRIX_SCORE)
+    }
+
+    fn lives(&mut self, cpu: &CPU) -> Option<u8> {
+        Some(cpu.registers()[0xE])
+    }
+
+    fn is_over(&mut self, cpu: &CPU) -> bool {
+        cpu.pc() == BRIX_OVER
+    }
+}
+
+/// TETRIS, scoring the lines cleared. The game keeps dropping pieces into a
+/// full well, so the episode ends once the stack reaches the row above where
+/// pieces appear.
+pub struct Tetris;
+
+const TETRIS_LINES: usize = 0x804;
+// Inside of the well, between the walls at x 0x1A and 0x25.
+const TETRIS_WELL: std::ops::Range<usize> = 0x1B..0x25;
+const TETRIS_TOP_ROW: usize = 2;
+
+impl Game for Tetris {
+    fn keys(&self) -> &[u8] {
+        &[0x4, 0x5, 0x6, 0x7]
+    }
+
+    fn score(&mut self, cpu: &CPU) -> i32 {
+        bcd(cpu.bus().bytes(), TETRIS_LINES)
+    }
+
+    fn is_over(&mut self, cpu: &CPU) -> bool {
+        let screen = cpu.screen();
+        let row = &screen.pixels()[TETRIS_TOP_ROW * screen.width()..];
+        TETRIS_WELL.into_iter().any(|x| row[x])
+    }
+}
+
+/// INVADERS, scoring an invader shot down. The game keeps no score: a wave
+/// is counted in V9 and the invaders left in it are the bits of VE, so they
+/// are counted as the bits go. It ends on the game over screen.
+#[derive(Default)]
+pub struct Invaders {
+    // Wave and invaders left when last seen during play.
+    last: Option<(u8, u8)>,
+    shot: i32,
+}
+
+// Play starts after the first wave is set up.
+const INVADERS_PLAY: std::ops::Range<u16> = 0x267..0x387;
+const INVADERS_OVER: std::ops::Range<u16> = 0x329..0x347;
+
+impl Game for Invaders {
+    fn keys(&self) -> &[u8] {
+        &[0x4, 0x5, 0x6]
+    }
+
+    fn reset(&mut self) {
+        *self = Invaders::default();
+    }
+
+    fn score(&mut self, cpu: &CPU) -> i32 {
+        let pc = cpu.pc();
+        if !INVADERS_PLAY.contains(&pc) || INVADERS_OVER.contains(&pc) {
+            self.last = None;
+            return self.shot;
+        }
+        let registers = cpu.registers();
+        let (wave, left) = (registers[0x9], registers[0xE] & 0xF);
+        self.shot += match self.last {
+            Some((last_wave, last_left)) if last_wave == wave => (last_left & !left).count_ones() as i32,
+            // The rest of the last wave went in the frame the next one started.
+            Some((_, last_left)) => last_left.count_ones() as i32,
+            None => 0,
+        };
+        self.last = Some((wave, left));
+        self.shot
+    }
+
+    fn is_over(&mut self, cpu: &CPU) -> bool {
+        INVADERS_OVER.contains(&cpu.pc())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_downsample() {
+        let mut pixels = vec![false; 4 * 2];
+        pixels[0] = true;
+        pixels[2] = true;
+        pixels[3] = true;
+        pixels[6] = true;
+        assert_eq!(downsample(&pixels, 4, 2), vec![0.25, 0.75]);
+        assert_eq!(downsample(&pixels, 4, 3), Vec::<f32>::new());
+    }
+
+    fn read_game(name: &str) -> Vec<u8> {
+        std::fs::read(format!("{}/resources/games/{}", env!("CARGO_MANIFEST_DIR"), name)).unwrap()
+    }
+
+    #[test]
+    fn test_games() {
+        for (name, frames, score) in [("PONG", 2668, 7), ("BRIX", 1896, 44), ("TETRIS", 7597, 0), ("INVADERS", 2295, 3)] {
+            let program = read_game(name);
+            let mut env = Env::new(&program, Platform::Chip8, for_game(name).unwrap()).unwrap();
+            env.observation = Observation::Downsampled(4);
+            assert_eq!(env.reset(1).len(), 16 * 8);
+            let mut total = 0.0;
+            let step = loop {
+                let keys = env.sample();
+                let step = env.step(&keys);
+                total += step.reward;
+                if step.done {
+                    break step;
+                }
+            };
+            assert_eq!((step.info.frame, step.info.score, total), (frames, score, score as f32), "{}", name);
+            assert_eq!(env.step(&[]).info.frame, frames);
+        }
+    }
+
+    #[test]
+    fn test_tetris_reward() {
+        let mut env = Env::new(&read_game("TETRIS"), Platform::Chip8, for_game("TETRIS").unwrap()).unwrap();
+        assert_eq!(env.step(&[]).reward, 0.0);
+        // Twelve lines cleared, in the BCD the game keeps them in.
+        env.cpu().get_memory().bytes_mut()[TETRIS_LINES..TETRIS_LINES + 3].copy_from_slice(&[0, 1, 2]);
+        let step = env.step(&[]);
+        assert_eq!((step.reward, step.info.score), (12.0, 12));
+    }
+}
@@ -0,0 +1,154 @@
+//! Properties that hold whatever a program does, checked after every
+//! instruction by the property tests and the fuzz targets.
+
+use crate::bus::Bus;
+use crate::cpu::CPU;
+use crate::differential::{differences, Config, KeyEvent};
+use crate::platform::Platform;
+use crate::state::State;
+
+/// A program with its input, run under a config.
+#[derive(Clone, Debug)]
+pub struct Run {
+    pub program: Vec<u8>,
+    pub platform: Platform,
+    pub config: Config,
+    pub seed: u64,
+    pub cycles: usize,
+    pub frames: usize,
+    pub input: Vec<KeyEvent>,
+}
+
+impl Run {
+    pub fn new(program: &[u8], platform: Platform) -> Run {
+        Run {
+            program: program.to_vec(),
+            platform,
+            config: Config::reference(platform),
+            seed: 0,
+            cycles: 8,
+            frames: 60,
+            input: Vec::new(),
+        }
+    }
+
+    /// Runs the program one instruction at a time, checking the invariants
+    /// after each one. At the end of every frame the state is saved and
+    /// loaded back, and the CPU must still match one that never was.
+    /// Programs too large for the platform are skipped.
+    pub fn check(&self) -> Result<(), String> {
+        let mut cpus = [self.cpu(), self.cpu()];
+        for cpu in cpus.iter_mut() {
+            if cpu.load_program(&self.program).is_err() {
+                return Ok(());
+            }
+        }
+        for frame in 0..self.frames {
+            for event in self.input.iter().filter(|event| event.frame == frame) {
+                for cpu in cpus.iter_mut() {
+                    if event.pressed {
+                        cpu.get_keyboard().key_down(event.key);
+                    } else {
+                        cpu.get_keyboard().key_up(event.key);
+                    }
+                }
+            }
+            let [reference, restored] = &mut cpus;
+            for cycle in 0..self.cycles {
+                let ended = reference.run_cycles(1);
+                check(reference).map_err(|err| format!("frame {} cycle {}: {}", frame, cycle, err))?;
+                if ended {
+                    break;
+                }
+            }
+            restored.run_cycles(self.cycles);
+            for cpu in cpus.iter_mut() {
+                cpu.end_frame();
+            }
+
+            let [reference, restored] = &mut cpus;
+            check_round_trip(restored).map_err(|err| format!("frame {}: {}", frame, err))?;
+            let differences = differences(reference, restored);
+            if !differences.is_empty() {
+                return Err(format!("frame {}: the loaded state ran differently: {}", frame, differences.join(", ")));
+            }
+        }
+        Ok(())
+    }
+
+    fn cpu(&self) -> CPU {
+        let mut cpu = CPU::with_platform(self.platform);
+        cpu.set_quirks(self.config.quirks);
+        cpu.set_engine(self.config.engine);
+        cpu.seed_random(self.seed);
+        cpu
+    }
+}
+
+/// PC and the return addresses are in memory, I fits in 24 bits and the
+/// screen has a pixel and a colour zone for every position.
+pub fn check(cpu: &CPU) -> Result<(), String> {
+    let size = cpu.bus().size();
+    if cpu.pc() as usize >= size {
+        return Err(format!("PC {:#06X} is past the end of memory", cpu.pc()));
+    }
+    if let Some(addr) = cpu.stack().iter().find(|&&addr| addr as usize >= size) {
+        return Err(format!("return address {:#06X} is past the end of memory", addr));
+    }
+    if cpu.i() > 0xFFFFFF {
+        return Err(format!("I {:#X} is wider than 24 bits", cpu.i()));
+    }
+    let screen = cpu.screen();
+    if screen.pixels().len() != screen.width() * screen.height() {
+        return Err(format!("{} pixels on a {}x{} screen", screen.pixels().len(), screen.width(), screen.height()));
+    }
+    let (_, colours) = cpu.screen_and_colours();
+    if !colours.is_valid(screen.width(), screen.height()) {
+        return Err(format!("the colours don't fit a {}x{} screen", screen.width(), screen.height()));
+    }
+    Ok(())
+}
+
+/// Saves the state as JSON and loads it back into `cpu` and into a new CPU,
+/// which must both save the same state again.
+pub fn check_round_trip(cpu: &mut CPU) -> Result<(), String> {
+    let state = match cpu.save_state() {
+        Some(state) => state,
+        None => return Ok(()),
+    };
+    let loaded = State::from_json(&state.to_json())?;
+    if loaded != state {
+        return Err("the state changed going through JSON".to_string());
+    }
+    let mut fresh = CPU::new();
+    for cpu in [cpu, &mut fresh] {
+        cpu.load_state(&loaded)?;
+        if cpu.save_state().as_ref() != Some(&state) {
+            return Err("a loaded state saved differently".to_string());
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_check() {
+        let mut cpu = CPU::new();
+        assert_eq!(check(&cpu), Ok(()));
+        cpu.set_pc(0x1000);
+        assert_eq!(check(&cpu), Err("PC 0x1000 is past the end of memory".to_string()));
+    }
+
+    #[test]
+    fn test_games() {
+        for name in ["PONG", "BRIX", "TETRIS"] {
+            let program = std::fs::read(format!("{}/resources/games/{}", env!("CARGO_MANIFEST_DIR"), name)).unwrap();
+            let mut run = Run::new(&program, Platform::Chip8);
+            run.input = crate::differential::random_input(1, run.frames);
+            assert_eq!(run.check(), Ok(()), "{}", name);
+        }
+    }
+}
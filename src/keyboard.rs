@@ -20,6 +20,10 @@ pub const FONT_SET: [u8; 80] = [
 #[derive(Default)]
 pub struct Keyboard {
     pub pressed_keys: [bool; 16],
+    // Press and release edges seen since the edges were last cleared,
+    // normally once per frame.
+    pressed_edges: [bool; 16],
+    released_edges: [bool; 16],
 }
 
 impl Keyboard {
@@ -29,17 +33,71 @@ impl Keyboard {
 
     pub fn clear(&mut self) {
         self.pressed_keys = [false; 16];
+        self.clear_edges();
     }
 
+    pub fn clear_edges(&mut self) {
+        self.pressed_edges = [false; 16];
+        self.released_edges = [false; 16];
+    }
+
+    pub fn end_frame(&mut self) {
+        self.clear_edges();
+    }
+
+    /// Only the low nibble of `key` counts, as on the COSMAC VIP.
     pub fn is_key_pressed(&mut self, key: u8) -> bool {
-        self.pressed_keys[key as usize]
+        self.pressed_keys[(key & 0xF) as usize]
+    }
+
+    /// First key that went down since the edges were last cleared.
+    pub fn newly_pressed(&self) -> Option<u8> {
+        self.pressed_edges.iter().position(|&edge| edge).map(|key| key as u8)
+    }
+
+    /// Whether the key went up since the edges were last cleared and is still up.
+    pub fn was_released(&self, key: u8) -> bool {
+        self.released_edges[key as usize] && !self.pressed_keys[key as usize]
     }
 
     pub fn key_down(&mut self, key: u8) {
+        if !self.pressed_keys[key as usize] {
+            self.pressed_edges[key as usize] = true;
+        }
         self.pressed_keys[key as usize] = true
     }
 
     pub fn key_up(&mut self, key: u8) {
+        if self.pressed_keys[key as usize] {
+            self.released_edges[key as usize] = true;
+        }
         self.pressed_keys[key as usize] = false;
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_edges() {
+        let mut keyboard = Keyboard::new();
+        assert_eq!(keyboard.newly_pressed(), None);
+
+        keyboard.key_down(5);
+        keyboard.key_down(2);
+        assert_eq!(keyboard.newly_pressed(), Some(2));
+        assert!(!keyboard.was_released(5));
+
+        keyboard.key_up(5);
+        assert!(keyboard.was_released(5));
+
+        keyboard.end_frame();
+        assert_eq!(keyboard.newly_pressed(), None);
+        assert!(!keyboard.was_released(5));
+
+        // holding a key down doesn't produce a new edge
+        keyboard.key_down(2);
+        assert_eq!(keyboard.newly_pressed(), None);
+    }
+}
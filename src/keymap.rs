@@ -0,0 +1,172 @@
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use wasm_bindgen::prelude::*;
+
+// Host keys of each layout, listed in the order of the COSMAC VIP keypad:
+//   1 2 3 C
+//   4 5 6 D
+//   7 8 9 E
+//   A 0 B F
+const KEYPAD: [u8; 16] = [
+    0x1, 0x2, 0x3, 0xC,
+    0x4, 0x5, 0x6, 0xD,
+    0x7, 0x8, 0x9, 0xE,
+    0xA, 0x0, 0xB, 0xF,
+];
+
+// Keys are named by `KeyboardEvent.code`, the physical key whatever the
+// layout, so this block is labelled 1 to V on QWERTY, & to V on AZERTY and
+// 1 to K on Dvorak.
+const LETTERS: [&str; 16] = [
+    "Digit1", "Digit2", "Digit3", "Digit4",
+    "KeyQ", "KeyW", "KeyE", "KeyR",
+    "KeyA", "KeyS", "KeyD", "KeyF",
+    "KeyZ", "KeyX", "KeyC", "KeyV",
+];
+
+// The numpad maps digits to themselves and the remaining keys to A-F.
+const NUMPAD: [(&str, u8); 16] = [
+    ("Numpad0", 0x0), ("Numpad1", 0x1), ("Numpad2", 0x2), ("Numpad3", 0x3),
+    ("Numpad4", 0x4), ("Numpad5", 0x5), ("Numpad6", 0x6), ("Numpad7", 0x7),
+    ("Numpad8", 0x8), ("Numpad9", 0x9), ("NumpadDivide", 0xA), ("NumpadMultiply", 0xB),
+    ("NumpadSubtract", 0xC), ("NumpadAdd", 0xD), ("NumpadEnter", 0xE), ("NumpadDecimal", 0xF),
+];
+
+/// The keyboard the keypad is played on. QWERTY, AZERTY and Dvorak use the
+/// same physical keys, whose labels differ.
+#[wasm_bindgen]
+#[derive(Clone, Copy, Debug, PartialEq, Default, Serialize, Deserialize)]
+pub enum Layout {
+    #[default]
+    Qwerty,
+    Azerty,
+    Dvorak,
+    Numpad,
+}
+
+/// Maps host keys, as reported by `KeyboardEvent.code`, to CHIP-8 keys.
+/// Overrides take precedence over the layout, e.g. for games that are easier
+/// to play with a different binding.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct Keymap {
+    layout: Layout,
+    #[serde(default)]
+    overrides: BTreeMap<String, u8>,
+}
+
+impl Keymap {
+    pub fn new(layout: Layout) -> Keymap {
+        Keymap {
+            layout,
+            overrides: BTreeMap::new(),
+        }
+    }
+
+    pub fn layout(&self) -> Layout {
+        self.layout
+    }
+
+    pub fn set_layout(&mut self, layout: Layout) {
+        self.layout = layout;
+    }
+
+    pub fn lookup(&self, host_key: &str) -> Option<u8> {
+        if let Some(&key) = self.overrides.get(host_key) {
+            return Some(key);
+        }
+        match self.layout {
+            Layout::Qwerty | Layout::Azerty | Layout::Dvorak => find(&LETTERS, host_key),
+            Layout::Numpad => NUMPAD
+                .iter()
+                .find(|(name, _)| *name == host_key)
+                .map(|&(_, key)| key),
+        }
+    }
+
+    /// Binds a host key to a CHIP-8 key on top of the layout.
+    /// Returns false if `key` isn't one of the 16 CHIP-8 keys.
+    pub fn bind(&mut self, host_key: &str, key: u8) -> bool {
+        if key > 0xF {
+            return false;
+        }
+        self.overrides.insert(host_key.to_string(), key);
+        true
+    }
+
+    pub fn clear_overrides(&mut self) {
+        self.overrides.clear();
+    }
+
+    pub fn to_json(&self) -> String {
+        serde_json::to_string(self).unwrap()
+    }
+
+    pub fn from_json(json: &str) -> Result<Keymap, String> {
+        let keymap: Keymap = serde_json::from_str(json).map_err(|err| err.to_string())?;
+        if let Some((host_key, key)) = keymap.overrides.iter().find(|(_, &key)| key > 0xF) {
+            return Err(format!("invalid CHIP-8 key {} for {}", key, host_key));
+        }
+        Ok(keymap)
+    }
+}
+
+fn find(layout: &[&str; 16], host_key: &str) -> Option<u8> {
+    layout
+        .iter()
+        .position(|&name| name == host_key)
+        .map(|idx| KEYPAD[idx])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_layouts() {
+        let mut keymap = Keymap::new(Layout::Qwerty);
+        assert_eq!(keymap.lookup("KeyQ"), Some(0x4));
+        assert_eq!(keymap.lookup("KeyV"), Some(0xF));
+        assert_eq!(keymap.lookup("q"), None);
+        assert_eq!(keymap.lookup("ShiftLeft"), None);
+
+        // Labelled A and Q on AZERTY.
+        keymap.set_layout(Layout::Azerty);
+        assert_eq!(keymap.lookup("KeyQ"), Some(0x4));
+        assert_eq!(keymap.lookup("KeyA"), Some(0x7));
+
+        keymap.set_layout(Layout::Numpad);
+        assert_eq!(keymap.lookup("Numpad7"), Some(0x7));
+        assert_eq!(keymap.lookup("Digit7"), None);
+        assert_eq!(keymap.lookup("NumpadEnter"), Some(0xE));
+    }
+
+    #[test]
+    fn test_overrides() {
+        let mut keymap = Keymap::new(Layout::Qwerty);
+        assert!(keymap.bind("ArrowUp", 0x2));
+        assert!(keymap.bind("KeyQ", 0x1));
+        assert!(!keymap.bind("KeyW", 0x10));
+        // The numpad can be bound next to the letters.
+        assert!(keymap.bind("Numpad1", 0x1));
+        assert_eq!(keymap.lookup("ArrowUp"), Some(0x2));
+        assert_eq!(keymap.lookup("KeyQ"), Some(0x1));
+        assert_eq!((keymap.lookup("Digit1"), keymap.lookup("Numpad1")), (Some(0x1), Some(0x1)));
+
+        keymap.clear_overrides();
+        assert_eq!(keymap.lookup("ArrowUp"), None);
+        assert_eq!(keymap.lookup("KeyQ"), Some(0x4));
+    }
+
+    #[test]
+    fn test_json_round_trip() {
+        let mut keymap = Keymap::new(Layout::Dvorak);
+        keymap.bind("ArrowLeft", 0x4);
+        let json = keymap.to_json();
+        assert_eq!(json, r#"{"layout":"Dvorak","overrides":{"ArrowLeft":4}}"#);
+        assert_eq!(Keymap::from_json(&json), Ok(keymap));
+
+        assert_eq!(Keymap::from_json(r#"{"layout":"Numpad"}"#), Ok(Keymap::new(Layout::Numpad)));
+        assert!(Keymap::from_json(r#"{"layout":"Qwerty","overrides":{"KeyX":16}}"#).is_err());
+        assert!(Keymap::from_json("[").is_err());
+    }
+}
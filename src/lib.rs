@@ -1,5 +1,51 @@
 mod chip8;
+mod analysis;
 mod utils;
+mod bus;
+mod colour;
+mod coverage;
 mod cpu;
 mod screen;
+mod display;
+mod decoder;
+pub mod differential;
+mod engine;
 mod keyboard;
+mod framebuffer;
+mod persistence;
+mod platform;
+mod profiler;
+mod keymap;
+mod gamepad;
+pub mod gym;
+pub mod invariants;
+mod database;
+mod cartridge;
+mod recompiler;
+mod rom;
+mod state;
+pub mod suite;
+mod symbols;
+#[cfg(feature = "megachip")]
+mod megachip;
+#[cfg(feature = "builtin-roms")]
+pub mod library;
+
+pub use crate::analysis::{analyze, Block, Cfg, Edge, EdgeKind, SelfModification};
+pub use crate::bus::{Access, Bus, Memory, MemoryHook, Watchpoints, WriteProtection};
+pub use crate::chip8::Chip8;
+pub use crate::colour::ColourAttributes;
+pub use crate::cpu::CPU;
+pub use crate::coverage::{Branch, Coverage};
+pub use crate::decoder::{decode, decode_at, Instruction};
+pub use crate::display::{Display, DrawResult};
+pub use crate::engine::Engine;
+pub use crate::keymap::Layout;
+pub use crate::persistence::PersistenceMode;
+pub use crate::platform::{Platform, Quirks};
+pub use crate::profiler::{AccessCounts, OpcodeStats, Profiler, SubroutineStats};
+pub use crate::recompiler::recompile;
+pub use crate::rom::RomError;
+pub use crate::screen::Screen;
+pub use crate::state::State;
+pub use crate::symbols::{SourceLine, Symbols};
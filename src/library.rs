@@ -0,0 +1,115 @@
+use crate::chip8::{Chip8, DEFAULT_TICK_RATE};
+use crate::persistence::PersistenceMode;
+use serde::Serialize;
+use wasm_bindgen::prelude::*;
+
+/// A game from `resources/games` compiled into the crate.
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BuiltinRom {
+    pub name: &'static str,
+    pub controls: &'static str,
+    pub tick_rate: usize,
+    pub persistence: PersistenceMode,
+    #[serde(skip)]
+    pub rom: &'static [u8],
+}
+
+macro_rules! builtin {
+    ($name:literal, $controls:literal, $persistence:ident) => {
+        builtin!($name, $controls, $persistence, DEFAULT_TICK_RATE)
+    };
+    ($name:literal, $controls:literal, $persistence:ident, $tick_rate:expr) => {
+        BuiltinRom {
+            name: $name,
+            controls: $controls,
+            tick_rate: $tick_rate,
+            persistence: PersistenceMode::$persistence,
+            rom: include_bytes!(concat!("../resources/games/", $name)),
+        }
+    };
+}
+
+pub const BUILTIN_ROMS: [BuiltinRom; 23] = [
+    builtin!("15PUZZLE", "Hex keys slide the tiles", Off),
+    builtin!("BLINKY", "3/6 up and down, 7/8 left and right", Decay),
+    builtin!("BLITZ", "5 drops a bomb", Decay),
+    builtin!("BRIX", "4/6 move the paddle", Decay),
+    builtin!("CONNECT4", "4/6 move, 5 drops a piece", Off),
+    builtin!("GUESS", "Answer with the hex keys", Off),
+    builtin!("HIDDEN", "2/4/6/8 move, 5 turns a card", Off),
+    builtin!("INVADERS", "4/6 move, 5 shoots", Decay),
+    builtin!("KALEID", "2/4/6/8 draw, 0 repeats the pattern", Off),
+    builtin!("MAZE", "No input", Off, 32),
+    builtin!("MERLIN", "4/5/7/8 pick the squares", Off),
+    builtin!("MISSILE", "8 fires", Decay),
+    builtin!("PONG", "1/4 left paddle, C/D right paddle", Decay),
+    builtin!("PONG2", "1/4 left paddle, C/D right paddle", Decay),
+    builtin!("PUZZLE", "Hex keys slide the tiles", Off),
+    builtin!("SYZYGY", "3/6 up and down, 7/8 left and right", Decay),
+    builtin!("TANK", "2/4/6/8 move, 5 fires", Decay),
+    builtin!("TETRIS", "4 rotates, 5/6 move, 1 drops", Decay),
+    builtin!("TICTAC", "1 to 9 pick a square", Off),
+    builtin!("UFO", "4/5/6 fire left, up and right", Decay),
+    builtin!("VBRIX", "1/4 move the paddle, 7 starts", Decay),
+    builtin!("VERS", "Player 1 on 7/A/1/2, player 2 on B/F/C/D", Decay),
+    builtin!("WIPEOFF", "4/6 move the paddle", Decay),
+];
+
+pub fn find(name: &str) -> Option<&'static BuiltinRom> {
+    BUILTIN_ROMS.iter().find(|rom| rom.name.eq_ignore_ascii_case(name))
+}
+
+#[wasm_bindgen]
+impl Chip8 {
+    /// Bundled games with their controls and recommended settings, as JSON.
+    pub fn list_builtin_roms() -> String {
+        serde_json::to_string(&BUILTIN_ROMS[..]).unwrap()
+    }
+
+    /// Resets the machine and loads a bundled game with its recommended settings.
+    /// Returns false if there is no game with that name.
+    pub fn load_builtin(&mut self, name: &str) -> bool {
+        let builtin = match find(name) {
+            Some(builtin) => builtin,
+            None => return false,
+        };
+        self.reset();
+        if self.load(builtin.rom, self.platform()).is_err() {
+            return false;
+        }
+        self.set_tick_rate(builtin.tick_rate);
+        self.set_persistence_mode(builtin.persistence);
+        self.set_gamepad_profile(builtin.name);
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_find() {
+        assert_eq!(find("brix").unwrap().name, "BRIX");
+        assert_eq!(find("brix").unwrap().rom.len(), 280);
+        assert!(find("ZELDA").is_none());
+    }
+
+    #[test]
+    fn test_load_builtin() {
+        let mut chip8 = Chip8::new();
+        assert!(chip8.load_builtin("MAZE"));
+        assert_eq!(chip8.tick_rate(), 32);
+        assert!(chip8.rom_info().is_some());
+        assert!(!chip8.load_builtin("ZELDA"));
+    }
+
+    #[test]
+    fn test_list_builtin_roms() {
+        let list: Vec<serde_json::Value> = serde_json::from_str(&Chip8::list_builtin_roms()).unwrap();
+        assert_eq!(list.len(), 23);
+        assert_eq!(list[3]["name"], "BRIX");
+        assert_eq!(list[3]["persistence"], "Decay");
+    }
+}
@@ -0,0 +1,261 @@
+//! MEGA-CHIP: a 256x192 mode with 256 colour sprites and digitised sound.
+//!
+//! The monochrome `Screen` keeps tracking which pixels are set, for collisions,
+//! while the colours live in an RGBA layer here.
+
+use crate::display::Display;
+use serde::Serialize;
+
+pub const WIDTH: usize = 256;
+pub const HEIGHT: usize = 192;
+/// Enough for the 24-bit addresses loaded by 01NN.
+pub const MEMORY_SIZE: usize = 0x1000000;
+
+const BLACK: u32 = 0x000000FF;
+
+/// How sprite pixels are combined with the screen, set by 080N.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Blend {
+    Normal,
+    Alpha25,
+    Alpha50,
+    Alpha75,
+    Add,
+    Multiply,
+}
+
+/// A digitised sound started by 060N.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize)]
+pub struct Sample {
+    /// Address of the first 8-bit unsigned sample.
+    pub start: usize,
+    pub length: usize,
+    /// Samples per second.
+    pub rate: u16,
+    pub looping: bool,
+}
+
+pub struct MegaChip {
+    enabled: bool,
+    /// `0xRRGGBBAA`, index 0 is transparent.
+    palette: [u32; 256],
+    sprite_width: usize,
+    sprite_height: usize,
+    alpha: u8,
+    blend: Blend,
+    pixels: Vec<u32>,
+    sample: Option<Sample>,
+    generation: u64,
+}
+
+impl Default for MegaChip {
+    fn default() -> MegaChip {
+        MegaChip {
+            enabled: false,
+            palette: [BLACK; 256],
+            sprite_width: 8,
+            sprite_height: 8,
+            alpha: 0xFF,
+            blend: Blend::Normal,
+            pixels: vec![BLACK; WIDTH * HEIGHT],
+            sample: None,
+            generation: 0,
+        }
+    }
+}
+
+impl MegaChip {
+    pub fn new() -> MegaChip {
+        Default::default()
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.enabled
+    }
+
+    /// 0011 and 0010, the CPU switches the display resolution.
+    pub fn set_enabled(&mut self, enabled: bool) {
+        self.enabled = enabled;
+        self.clear();
+    }
+
+    pub fn clear(&mut self) {
+        self.pixels.iter_mut().for_each(|pixel| *pixel = BLACK);
+        self.generation += 1;
+    }
+
+    /// 02NN: loads `count` ARGB colours from `data` into palette entries 1 to `count`.
+    pub fn load_palette(&mut self, data: &[u8], count: usize) {
+        for (entry, argb) in self.palette[1..].iter_mut().zip(data.chunks_exact(4).take(count)) {
+            *entry = u32::from_be_bytes([argb[1], argb[2], argb[3], argb[0]]);
+        }
+    }
+
+    /// 03NN, where 0 stands for 256.
+    pub fn set_sprite_width(&mut self, width: u8) {
+        self.sprite_width = if width == 0 { 256 } else { width as usize };
+    }
+
+    /// 04NN, where 0 stands for 256.
+    pub fn set_sprite_height(&mut self, height: u8) {
+        self.sprite_height = if height == 0 { 256 } else { height as usize };
+    }
+
+    /// Bytes read by DXYN: one per pixel.
+    pub fn sprite_size(&self) -> usize {
+        self.sprite_width * self.sprite_height
+    }
+
+    /// 05NN
+    pub fn set_alpha(&mut self, alpha: u8) {
+        self.alpha = alpha;
+    }
+
+    /// 080N, ignoring unknown modes.
+    pub fn set_blend(&mut self, mode: u8) {
+        self.blend = match mode {
+            0 => Blend::Normal,
+            1 => Blend::Alpha25,
+            2 => Blend::Alpha50,
+            3 => Blend::Alpha75,
+            4 => Blend::Add,
+            5 => Blend::Multiply,
+            _ => self.blend,
+        };
+    }
+
+    /// 060N: `memory[start..]` holds a 16-bit rate, a 24-bit length and a
+    /// padding byte before the samples. N = 0 loops the sound.
+    pub fn play(&mut self, memory: &[u8], start: usize, looping: bool) {
+        let header = match memory.get(start..start + 6) {
+            Some(header) => header,
+            None => return,
+        };
+        let rate = u16::from_be_bytes([header[0], header[1]]);
+        let length = u32::from_be_bytes([0, header[2], header[3], header[4]]) as usize;
+        let start = start + 6;
+        self.sample = Some(Sample {
+            start,
+            length: length.min(memory.len() - start),
+            rate,
+            looping,
+        });
+    }
+
+    /// 0700
+    pub fn stop(&mut self) {
+        self.sample = None;
+    }
+
+    pub fn sample(&self) -> Option<Sample> {
+        self.sample
+    }
+
+    /// DXYN in MEGA-CHIP mode: draws a sprite of palette indices, one byte per
+    /// pixel. Returns whether it overlapped set pixels.
+    pub fn draw<D: Display>(&mut self, sprite: &[u8], x: usize, y: usize, screen: &mut D) -> bool {
+        let mut collided = false;
+        let rows = sprite.chunks(self.sprite_width).take(self.sprite_height);
+        for (row, colours) in rows.enumerate().filter(|(row, _)| y + row < HEIGHT) {
+            for (col, &colour) in colours.iter().enumerate().filter(|(col, _)| x + col < WIDTH) {
+                if colour == 0 {
+                    continue;
+                }
+                let (r, c) = (y + row, x + col);
+                collided |= screen.plot(r, c);
+                let idx = r * WIDTH + c;
+                self.pixels[idx] = self.blend(self.palette[colour as usize], self.pixels[idx]);
+            }
+        }
+        screen.end_draw();
+        self.generation += 1;
+        collided
+    }
+
+    fn blend(&self, src: u32, dst: u32) -> u32 {
+        let [sr, sg, sb, sa] = src.to_be_bytes();
+        let [dr, dg, db, _] = dst.to_be_bytes();
+        let opacity = match self.blend {
+            Blend::Normal => sa as u32 * self.alpha as u32 / 255,
+            Blend::Alpha25 => 64,
+            Blend::Alpha50 => 128,
+            Blend::Alpha75 => 191,
+            Blend::Add | Blend::Multiply => 255,
+        };
+        let channel = |s: u8, d: u8| -> u8 {
+            let (s, d) = (s as u32, d as u32);
+            match self.blend {
+                Blend::Add => (s + d).min(255) as u8,
+                Blend::Multiply => (s * d / 255) as u8,
+                _ => ((s * opacity + d * (255 - opacity)) / 255) as u8,
+            }
+        };
+        u32::from_be_bytes([channel(sr, dr), channel(sg, dg), channel(sb, db), 0xFF])
+    }
+
+    /// The 256x192 colour layer as `0xRRGGBBAA`.
+    pub fn pixels(&self) -> &[u32] {
+        &self.pixels
+    }
+
+    /// Increases every time the colour layer changes.
+    pub fn generation(&self) -> u64 {
+        self.generation
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::screen::Screen;
+
+    fn megachip() -> (MegaChip, Screen) {
+        let mut mega = MegaChip::new();
+        let mut screen = Screen::new();
+        screen.set_resolution(WIDTH, HEIGHT);
+        mega.set_enabled(true);
+        mega.load_palette(&[0xFF, 0xFF, 0x00, 0x00, 0x80, 0x00, 0x00, 0xFF], 2);
+        (mega, screen)
+    }
+
+    #[test]
+    fn test_draw() {
+        let (mut mega, mut screen) = megachip();
+        mega.set_sprite_width(2);
+        mega.set_sprite_height(1);
+        assert!(!mega.draw(&[1, 0], 10, 5, &mut screen));
+        assert_eq!(mega.pixels()[5 * WIDTH + 10], 0xFF0000FF);
+        assert!(screen.get_pixel(5, 10));
+        assert!(!screen.get_pixel(5, 11));
+
+        // half transparent blue over red
+        assert!(mega.draw(&[2, 2], 10, 5, &mut screen));
+        assert_eq!(mega.pixels()[5 * WIDTH + 10], 0x7F0080FF);
+        assert_eq!(mega.pixels()[5 * WIDTH + 11], 0x000080FF);
+    }
+
+    #[test]
+    fn test_blend_modes() {
+        let (mut mega, mut screen) = megachip();
+        mega.set_sprite_width(1);
+        mega.set_sprite_height(1);
+        mega.draw(&[1], 0, 0, &mut screen);
+        mega.set_blend(4);
+        mega.draw(&[2], 0, 0, &mut screen);
+        assert_eq!(mega.pixels()[0], 0xFF00FFFF);
+        mega.set_blend(5);
+        mega.draw(&[1], 0, 0, &mut screen);
+        assert_eq!(mega.pixels()[0], 0xFF0000FF);
+    }
+
+    #[test]
+    fn test_play_sample() {
+        let mut mega = MegaChip::new();
+        let mut memory = vec![0; 32];
+        memory[8..14].copy_from_slice(&[0x1F, 0x40, 0x00, 0x00, 0x04, 0x00]);
+        mega.play(&memory, 8, true);
+        assert_eq!(mega.sample(), Some(Sample { start: 14, length: 4, rate: 8000, looping: true }));
+        mega.stop();
+        assert_eq!(mega.sample(), None);
+    }
+}
@@ -0,0 +1,164 @@
+use crate::screen::Screen;
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use wasm_bindgen::prelude::*;
+
+const DEFAULT_DECAY: f32 = 0.6;
+const DEFAULT_BLEND_FRAMES: usize = 2;
+
+#[wasm_bindgen]
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub enum PersistenceMode {
+    /// Pixels are shown exactly as they are on the screen.
+    Off,
+    /// Set pixels light up fully and fade out by the decay factor every frame,
+    /// like the phosphor of the original displays.
+    Decay,
+    /// A pixel is lit if it was set in any of the last N frames.
+    Blend,
+}
+
+/// Post-processing over `Screen` that hides the flicker caused by games
+/// erasing and redrawing sprites with XOR. Produces one intensity per pixel,
+/// 0 for unlit up to 255 for fully lit.
+pub struct Persistence {
+    mode: PersistenceMode,
+    decay: f32,
+    blend_frames: usize,
+    intensity: Vec<u8>,
+    history: VecDeque<Vec<bool>>,
+}
+
+impl Default for Persistence {
+    fn default() -> Persistence {
+        Persistence {
+            mode: PersistenceMode::Off,
+            decay: DEFAULT_DECAY,
+            blend_frames: DEFAULT_BLEND_FRAMES,
+            intensity: Vec::new(),
+            history: VecDeque::new(),
+        }
+    }
+}
+
+impl Persistence {
+    pub fn new() -> Persistence {
+        Default::default()
+    }
+
+    pub fn mode(&self) -> PersistenceMode {
+        self.mode
+    }
+
+    pub fn set_mode(&mut self, mode: PersistenceMode) {
+        self.mode = mode;
+        self.history.clear();
+    }
+
+    /// Fraction of its intensity an unlit pixel keeps every frame, between 0 and 1.
+    #[allow(clippy::manual_clamp)]
+    pub fn set_decay(&mut self, decay: f32) {
+        self.decay = decay.max(0.0).min(1.0);
+    }
+
+    pub fn set_blend_frames(&mut self, frames: usize) {
+        self.blend_frames = frames.max(1);
+        while self.history.len() > self.blend_frames {
+            self.history.pop_front();
+        }
+    }
+
+    pub fn intensity(&self) -> &[u8] {
+        &self.intensity
+    }
+
+    /// Advances the effect by one frame. Returns whether any intensity changed.
+    pub fn update(&mut self, screen: &Screen) -> bool {
+        let pixels = screen.pixels();
+        self.intensity.resize(pixels.len(), 0);
+
+        if self.mode == PersistenceMode::Blend {
+            if self.history.len() == self.blend_frames {
+                self.history.pop_front();
+            }
+            self.history.push_back(pixels.to_vec());
+        }
+
+        let mut changed = false;
+        for idx in 0..pixels.len() {
+            let level = match self.mode {
+                PersistenceMode::Off => lit(pixels[idx]),
+                PersistenceMode::Decay => {
+                    if pixels[idx] {
+                        u8::MAX
+                    } else {
+                        (self.intensity[idx] as f32 * self.decay) as u8
+                    }
+                }
+                PersistenceMode::Blend => lit(self.history.iter().any(|frame| frame[idx])),
+            };
+            changed |= level != self.intensity[idx];
+            self.intensity[idx] = level;
+        }
+        changed
+    }
+}
+
+fn lit(on: bool) -> u8 {
+    if on { u8::MAX } else { 0 }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_decay_fades_out() {
+        let mut screen = Screen::new();
+        let mut persistence = Persistence::new();
+        persistence.set_mode(PersistenceMode::Decay);
+        persistence.set_decay(0.5);
+
+        screen.set_pixel(0, 0);
+        assert!(persistence.update(&screen));
+        assert_eq!(persistence.intensity()[0], 255);
+
+        screen.set_pixel(0, 0);
+        assert!(persistence.update(&screen));
+        assert_eq!(persistence.intensity()[0], 127);
+        persistence.update(&screen);
+        assert_eq!(persistence.intensity()[0], 63);
+
+        for _ in 0..8 {
+            persistence.update(&screen);
+        }
+        assert_eq!(persistence.intensity()[0], 0);
+        assert!(!persistence.update(&screen));
+    }
+
+    #[test]
+    fn test_blend_keeps_last_frames() {
+        let mut screen = Screen::new();
+        let mut persistence = Persistence::new();
+        persistence.set_mode(PersistenceMode::Blend);
+        persistence.set_blend_frames(2);
+
+        screen.set_pixel(0, 0);
+        persistence.update(&screen);
+        screen.set_pixel(0, 0);
+        persistence.update(&screen);
+        assert_eq!(persistence.intensity()[0], 255);
+
+        persistence.update(&screen);
+        assert_eq!(persistence.intensity()[0], 0);
+    }
+
+    #[test]
+    fn test_off_mirrors_screen() {
+        let mut screen = Screen::new();
+        let mut persistence = Persistence::new();
+        screen.set_pixel(0, 1);
+        persistence.update(&screen);
+        assert_eq!(&persistence.intensity()[0..2], &[0, 255]);
+    }
+}
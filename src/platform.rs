@@ -0,0 +1,134 @@
+use serde::{Deserialize, Serialize};
+use wasm_bindgen::prelude::*;
+
+/// Where programs are loaded and start executing on most platforms.
+pub const START_ADDR: u16 = 0x200;
+
+#[wasm_bindgen]
+#[derive(Clone, Copy, Debug, PartialEq, Default, Serialize, Deserialize)]
+pub enum Platform {
+    /// The original COSMAC VIP interpreter.
+    #[default]
+    Chip8,
+    /// SUPER-CHIP 1.1, adding the 128x64 hi-res mode.
+    SuperChip,
+    /// CHIP-8 on the ETI-660, where programs start at 0x600.
+    Eti660,
+    /// The two-page hi-res CHIP-8 for the VIP, with a 64x64 screen.
+    HiresChip8,
+    /// CHIP-8X for the VIP with the VP-590 colour board and a second keypad.
+    Chip8X,
+    /// MEGA-CHIP, SUPER-CHIP with a 256x192 colour mode and 16 MiB of memory.
+    /// Without the `megachip` feature it runs as plain SUPER-CHIP.
+    MegaChip,
+}
+
+/// Behaviours that differ between interpreters for the same opcode.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Quirks {
+    /// Sprite pixels past the right or bottom edge wrap around to the other
+    /// side instead of being clipped.
+    pub wrap_sprites: bool,
+    /// In hi-res mode DXYN sets VF to the number of sprite rows that collided
+    /// or were clipped by the bottom edge, instead of just 0 or 1.
+    pub count_collided_rows: bool,
+    /// FX0A completes when the key is released rather than when it is pressed,
+    /// like the COSMAC VIP.
+    pub key_wait_release: bool,
+    /// 00FE/00FF switch between low and high resolution, and DXY0 draws 16x16 sprites.
+    pub hires: bool,
+    /// 8XY6/8XYE shift VX in place instead of storing the shifted VY in VX.
+    pub shift: bool,
+    /// FX55/FX65 leave I unchanged instead of advancing it past the last register.
+    pub memory_leave_i_unchanged: bool,
+    /// FX55/FX65 advance I by X instead of X + 1 (CHIP-48).
+    pub memory_increment_by_x: bool,
+    /// BNNN jumps to NNN plus VX, where X is the highest nibble of NNN, instead of V0.
+    pub jump: bool,
+    /// 8XY1/8XY2/8XY3 reset VF to 0.
+    pub logic: bool,
+    /// DXYN waits for the next frame, so at most one sprite is drawn per frame.
+    pub vblank: bool,
+}
+
+impl Platform {
+    pub const ALL: [Platform; 6] = [
+        Platform::Chip8,
+        Platform::SuperChip,
+        Platform::Eti660,
+        Platform::HiresChip8,
+        Platform::Chip8X,
+        Platform::MegaChip,
+    ];
+
+    pub fn load_address(&self) -> u16 {
+        match self {
+            Platform::Eti660 => 0x600,
+            Platform::Chip8X => 0x300,
+            _ => START_ADDR,
+        }
+    }
+
+    pub fn memory_size(&self) -> usize {
+        match self {
+            #[cfg(feature = "megachip")]
+            Platform::MegaChip => crate::megachip::MEMORY_SIZE,
+            _ => 4096,
+        }
+    }
+
+    /// Where execution starts once `program` is loaded.
+    pub fn entry_point(&self, program: &[u8]) -> u16 {
+        match self {
+            // Hi-res programs start with a jump to the patched interpreter at
+            // 0x260, which then runs the program proper from 0x2C0.
+            Platform::HiresChip8 if program.starts_with(&[0x12, 0x60]) => 0x2C0,
+            _ => self.load_address(),
+        }
+    }
+
+    /// Screen size outside of SUPER-CHIP hi-res mode.
+    pub fn resolution(&self) -> (usize, usize) {
+        match self {
+            Platform::HiresChip8 => (64, 64),
+            _ => (64, 32),
+        }
+    }
+
+    pub fn quirks(&self) -> Quirks {
+        match self {
+            Platform::Chip8 | Platform::Eti660 | Platform::HiresChip8 | Platform::Chip8X => Quirks {
+                wrap_sprites: false,
+                count_collided_rows: false,
+                key_wait_release: true,
+                hires: false,
+                shift: true,
+                memory_leave_i_unchanged: true,
+                memory_increment_by_x: false,
+                jump: false,
+                logic: false,
+                vblank: false,
+            },
+            Platform::MegaChip => Platform::SuperChip.quirks(),
+            Platform::SuperChip => Quirks {
+                wrap_sprites: false,
+                count_collided_rows: true,
+                key_wait_release: false,
+                hires: true,
+                shift: true,
+                memory_leave_i_unchanged: true,
+                memory_increment_by_x: false,
+                jump: true,
+                logic: false,
+                vblank: false,
+            },
+        }
+    }
+}
+
+impl Default for Quirks {
+    fn default() -> Quirks {
+        Platform::default().quirks()
+    }
+}
@@ -0,0 +1,390 @@
+//! Profiling: how often each address is fetched, read and written, how often
+//! each kind of instruction runs, and where the cycles go.
+
+use crate::bus::{Access, MemoryHook};
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::fmt::Write;
+
+/// Per-address access counters, attached to the memory as a hook.
+#[derive(Default)]
+pub struct AccessCounts {
+    pub fetches: Vec<u32>,
+    pub reads: Vec<u32>,
+    pub writes: Vec<u32>,
+}
+
+impl AccessCounts {
+    /// Counters for `size` bytes. They grow if addresses past the end are
+    /// accessed, e.g. after switching to MEGA-CHIP.
+    pub fn new(size: usize) -> AccessCounts {
+        AccessCounts {
+            fetches: vec![0; size],
+            reads: vec![0; size],
+            writes: vec![0; size],
+        }
+    }
+
+    /// Fetches, reads and writes of `addr` together.
+    pub fn total(&self, addr: usize) -> u32 {
+        [&self.fetches, &self.reads, &self.writes]
+            .iter()
+            .map(|counts| counts.get(addr).copied().unwrap_or(0))
+            .sum()
+    }
+
+    pub fn len(&self) -> usize {
+        self.fetches.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.fetches.is_empty()
+    }
+}
+
+fn count(counts: &mut Vec<u32>, addr: usize) {
+    if addr >= counts.len() {
+        counts.resize(addr + 1, 0);
+    }
+    counts[addr] = counts[addr].saturating_add(1);
+}
+
+impl MemoryHook for AccessCounts {
+    fn on_read(&mut self, addr: usize, _value: u8, access: Access) {
+        match access {
+            Access::Fetch => count(&mut self.fetches, addr),
+            _ => count(&mut self.reads, addr),
+        }
+    }
+
+    fn on_write(&mut self, addr: usize, _old: u8, _value: u8) -> bool {
+        count(&mut self.writes, addr);
+        true
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize)]
+pub struct OpcodeStats {
+    pub count: u64,
+    /// Cycles taken, which is more than `count` for FX0A waiting for a key.
+    pub cycles: u64,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize)]
+pub struct SubroutineStats {
+    pub addr: u16,
+    pub calls: u64,
+    /// Cycles from entering the subroutine to returning from it, including
+    /// the subroutines it calls.
+    pub inclusive_cycles: u64,
+}
+
+// A subroutine on the call stack.
+struct Frame {
+    addr: u16,
+    entered: u64,
+}
+
+/// Instruction and subroutine statistics, fed by the CPU after every cycle.
+#[derive(Default)]
+pub struct Profiler {
+    cycles: u64,
+    opcodes: BTreeMap<&'static str, OpcodeStats>,
+    last: Option<&'static str>,
+    frames: Vec<Frame>,
+    subroutines: BTreeMap<u16, SubroutineStats>,
+    stack_overflows: u64,
+    stack_underflows: u64,
+}
+
+impl Profiler {
+    pub fn new() -> Profiler {
+        Default::default()
+    }
+
+    pub fn cycles(&self) -> u64 {
+        self.cycles
+    }
+
+    /// Records an executed `opcode`. `depth` is the call stack depth after it
+    /// ran and `pc` the next instruction, which starts a subroutine when the
+    /// stack got deeper.
+    pub fn instruction(&mut self, opcode: u16, depth: usize, pc: u16) {
+        let class = opcode_class(opcode);
+        let stats = self.opcodes.entry(class).or_default();
+        stats.count += 1;
+        stats.cycles += 1;
+        self.last = Some(class);
+        self.cycles += 1;
+
+        while self.frames.len() > depth {
+            self.leave();
+        }
+        if depth > self.frames.len() {
+            self.subroutines.entry(pc).or_insert(SubroutineStats { addr: pc, ..Default::default() }).calls += 1;
+            self.frames.push(Frame { addr: pc, entered: self.cycles });
+        }
+    }
+
+    /// Records a cycle spent waiting for a key, charged to the FX0A.
+    pub fn wait(&mut self) {
+        self.cycles += 1;
+        if let Some(class) = self.last {
+            self.opcodes.entry(class).or_default().cycles += 1;
+        }
+    }
+
+    /// Records a push onto a full stack, which dropped the oldest address.
+    pub fn stack_overflow(&mut self) {
+        self.stack_overflows += 1;
+    }
+
+    /// Records a pop off an empty stack.
+    pub fn stack_underflow(&mut self) {
+        self.stack_underflows += 1;
+    }
+
+    /// Stack overflows and underflows while profiling.
+    pub fn stack_faults(&self) -> (u64, u64) {
+        (self.stack_overflows, self.stack_underflows)
+    }
+
+    /// Forgets the call stack, for when the CPU is reset.
+    pub fn reset_calls(&mut self) {
+        self.frames.clear();
+    }
+
+    fn leave(&mut self) {
+        let frame = self.frames.pop().unwrap();
+        // Recursive calls are already covered by the outermost one.
+        if self.frames.iter().all(|outer| outer.addr != frame.addr) {
+            self.subroutines.get_mut(&frame.addr).unwrap().inclusive_cycles += self.cycles - frame.entered;
+        }
+    }
+
+    /// Statistics per kind of instruction, keyed by patterns like `8XY4`.
+    pub fn opcodes(&self) -> &BTreeMap<&'static str, OpcodeStats> {
+        &self.opcodes
+    }
+
+    /// Every subroutine called so far, by decreasing inclusive time. Those
+    /// still running count the cycles up to now.
+    pub fn subroutines(&self) -> Vec<SubroutineStats> {
+        let mut subroutines = self.subroutines.clone();
+        for (depth, frame) in self.frames.iter().enumerate() {
+            if self.frames[..depth].iter().all(|outer| outer.addr != frame.addr) {
+                subroutines.get_mut(&frame.addr).unwrap().inclusive_cycles += self.cycles - frame.entered;
+            }
+        }
+        let mut subroutines: Vec<_> = subroutines.into_values().collect();
+        subroutines.sort_by(|a, b| b.inclusive_cycles.cmp(&a.inclusive_cycles).then(a.addr.cmp(&b.addr)));
+        subroutines
+    }
+
+    /// A text report of the `top` busiest addresses, instructions and subroutines.
+    pub fn report(&self, counts: Option<&AccessCounts>, top: usize) -> String {
+        let mut report = String::new();
+        let cycles = self.cycles.max(1) as f64;
+        writeln!(report, "{} cycles", self.cycles).unwrap();
+        if self.stack_overflows > 0 || self.stack_underflows > 0 {
+            writeln!(report, "{} stack overflows, {} underflows", self.stack_overflows, self.stack_underflows).unwrap();
+        }
+
+        if let Some(counts) = counts {
+            let mut addresses: Vec<usize> = (0..counts.len()).filter(|&addr| counts.total(addr) > 0).collect();
+            addresses.sort_by_key(|&addr| (std::cmp::Reverse(counts.total(addr)), addr));
+            writeln!(report, "\naddress   fetches     reads    writes").unwrap();
+            for &addr in addresses.iter().take(top) {
+                writeln!(
+                    report,
+                    "{:#06X} {:>10}{:>10}{:>10}",
+                    addr, counts.fetches[addr], counts.reads[addr], counts.writes[addr]
+                )
+                .unwrap();
+            }
+        }
+
+        let mut opcodes: Vec<_> = self.opcodes.iter().collect();
+        opcodes.sort_by_key(|(class, stats)| (std::cmp::Reverse(stats.cycles), **class));
+        writeln!(report, "\nopcode       count    cycles").unwrap();
+        for (class, stats) in opcodes.into_iter().take(top) {
+            writeln!(
+                report,
+                "{:<6}{:>12}{:>10} {:5.1}%",
+                class,
+                stats.count,
+                stats.cycles,
+                stats.cycles as f64 * 100.0 / cycles
+            )
+            .unwrap();
+        }
+
+        writeln!(report, "\nsubroutine   calls inclusive").unwrap();
+        for subroutine in self.subroutines().into_iter().take(top) {
+            writeln!(
+                report,
+                "{:#06X}{:>12}{:>10} {:5.1}%",
+                subroutine.addr,
+                subroutine.calls,
+                subroutine.inclusive_cycles,
+                subroutine.inclusive_cycles as f64 * 100.0 / cycles
+            )
+            .unwrap();
+        }
+        report
+    }
+}
+
+/// The kind of instruction `opcode` is, written with X, Y, N, NN and NNN for
+/// its operands, or `????` for unknown opcodes.
+pub fn opcode_class(opcode: u16) -> &'static str {
+    let nn = opcode & 0xFF;
+    match opcode >> 12 {
+        0x0 => match opcode {
+            0x0010 => "0010",
+            0x0011 => "0011",
+            0x00E0 => "00E0",
+            0x00EE => "00EE",
+            0x00FB => "00FB",
+            0x00FC => "00FC",
+            0x00FD => "00FD",
+            0x00FE => "00FE",
+            0x00FF => "00FF",
+            0x0230 => "0230",
+            0x02A0 => "02A0",
+            _ if opcode & 0xFFF0 == 0x00C0 => "00CN",
+            _ if opcode & 0xFFF0 == 0x00D0 => "00DN",
+            0x0100..=0x01FF => "01NN",
+            0x0200..=0x02FF => "02NN",
+            0x0300..=0x03FF => "03NN",
+            0x0400..=0x04FF => "04NN",
+            0x0500..=0x05FF => "05NN",
+            0x0600..=0x06FF => "06NN",
+            0x0700..=0x07FF => "07NN",
+            0x0800..=0x08FF => "08NN",
+            _ => "0NNN",
+        },
+        0x1 => "1NNN",
+        0x2 => "2NNN",
+        0x3 => "3XNN",
+        0x4 => "4XNN",
+        0x5 => match opcode & 0xF {
+            0x0 => "5XY0",
+            0x1 => "5XY1",
+            _ => "????",
+        },
+        0x6 => "6XNN",
+        0x7 => "7XNN",
+        0x8 => match opcode & 0xF {
+            0x0 => "8XY0",
+            0x1 => "8XY1",
+            0x2 => "8XY2",
+            0x3 => "8XY3",
+            0x4 => "8XY4",
+            0x5 => "8XY5",
+            0x6 => "8XY6",
+            0x7 => "8XY7",
+            0xE => "8XYE",
+            _ => "????",
+        },
+        0x9 if opcode & 0xF == 0 => "9XY0",
+        0xA => "ANNN",
+        0xB => "BNNN",
+        0xC => "CXNN",
+        0xD => "DXYN",
+        0xE => match nn {
+            0x9E => "EX9E",
+            0xA1 => "EXA1",
+            0xF2 => "EXF2",
+            0xF5 => "EXF5",
+            _ => "????",
+        },
+        0xF => match nn {
+            0x07 => "FX07",
+            0x0A => "FX0A",
+            0x15 => "FX15",
+            0x18 => "FX18",
+            0x1E => "FX1E",
+            0x29 => "FX29",
+            0x30 => "FX30",
+            0x33 => "FX33",
+            0x55 => "FX55",
+            0x65 => "FX65",
+            0x75 => "FX75",
+            0x85 => "FX85",
+            0xF8 => "FXF8",
+            0xFB => "FXFB",
+            _ => "????",
+        },
+        _ => "????",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bus::{Bus, Memory};
+
+    #[test]
+    fn test_access_counts() {
+        let mut memory = Memory::new(16);
+        memory.add_hook(AccessCounts::new(16));
+        memory.read(2, Access::Fetch);
+        memory.read(3, Access::Fetch);
+        memory.read(3, Access::Read);
+        memory.write(3, 1);
+        let counts = memory.hook::<AccessCounts>().unwrap();
+        assert_eq!(counts.fetches[2..4], [1, 1]);
+        assert_eq!(counts.reads[3], 1);
+        assert_eq!(counts.writes[3], 1);
+        assert_eq!(counts.total(3), 3);
+    }
+
+    #[test]
+    fn test_opcode_class() {
+        assert_eq!(opcode_class(0x00E0), "00E0");
+        assert_eq!(opcode_class(0x00C4), "00CN");
+        assert_eq!(opcode_class(0x0123), "01NN");
+        assert_eq!(opcode_class(0x0ABC), "0NNN");
+        assert_eq!(opcode_class(0x8AB4), "8XY4");
+        assert_eq!(opcode_class(0x8AB9), "????");
+        assert_eq!(opcode_class(0xF10A), "FX0A");
+    }
+
+    #[test]
+    fn test_opcode_stats() {
+        let mut profiler = Profiler::new();
+        profiler.instruction(0x6001, 0, 0x202);
+        profiler.instruction(0x6102, 0, 0x204);
+        profiler.instruction(0xF00A, 0, 0x206);
+        profiler.wait();
+        profiler.wait();
+        assert_eq!(profiler.cycles(), 5);
+        assert_eq!(profiler.opcodes()["6XNN"], OpcodeStats { count: 2, cycles: 2 });
+        assert_eq!(profiler.opcodes()["FX0A"], OpcodeStats { count: 1, cycles: 3 });
+    }
+
+    #[test]
+    fn test_subroutines() {
+        let mut profiler = Profiler::new();
+        // 0x200 calls 0x300, which calls itself once and then 0x400.
+        profiler.instruction(0x2300, 1, 0x300);
+        profiler.instruction(0x2300, 2, 0x300);
+        profiler.instruction(0x00EE, 1, 0x302);
+        profiler.instruction(0x2400, 2, 0x400);
+        profiler.instruction(0x6000, 2, 0x402);
+        profiler.instruction(0x00EE, 1, 0x304);
+        profiler.instruction(0x00EE, 0, 0x202);
+        profiler.instruction(0x2400, 1, 0x400);
+        profiler.instruction(0x6000, 1, 0x402);
+
+        let subroutines = profiler.subroutines();
+        assert_eq!(subroutines[0], SubroutineStats { addr: 0x300, calls: 2, inclusive_cycles: 6 });
+        // Two cycles in the call from 0x300 and one in the unfinished call.
+        assert_eq!(subroutines[1], SubroutineStats { addr: 0x400, calls: 2, inclusive_cycles: 3 });
+
+        let report = profiler.report(None, 1);
+        assert!(report.contains("0x0300           2         6"));
+        assert!(!report.contains("0x0400"));
+    }
+}
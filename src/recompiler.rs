@@ -0,0 +1,266 @@
+//! Static recompilation of a ROM into a Rust module.
+//!
+//! Every basic block found by `analysis` becomes a function working on the
+//! `CPU` state. Register arithmetic, jumps, calls and skips are written out in
+//! Rust; everything else, such as drawing, timers and keys, runs through
+//! `CPU::step`. The generated `run_frame` runs a block only while the bytes in
+//! memory are still those it was compiled from, and falls back to the
+//! interpreter elsewhere: after computed jumps that land inside a block, in
+//! code that was written over, and while FX0A waits for a key.
+//!
+//! The module also has a `verify` function, and a test calling it, that runs
+//! the recompiled program next to the interpreter and compares them after
+//! every frame.
+
+use crate::analysis::{self, Block};
+use crate::decoder::Instruction;
+use crate::platform::Platform;
+use std::fmt::Write;
+
+/// Rust source of a module running `program` on `platform`. `name` only
+/// appears in the documentation.
+pub fn recompile(program: &[u8], platform: Platform, name: &str) -> String {
+    let cfg = analysis::analyze(program, platform);
+    let load_address = platform.load_address();
+    let blocks: Vec<&Block> = cfg
+        .blocks
+        .values()
+        .filter(|block| {
+            let written = cfg.self_modifying.iter().any(|modification| (block.start..block.end()).contains(&modification.target));
+            // 01NN is a no-op outside the MEGA-CHIP mode, so the next word
+            // may be an instruction too.
+            let long = block.instructions.iter().any(|(_, instruction)| matches!(instruction, Instruction::LoadLongI(_)));
+            !written && !long
+        })
+        .collect();
+
+    let mut out = String::new();
+    writeln!(out, "//! `{}` recompiled from CHIP-8 to Rust by `chip8-cli recompile`.", name).unwrap();
+    writeln!(out, "\nuse chip8::{{Bus, Display, Platform, CPU}};\n").unwrap();
+    writeln!(out, "pub const PLATFORM: Platform = Platform::{:?};", platform).unwrap();
+    writeln!(out, "const LOAD_ADDRESS: usize = {:#05X};\n", load_address).unwrap();
+    writeln!(out, "pub const PROGRAM: [u8; {}] = [", program.len()).unwrap();
+    for line in program.chunks(12) {
+        let bytes: Vec<String> = line.iter().map(|byte| format!("{:#04X}", byte)).collect();
+        writeln!(out, "    {},", bytes.join(", ")).unwrap();
+    }
+    writeln!(out, "];").unwrap();
+
+    out.push_str(
+        "
+/// Runs one frame like `CPU::run_frame`, with the recompiled blocks wherever
+/// the code in memory is still the original.
+pub fn run_frame<D: Display, B: Bus>(cpu: &mut CPU<D, B>, mut cycles: usize) {
+    while cycles > 0 {
+        if !cpu.is_waiting_for_key() && run_block(cpu, &mut cycles) {
+            continue;
+        }
+        cycles -= 1;
+        if cpu.step() {
+            break;
+        }
+    }
+    cpu.end_frame();
+}
+
+/// Runs the recompiled program and the interpreter side by side for `frames`
+/// frames of `cycles` cycles, with the same random numbers, and returns the
+/// first frame after which their screens or registers differ.
+pub fn verify(frames: usize, cycles: usize) -> Result<(), usize> {
+    let mut recompiled = CPU::with_platform(PLATFORM);
+    let mut interpreted = CPU::with_platform(PLATFORM);
+    for cpu in [&mut recompiled, &mut interpreted] {
+        cpu.load_program(&PROGRAM).unwrap();
+        cpu.seed_random(0);
+    }
+    for frame in 0..frames {
+        run_frame(&mut recompiled, cycles);
+        interpreted.run_frame(cycles);
+        let same = recompiled.pc() == interpreted.pc()
+            && recompiled.i() == interpreted.i()
+            && recompiled.registers() == interpreted.registers()
+            && recompiled.get_screen().pixels() == interpreted.get_screen().pixels();
+        if !same {
+            return Err(frame);
+        }
+    }
+    Ok(())
+}
+
+// Whether memory from `start` to `end` still holds the program.
+fn is_original<D: Display, B: Bus>(cpu: &CPU<D, B>, start: usize, end: usize) -> bool {
+    cpu.bus().bytes().get(start..end) == PROGRAM.get(start - LOAD_ADDRESS..end - LOAD_ADDRESS)
+}
+
+// Runs the block at PC if there is one and the frame has cycles left for all
+// of it.
+fn run_block<D: Display, B: Bus>(cpu: &mut CPU<D, B>, cycles: &mut usize) -> bool {
+    match cpu.pc() {
+",
+    );
+    for block in blocks.iter() {
+        writeln!(
+            out,
+            "        {:#05X} if *cycles >= {} && is_original(cpu, {:#05X}, {:#05X}) => block_{:04x}(cpu, cycles),",
+            block.start,
+            block.instructions.len(),
+            block.start,
+            block.end(),
+            block.start
+        )
+        .unwrap();
+    }
+    out.push_str("        _ => return false,\n    }\n    true\n}\n");
+
+    for block in blocks {
+        emit_block(&mut out, block);
+    }
+
+    out.push_str(
+        "
+#[cfg(test)]
+mod tests {
+    #[test]
+    fn test_matches_interpreter() {
+        assert_eq!(super::verify(600, 8), Ok(()));
+    }
+}
+",
+    );
+    out
+}
+
+fn emit_block(out: &mut String, block: &Block) {
+    use Instruction::*;
+
+    writeln!(out, "\nfn block_{:04x}<D: Display, B: Bus>(cpu: &mut CPU<D, B>, cycles: &mut usize) {{", block.start).unwrap();
+    writeln!(out, "    *cycles -= {};", block.instructions.len()).unwrap();
+    let uses_quirks = block
+        .instructions
+        .iter()
+        .any(|(_, instruction)| matches!(instruction, Or(..) | And(..) | Xor(..) | ShiftRight(..) | ShiftLeft(..)));
+    if uses_quirks {
+        writeln!(out, "    let quirks = cpu.quirks();").unwrap();
+    }
+
+    for (index, &(addr, instruction)) in block.instructions.iter().enumerate() {
+        let last = index + 1 == block.instructions.len();
+        let next = addr + instruction.size() as u16;
+        writeln!(out, "    // {:#05X}  {}", addr, instruction).unwrap();
+        let registers = |code: &str| format!("    {{\n        let v = cpu.registers_mut();\n{}    }}\n", code);
+        let logic = "        if quirks.logic {\n            v[0xF] = 0;\n        }\n";
+        let code = match instruction {
+            LoadImm(x, nn) => registers(&format!("        v[{:#X}] = {:#04X};\n", x, nn)),
+            AddImm(x, nn) => registers(&format!("        v[{:#X}] = v[{:#X}].wrapping_add({:#04X});\n", x, x, nn)),
+            Move(x, y) if x == y => String::new(),
+            Move(x, y) => registers(&format!("        v[{:#X}] = v[{:#X}];\n", x, y)),
+            Or(x, y) | And(x, y) if x == y => registers(logic),
+            Or(x, y) => registers(&format!("        v[{:#X}] |= v[{:#X}];\n{}", x, y, logic)),
+            And(x, y) => registers(&format!("        v[{:#X}] &= v[{:#X}];\n{}", x, y, logic)),
+            Xor(x, y) if x == y => registers(&format!("        v[{:#X}] = 0;\n{}", x, logic)),
+            Xor(x, y) => registers(&format!("        v[{:#X}] ^= v[{:#X}];\n{}", x, y, logic)),
+            Add(x, y) => registers(&format!(
+                "        let (result, carry) = v[{:#X}].overflowing_add(v[{:#X}]);\n        v[{:#X}] = result;\n        v[0xF] = carry as u8;\n",
+                x, y, x
+            )),
+            Sub(x, y) => registers(&subtract(x, x, y)),
+            SubReverse(x, y) => registers(&subtract(x, y, x)),
+            ShiftRight(x, y) => registers(&shift(x, y, "& 1", ">>=")),
+            ShiftLeft(x, y) => registers(&shift(x, y, ">> 7", "<<=")),
+            LoadI(nnn) => format!("    cpu.set_i({:#05X});\n", nnn),
+            Jump(target) => format!("    cpu.set_pc({:#05X});\n", target),
+            Call(target) => format!("    cpu.push({:#05X});\n    cpu.set_pc({:#05X});\n", next, target),
+            Return => "    let pc = cpu.pop();\n    cpu.set_pc(pc);\n".to_string(),
+            SkipEqImm(x, nn) => skip(&format!("cpu.registers()[{:#X}] == {:#04X}", x, nn), next),
+            SkipNeImm(x, nn) => skip(&format!("cpu.registers()[{:#X}] != {:#04X}", x, nn), next),
+            SkipEq(x, y) if x == y => format!("    cpu.set_pc({:#05X});\n", next + 2),
+            SkipNe(x, y) if x == y => format!("    cpu.set_pc({:#05X});\n", next),
+            SkipEq(x, y) => skip(&format!("{{\n        let v = cpu.registers();\n        v[{:#X}] == v[{:#X}]\n    }}", x, y), next),
+            SkipNe(x, y) => skip(&format!("{{\n        let v = cpu.registers();\n        v[{:#X}] != v[{:#X}]\n    }}", x, y), next),
+            // The interpreter sets PC itself.
+            _ if last => format!("    cpu.set_pc({:#05X});\n    if cpu.step() {{\n        *cycles = 0;\n    }}\n", addr),
+            _ => format!(
+                "    cpu.set_pc({:#05X});\n    if cpu.step() {{\n        *cycles = 0;\n        return;\n    }}\n",
+                addr
+            ),
+        };
+        out.push_str(&code);
+        let sets_pc = matches!(instruction, Jump(_) | Call(_) | Return) || instruction.is_skip() || code.contains("cpu.step()");
+        if last && !sets_pc {
+            writeln!(out, "    cpu.set_pc({:#05X});", next).unwrap();
+        }
+    }
+    writeln!(out, "}}").unwrap();
+}
+
+// Without the shift quirk, VY is shifted into VX.
+fn unshifted(x: u8, y: u8) -> String {
+    if x == y {
+        return String::new();
+    }
+    format!("        if !quirks.shift {{\n            v[{:#X}] = v[{:#X}];\n        }}\n", x, y)
+}
+
+// Shifts VX by one with `operator`, and sets VF to the bit shifted out,
+// which `flag` takes from VX.
+fn shift(x: u8, y: u8, flag: &str, operator: &str) -> String {
+    format!(
+        "{}        let flag = v[{:#X}] {};\n        v[{:#X}] {} 1;\n        v[0xF] = flag;\n",
+        unshifted(x, y),
+        x,
+        flag,
+        x,
+        operator
+    )
+}
+
+// VX = VA - VB, with VF set when there is no borrow.
+fn subtract(x: u8, a: u8, b: u8) -> String {
+    format!(
+        "        let (result, borrow) = v[{:#X}].overflowing_sub(v[{:#X}]);\n        v[{:#X}] = result;\n        v[0xF] = !borrow as u8;\n",
+        a, b, x
+    )
+}
+
+fn skip(condition: &str, next: u16) -> String {
+    format!(
+        "    let skip = {};\n    cpu.set_pc(if skip {{ {:#05X} }} else {{ {:#05X} }});\n",
+        condition,
+        next + 2,
+        next
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    #[test]
+    fn test_recompile() {
+        let program = [
+            0x60, 0x05, // 0x200: V0 = 5
+            0x80, 0x14, // 0x202: V0 += V1
+            0xD0, 0x15, // 0x204: draw
+            0x30, 0x05, // 0x206: skip if V0 == 5
+            0x12, 0x00, // 0x208: jump 0x200
+            0x12, 0x0A, // 0x20A: jump 0x20A
+        ];
+        let source = recompile(&program, Platform::Chip8, "test");
+        assert!(source.contains("pub const PLATFORM: Platform = Platform::Chip8;"));
+        assert!(source.contains("0x200 if *cycles >= 4 && is_original(cpu, 0x200, 0x208) => block_0200(cpu, cycles),"));
+        assert!(source.contains("    // 0x200  LD V0, 0x05\n    {\n        let v = cpu.registers_mut();\n        v[0x0] = 0x05;\n    }\n"));
+        assert!(source.contains("    cpu.set_pc(0x204);\n    if cpu.step() {\n        *cycles = 0;\n        return;\n    }\n"));
+        assert!(source.contains("    cpu.set_pc(if skip { 0x20A } else { 0x208 });\n}\n"));
+    }
+
+    // The module under `tests/recompiled` is this generator's output, checked in
+    // so that it is compiled and its test run.
+    #[test]
+    fn test_recompiled_brix_is_up_to_date() {
+        let root = env!("CARGO_MANIFEST_DIR");
+        let program = fs::read(format!("{}/resources/games/BRIX", root)).unwrap();
+        let checked_in = fs::read_to_string(format!("{}/tests/recompiled/brix.rs", root)).unwrap();
+        assert!(recompile(&program, Platform::Chip8, "BRIX") == checked_in, "regenerate with chip8-cli recompile");
+    }
+}
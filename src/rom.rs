@@ -0,0 +1,212 @@
+use crate::platform::Platform;
+use std::fmt;
+use std::io::{Cursor, Read};
+
+const ZIP_MAGIC: [u8; 4] = [0x50, 0x4B, 0x03, 0x04];
+const ROM_EXTENSIONS: [&str; 6] = ["ch8", "sc8", "xo8", "c8", "c8x", "mc8"];
+
+#[derive(Debug, PartialEq)]
+pub enum RomError {
+    Empty,
+    /// The program doesn't fit between the load address and the end of memory.
+    TooLarge { size: usize, max: usize },
+    /// The ROM targets a platform we can't emulate, e.g. XO-CHIP.
+    UnsupportedPlatform(String),
+    Zip(String),
+    /// A zip archive without any `.ch8`, `.sc8` or `.xo8` file.
+    NoRomInArchive,
+}
+
+impl fmt::Display for RomError {
+    fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            RomError::Empty => write!(formatter, "ROM is empty"),
+            RomError::TooLarge { size, max } => {
+                write!(formatter, "ROM is {} bytes but at most {} fit in memory", size, max)
+            }
+            RomError::UnsupportedPlatform(platform) => write!(formatter, "{} ROMs are not supported", platform),
+            RomError::Zip(err) => write!(formatter, "invalid zip archive: {}", err),
+            RomError::NoRomInArchive => write!(formatter, "zip archive contains no ROM"),
+        }
+    }
+}
+
+/// A program extracted from a ROM file, with the platform it was made for.
+#[derive(Debug, PartialEq)]
+pub struct Rom {
+    pub name: String,
+    pub platform: Platform,
+    pub program: Vec<u8>,
+}
+
+/// Reads a raw ROM or a zip archive containing one. The platform is inferred
+/// from the extension, falling back to `default` for unknown extensions.
+pub fn parse(name: &str, data: &[u8], default: Platform) -> Result<Rom, RomError> {
+    if data.starts_with(&ZIP_MAGIC) {
+        let (name, program) = extract_zip(data, default)?;
+        return parse(&name, &program, default);
+    }
+    let platform = infer_platform(name, data.len(), default)?;
+    validate(data, platform)?;
+    Ok(Rom {
+        name: name.to_string(),
+        platform,
+        program: data.to_vec(),
+    })
+}
+
+pub fn validate(program: &[u8], platform: Platform) -> Result<(), RomError> {
+    check_fits(program, platform.load_address() as usize, platform.memory_size())
+}
+
+/// Checks that `program` fits in `memory_size` bytes from `start`.
+pub fn check_fits(program: &[u8], start: usize, memory_size: usize) -> Result<(), RomError> {
+    let max = memory_size.saturating_sub(start);
+    if program.is_empty() {
+        Err(RomError::Empty)
+    } else if program.len() > max {
+        Err(RomError::TooLarge { size: program.len(), max })
+    } else {
+        Ok(())
+    }
+}
+
+fn infer_platform(name: &str, size: usize, default: Platform) -> Result<Platform, RomError> {
+    match extension(name).as_deref() {
+        // Keep CHIP-8 variants chosen by the caller, their ROMs use the same extension.
+        Some("ch8") | Some("c8") => match default {
+            Platform::SuperChip => Ok(Platform::Chip8),
+            _ => Ok(default),
+        },
+        Some("sc8") => Ok(Platform::SuperChip),
+        Some("c8x") => Ok(Platform::Chip8X),
+        Some("mc8") if cfg!(feature = "megachip") => Ok(Platform::MegaChip),
+        Some("mc8") => Err(RomError::UnsupportedPlatform("MEGA-CHIP".to_string())),
+        Some("xo8") => Err(RomError::UnsupportedPlatform("XO-CHIP".to_string())),
+        // Only XO-CHIP programs are larger than 4K memory allows.
+        _ if size > default.memory_size() - default.load_address() as usize => {
+            Err(RomError::UnsupportedPlatform("XO-CHIP".to_string()))
+        }
+        _ => Ok(default),
+    }
+}
+
+fn extension(name: &str) -> Option<String> {
+    let (_, extension) = name.rsplit_once('.')?;
+    Some(extension.to_lowercase())
+}
+
+// Picks the first file with a ROM extension. At most one byte more than
+// fits in memory is extracted, so a zip bomb is rejected before it's unpacked.
+// The first ROM in the archive that fits a supported platform. If there's
+// none, the error from the first ROM that didn't.
+fn extract_zip(data: &[u8], default: Platform) -> Result<(String, Vec<u8>), RomError> {
+    let mut archive = zip::ZipArchive::new(Cursor::new(data)).map_err(|err| RomError::Zip(err.to_string()))?;
+    let mut skipped = None;
+    for idx in 0..archive.len() {
+        let file = archive.by_index(idx).map_err(|err| RomError::Zip(err.to_string()))?;
+        let is_rom = extension(file.name()).is_some_and(|ext| ROM_EXTENSIONS.contains(&ext.as_str()));
+        if !file.is_file() || !is_rom {
+            continue;
+        }
+        let name = file.name().to_string();
+        let platform = match infer_platform(&name, 0, default) {
+            Ok(platform) => platform,
+            Err(err) => {
+                skipped.get_or_insert(err);
+                continue;
+            }
+        };
+        let max = platform.memory_size().saturating_sub(platform.load_address() as usize);
+        if file.size() > max as u64 {
+            skipped.get_or_insert(RomError::TooLarge { size: file.size() as usize, max });
+            continue;
+        }
+        let mut program = Vec::new();
+        file.take(max as u64 + 1).read_to_end(&mut program).map_err(|err| RomError::Zip(err.to_string()))?;
+        if program.len() > max {
+            skipped.get_or_insert(RomError::TooLarge { size: program.len(), max });
+            continue;
+        }
+        return Ok((name, program));
+    }
+    Err(skipped.unwrap_or(RomError::NoRomInArchive))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+
+    fn zip(files: &[(&str, &[u8])]) -> Vec<u8> {
+        let mut writer = zip::ZipWriter::new(Cursor::new(Vec::new()));
+        for (name, data) in files {
+            writer.start_file(*name, zip::write::FileOptions::default()).unwrap();
+            writer.write_all(data).unwrap();
+        }
+        writer.finish().unwrap().into_inner()
+    }
+
+    #[test]
+    fn test_parse_raw() {
+        let rom = parse("game.sc8", &[0x00, 0xFF], Platform::Chip8).unwrap();
+        assert_eq!(rom.platform, Platform::SuperChip);
+        assert_eq!(rom.program, vec![0x00, 0xFF]);
+
+        let rom = parse("BRIX", &[0x00, 0xE0], Platform::Chip8).unwrap();
+        assert_eq!(rom.platform, Platform::Chip8);
+
+        let rom = parse("game.ch8", &[0x00, 0xE0], Platform::Eti660).unwrap();
+        assert_eq!(rom.platform, Platform::Eti660);
+        assert_eq!(
+            parse("game.ch8", &[0; 2561], Platform::Eti660),
+            Err(RomError::TooLarge { size: 2561, max: 2560 })
+        );
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        assert_eq!(parse("game.ch8", &[], Platform::Chip8), Err(RomError::Empty));
+        assert_eq!(
+            parse("game.ch8", &[0; 3585], Platform::Chip8),
+            Err(RomError::TooLarge { size: 3585, max: 3584 })
+        );
+        assert_eq!(
+            parse("game.xo8", &[0; 2], Platform::Chip8),
+            Err(RomError::UnsupportedPlatform("XO-CHIP".to_string()))
+        );
+        assert_eq!(
+            parse("game", &[0; 5000], Platform::Chip8),
+            Err(RomError::UnsupportedPlatform("XO-CHIP".to_string()))
+        );
+    }
+
+    #[test]
+    fn test_parse_zip() {
+        let archive = zip(&[("readme.txt", b"hello"), ("games/blitz.sc8", &[0x12, 0x00])]);
+        let rom = parse("games.zip", &archive, Platform::Chip8).unwrap();
+        assert_eq!(rom.name, "games/blitz.sc8");
+        assert_eq!(rom.platform, Platform::SuperChip);
+        assert_eq!(rom.program, vec![0x12, 0x00]);
+
+        let archive = zip(&[("readme.txt", b"hello")]);
+        assert_eq!(parse("games.zip", &archive, Platform::Chip8), Err(RomError::NoRomInArchive));
+
+        let archive = zip(&[("bomb.ch8", &vec![0; 1 << 20])]);
+        assert_eq!(
+            parse("bomb.zip", &archive, Platform::Chip8),
+            Err(RomError::TooLarge { size: 1 << 20, max: 0xE00 })
+        );
+
+        let archive = zip(&[("colour.xo8", &[0x12, 0x00]), ("mono.ch8", &[0x12, 0x02])]);
+        let rom = parse("pack.zip", &archive, Platform::Chip8).unwrap();
+        assert_eq!(rom.name, "mono.ch8");
+        assert_eq!(rom.program, vec![0x12, 0x02]);
+
+        let archive = zip(&[("colour.xo8", &[0x12, 0x00])]);
+        assert_eq!(
+            parse("pack.zip", &archive, Platform::Chip8),
+            Err(RomError::UnsupportedPlatform("XO-CHIP".to_string()))
+        );
+    }
+}
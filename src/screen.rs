@@ -1,20 +1,54 @@
+use crate::display::{Display, DrawResult};
 use std::fmt;
 
 const WIDTH: usize = 64;
 const HEIGHT: usize = 32;
+// Beyond this many pending rectangles they are merged into one, so a
+// frontend that never drains them doesn't grow the list forever.
+const MAX_DIRTY_RECTS: usize = 64;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Rect {
+    pub x: usize,
+    pub y: usize,
+    pub width: usize,
+    pub height: usize,
+}
+
+impl Rect {
+    pub fn new(x: usize, y: usize, width: usize, height: usize) -> Rect {
+        Rect { x, y, width, height }
+    }
+
+    pub fn union(&self, other: &Rect) -> Rect {
+        let x = self.x.min(other.x);
+        let y = self.y.min(other.y);
+        let right = (self.x + self.width).max(other.x + other.width);
+        let bottom = (self.y + self.height).max(other.y + other.height);
+        Rect::new(x, y, right - x, bottom - y)
+    }
+}
 
 pub struct Screen {
-    bit_map: [bool; 2048],
+    bit_map: Vec<bool>,
     width: usize,
     height: usize,
+    plane_mask: u8,
+    generation: u64,
+    pending: Option<Rect>,
+    dirty_rects: Vec<Rect>,
 }
 
 impl Default for Screen {
     fn default() -> Screen {
         Screen {
-            bit_map: [false; 2048],
+            bit_map: vec![false; WIDTH * HEIGHT],
             width: WIDTH,
             height: HEIGHT,
+            plane_mask: 1,
+            generation: 0,
+            pending: None,
+            dirty_rects: Vec::new(),
         }
     }
 }
@@ -24,19 +58,70 @@ impl Screen {
         Default::default()
     }
     pub fn set_pixel(&mut self, row: usize, col: usize) {
-        self.bit_map[row * WIDTH + col] = !self.bit_map[row * WIDTH + col];
+        let idx = row * self.width + col;
+        self.bit_map[idx] = !self.bit_map[idx];
+        let pixel = Rect::new(col, row, 1, 1);
+        self.pending = Some(match self.pending {
+            Some(rect) => rect.union(&pixel),
+            None => pixel,
+        });
     }
 
     pub fn get_pixel(&mut self, row: usize, col: usize) -> bool {
-        self.bit_map[row * WIDTH + col]
+        self.bit_map[row * self.width + col]
     }
 
     pub fn clear(&mut self) {
-        self.bit_map = [false; 2048];
+        for pixel in self.bit_map.iter_mut() {
+            *pixel = false;
+        }
+        self.pending = Some(Rect::new(0, 0, self.width, self.height));
+        self.end_draw();
+    }
+
+    /// Closes the current draw call, recording the area touched by `set_pixel`
+    /// since the previous call as one dirty rectangle.
+    pub fn end_draw(&mut self) {
+        if let Some(rect) = self.pending.take() {
+            self.generation += 1;
+            self.dirty_rects.push(rect);
+            if self.dirty_rects.len() > MAX_DIRTY_RECTS {
+                let merged = self.frame_rect();
+                self.dirty_rects.clear();
+                self.dirty_rects.extend(merged);
+            }
+        }
+    }
+
+    pub fn is_dirty(&self) -> bool {
+        !self.dirty_rects.is_empty()
+    }
+
+    /// Increases every time a draw call changes the screen, so consumers can
+    /// tell whether they are looking at the same frame as before.
+    pub fn generation(&self) -> u64 {
+        self.generation
+    }
+
+    /// Bounding box of every change since the rectangles were last taken.
+    pub fn frame_rect(&self) -> Option<Rect> {
+        self.dirty_rects.iter().fold(None, |acc, rect| match acc {
+            Some(acc) => Some(rect.union(&acc)),
+            None => Some(*rect),
+        })
+    }
+
+    /// Returns one rectangle per draw call since the last call and marks the screen clean.
+    pub fn take_dirty_rects(&mut self) -> Vec<Rect> {
+        std::mem::take(&mut self.dirty_rects)
     }
 
     pub fn get_screen_memory(&self) -> *const bool {
-        return self.bit_map.as_ptr();
+        self.bit_map.as_ptr()
+    }
+
+    pub fn pixels(&self) -> &[bool] {
+        &self.bit_map
     }
 
     pub fn width(&self) -> usize {
@@ -48,12 +133,119 @@ impl Screen {
     }
 }
 
+impl Display for Screen {
+    fn width(&self) -> usize {
+        self.width
+    }
+
+    fn height(&self) -> usize {
+        self.height
+    }
+
+    fn clear(&mut self) {
+        if self.plane_mask & 1 != 0 {
+            Screen::clear(self);
+        }
+    }
+
+    fn draw_sprite(&mut self, x: usize, y: usize, rows: &[u16], width: usize, wrap: bool) -> DrawResult {
+        let mut result = DrawResult::default();
+        if self.plane_mask & 1 == 0 {
+            return result;
+        }
+        let left = x % self.width;
+        let top = y % self.height;
+        for (row, &sprite) in rows.iter().enumerate() {
+            let mut r = top + row;
+            if r >= self.height {
+                if !wrap {
+                    result.clipped_rows = rows.len() - row;
+                    break;
+                }
+                r %= self.height;
+            }
+            let mut collided = false;
+            for col in 0..width {
+                if sprite & (0x8000 >> col) == 0 {
+                    continue;
+                }
+                let mut c = left + col;
+                if c >= self.width {
+                    if !wrap {
+                        break;
+                    }
+                    c %= self.width;
+                }
+                collided |= self.get_pixel(r, c);
+                self.set_pixel(r, c);
+            }
+            if collided {
+                result.collided_rows += 1;
+            }
+        }
+        Screen::end_draw(self);
+        result
+    }
+
+    fn plot(&mut self, row: usize, col: usize) -> bool {
+        let was_on = self.get_pixel(row, col);
+        if !was_on {
+            self.set_pixel(row, col);
+        }
+        was_on
+    }
+
+    fn end_draw(&mut self) {
+        Screen::end_draw(self);
+    }
+
+    fn scroll(&mut self, dx: isize, dy: isize) {
+        if self.plane_mask & 1 == 0 {
+            return;
+        }
+        let (width, height) = (self.width as isize, self.height as isize);
+        let mut bit_map = vec![false; self.bit_map.len()];
+        for row in 0..height {
+            for col in 0..width {
+                let (from_row, from_col) = (row - dy, col - dx);
+                if (0..height).contains(&from_row) && (0..width).contains(&from_col) {
+                    bit_map[(row * width + col) as usize] = self.bit_map[(from_row * width + from_col) as usize];
+                }
+            }
+        }
+        self.bit_map = bit_map;
+        self.pending = Some(Rect::new(0, 0, self.width, self.height));
+        Screen::end_draw(self);
+    }
+
+    fn set_resolution(&mut self, width: usize, height: usize) {
+        self.width = width;
+        self.height = height;
+        self.bit_map = vec![false; width * height];
+        Screen::clear(self);
+    }
+
+    fn set_plane_mask(&mut self, mask: u8) {
+        self.plane_mask = mask;
+    }
+}
+
 impl fmt::Debug for Screen {
     fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
         self.bit_map.fmt(formatter)
     }
 }
 
+impl fmt::Display for Screen {
+    fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        for row in self.bit_map.chunks(self.width) {
+            let line: String = row.iter().map(|&on| if on { '#' } else { '.' }).collect();
+            writeln!(formatter, "{}", line)?;
+        }
+        Ok(())
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -66,9 +258,127 @@ mod tests {
     }
 
     #[test]
+    #[allow(clippy::bool_assert_comparison)]
     pub fn test_set_pixel() {
         let mut screen = Screen::new();
         screen.set_pixel(1, 3);
         assert_eq!(screen.get_pixel(1, 3), true);
     }
-}
\ No newline at end of file
+
+    #[test]
+    pub fn test_set_resolution() {
+        let mut screen = Screen::new();
+        screen.set_resolution(128, 64);
+        assert_eq!(screen.width(), 128);
+        assert_eq!(screen.height(), 64);
+        screen.set_pixel(63, 127);
+        assert!(screen.get_pixel(63, 127));
+
+        screen.set_resolution(WIDTH, HEIGHT);
+        assert_eq!(screen.pixels().len(), WIDTH * HEIGHT);
+    }
+
+    #[test]
+    pub fn test_draw_sprite() {
+        let mut screen = Screen::new();
+        let result = screen.draw_sprite(62, 31, &[0xC000, 0xC000], 8, false);
+        assert_eq!(result, DrawResult { collided_rows: 0, clipped_rows: 1 });
+        assert!(screen.get_pixel(31, 63));
+        assert!(!screen.get_pixel(0, 62));
+
+        let result = screen.draw_sprite(62, 31, &[0xC000, 0xC000], 8, true);
+        assert!(result.collided());
+        assert!(!screen.get_pixel(31, 63));
+        assert!(screen.get_pixel(0, 62));
+    }
+
+    #[test]
+    pub fn test_scroll() {
+        let mut screen = Screen::new();
+        screen.set_pixel(0, 0);
+        screen.scroll(4, 2);
+        assert!(screen.get_pixel(2, 4));
+        assert!(!screen.get_pixel(0, 0));
+        screen.scroll(-8, 0);
+        assert!(screen.pixels().iter().all(|&on| !on));
+    }
+
+    #[test]
+    pub fn test_plot() {
+        let mut screen = Screen::new();
+        assert!(!screen.plot(0, 0));
+        assert!(screen.plot(0, 0));
+    }
+
+    #[test]
+    pub fn test_plane_mask() {
+        let mut screen = Screen::new();
+        screen.set_plane_mask(0);
+        screen.draw_sprite(0, 0, &[0x8000], 8, false);
+        assert!(!screen.get_pixel(0, 0));
+        screen.set_plane_mask(1);
+        screen.draw_sprite(0, 0, &[0x8000], 8, false);
+        assert!(screen.get_pixel(0, 0));
+        screen.set_plane_mask(0);
+        Display::clear(&mut screen);
+        assert!(screen.get_pixel(0, 0));
+    }
+
+    #[test]
+    pub fn test_set_lores_resolution() {
+        let mut screen = Screen::new();
+        screen.set_resolution(64, 64);
+        assert_eq!((screen.width(), screen.height()), (64, 64));
+        screen.set_resolution(128, 64);
+        screen.set_resolution(64, 64);
+        assert_eq!(screen.pixels().len(), 64 * 64);
+        assert_eq!((screen.width(), screen.height()), (64, 64));
+    }
+
+    #[test]
+    pub fn test_display() {
+        let mut screen = Screen::new();
+        screen.set_pixel(0, 1);
+        let text = screen.to_string();
+        assert_eq!(text.lines().count(), HEIGHT);
+        assert!(text.starts_with(".#.."));
+    }
+
+    #[test]
+    pub fn test_dirty_rect_per_draw() {
+        let mut screen = Screen::new();
+        assert!(!screen.is_dirty());
+
+        screen.set_pixel(1, 3);
+        screen.set_pixel(4, 5);
+        screen.end_draw();
+        screen.set_pixel(10, 10);
+        screen.end_draw();
+        assert!(screen.is_dirty());
+        assert_eq!(screen.frame_rect(), Some(Rect::new(3, 1, 8, 10)));
+
+        let rects = screen.take_dirty_rects();
+        assert_eq!(rects, vec![Rect::new(3, 1, 3, 4), Rect::new(10, 10, 1, 1)]);
+        assert!(!screen.is_dirty());
+        assert_eq!(screen.frame_rect(), None);
+    }
+
+    #[test]
+    pub fn test_clear_marks_whole_screen() {
+        let mut screen = Screen::new();
+        let generation = screen.generation();
+        screen.clear();
+        assert_eq!(screen.take_dirty_rects(), vec![Rect::new(0, 0, WIDTH, HEIGHT)]);
+        assert!(screen.generation() > generation);
+    }
+
+    #[test]
+    pub fn test_dirty_rects_are_merged_when_not_taken() {
+        let mut screen = Screen::new();
+        for idx in 0..MAX_DIRTY_RECTS + 1 {
+            screen.set_pixel(0, idx % WIDTH);
+            screen.end_draw();
+        }
+        assert_eq!(screen.take_dirty_rects(), vec![Rect::new(0, 0, WIDTH, 1)]);
+    }
+}
@@ -0,0 +1,66 @@
+//! Save states: a snapshot of everything a running program can observe,
+//! stored as JSON.
+
+use crate::colour::ColourAttributes;
+use crate::platform::{Platform, Quirks};
+use serde::{Deserialize, Serialize};
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct State {
+    pub platform: Platform,
+    pub quirks: Quirks,
+    pub pc: u16,
+    pub v: [u8; 16],
+    pub i: u32,
+    /// Return addresses, oldest first.
+    pub stack: Vec<u16>,
+    pub delay_timer: u8,
+    pub sound_timer: u8,
+    /// The register of a waiting FX0A, and the key pressed so far.
+    pub key_wait: Option<(u8, Option<u8>)>,
+    pub memory_size: usize,
+    /// The memory up to its last non-zero byte.
+    pub memory: Vec<u8>,
+    pub width: usize,
+    pub height: usize,
+    /// One bit per pixel, row by row, from the most significant bit.
+    pub pixels: Vec<u8>,
+    pub colours: ColourAttributes,
+    /// The state of the generator seeded by `seed_random`.
+    pub rng: Option<u64>,
+}
+
+impl State {
+    pub fn to_json(&self) -> String {
+        serde_json::to_string(self).unwrap()
+    }
+
+    pub fn from_json(json: &str) -> Result<State, String> {
+        serde_json::from_str(json).map_err(|err| err.to_string())
+    }
+}
+
+pub fn pack_pixels(pixels: &[bool]) -> Vec<u8> {
+    pixels
+        .chunks(8)
+        .map(|chunk| chunk.iter().enumerate().fold(0, |byte, (bit, &on)| byte | (on as u8) << (7 - bit)))
+        .collect()
+}
+
+/// The first `count` pixels packed by `pack_pixels`.
+pub fn unpack_pixels(bytes: &[u8], count: usize) -> Vec<bool> {
+    (0..count).map(|pixel| bytes[pixel / 8] & 0x80 >> (pixel % 8) != 0).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_pack_pixels() {
+        let pixels = [true, false, false, false, false, false, false, true, true, true];
+        assert_eq!(pack_pixels(&pixels), vec![0x81, 0xC0]);
+        assert_eq!(unpack_pixels(&[0x81, 0xC0], 10), pixels);
+    }
+}
@@ -0,0 +1,186 @@
+//! Runs test ROMs headlessly and compares their final screens with golden
+//! bitmaps.
+//!
+//! A suite is a directory with the ROMs, a `golden` directory of screens in
+//! the text format of `Screen`'s `Display`, and a `suite.txt` manifest with one
+//! test per line: a name, the ROM file, the platform, how many frames to run
+//! and how many cycles per frame, then key presses (`30+A`) and releases
+//! (`40-A`) before the given frames:
+//!
+//! ```text
+//! # name    rom           platform  frames  cycles  input
+//! keypad    keypad.ch8    Chip8     60      20      10+5 15-5
+//! ```
+
+use crate::cpu::CPU;
+use crate::differential::KeyEvent;
+use crate::platform::Platform;
+use std::fmt;
+use std::fs;
+use std::path::Path;
+
+pub const MANIFEST: &str = "suite.txt";
+pub const GOLDEN_DIR: &str = "golden";
+
+/// One entry of the manifest.
+#[derive(Clone, Debug, PartialEq)]
+pub struct TestRom {
+    pub name: String,
+    pub rom: String,
+    pub platform: Platform,
+    pub frames: usize,
+    pub cycles: usize,
+    pub input: Vec<KeyEvent>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Outcome {
+    Pass,
+    /// The screen didn't match, with a description of how.
+    Fail(String),
+    /// The ROM or its golden screen isn't there, which fails like a wrong
+    /// screen does.
+    Missing(String),
+}
+
+impl fmt::Display for Outcome {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Outcome::Pass => write!(f, "PASS"),
+            Outcome::Fail(reason) => write!(f, "FAIL: {}", reason),
+            Outcome::Missing(file) => write!(f, "MISSING: {}", file),
+        }
+    }
+}
+
+pub fn parse_manifest(text: &str) -> Result<Vec<TestRom>, String> {
+    let mut tests = Vec::new();
+    for (number, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let error = |what: &str| format!("line {}: {}: {}", number + 1, what, line);
+        let fields: Vec<&str> = line.split_whitespace().collect();
+        if fields.len() < 5 {
+            return Err(error("expected a name, ROM, platform, frames and cycles"));
+        }
+        let platform = serde_json::from_str(&format!("\"{}\"", fields[2])).map_err(|_| error("bad platform"))?;
+        let number = |field: &str| field.parse().map_err(|_| error("bad number"));
+        let input = fields[5..].iter().map(|event| parse_event(event).ok_or_else(|| error("bad key event")));
+        tests.push(TestRom {
+            name: fields[0].to_string(),
+            rom: fields[1].to_string(),
+            platform,
+            frames: number(fields[3])?,
+            cycles: number(fields[4])?,
+            input: input.collect::<Result<_, _>>()?,
+        });
+    }
+    Ok(tests)
+}
+
+// `30+A` presses A before frame 30, `40-A` releases it.
+fn parse_event(event: &str) -> Option<KeyEvent> {
+    let at = event.find(['+', '-'])?;
+    let key = u8::from_str_radix(&event[at + 1..], 16).ok().filter(|&key| key < 16)?;
+    Some(KeyEvent { frame: event[..at].parse().ok()?, key, pressed: event[at..].starts_with('+') })
+}
+
+impl TestRom {
+    /// Runs the program and returns its final screen as text.
+    pub fn run(&self, program: &[u8]) -> Result<String, String> {
+        let mut cpu = CPU::with_platform(self.platform);
+        cpu.seed_random(0);
+        cpu.load_program(program).map_err(|err| err.to_string())?;
+        for frame in 0..self.frames {
+            for event in self.input.iter().filter(|event| event.frame == frame) {
+                if event.pressed {
+                    cpu.get_keyboard().key_down(event.key);
+                } else {
+                    cpu.get_keyboard().key_up(event.key);
+                }
+            }
+            cpu.run_frame(self.cycles);
+        }
+        Ok(cpu.get_screen().to_string())
+    }
+}
+
+/// Runs every test of the suite in `dir`. With `update`, the golden screens
+/// are written instead of compared.
+pub fn run_suite(dir: &Path, update: bool) -> Result<Vec<(String, Outcome)>, String> {
+    let manifest = dir.join(MANIFEST);
+    let text = fs::read_to_string(&manifest).map_err(|err| format!("{}: {}", manifest.display(), err))?;
+    let mut results = Vec::new();
+    for test in parse_manifest(&text)? {
+        let golden = dir.join(GOLDEN_DIR).join(format!("{}.txt", test.name));
+        let rom = dir.join(&test.rom);
+        let outcome = match fs::read(&rom) {
+            Err(_) => Outcome::Missing(test.rom.clone()),
+            Ok(program) => match test.run(&program) {
+                Err(err) => Outcome::Fail(err),
+                Ok(screen) if update => {
+                    fs::write(&golden, screen).map_err(|err| format!("{}: {}", golden.display(), err))?;
+                    Outcome::Pass
+                }
+                Ok(screen) => match fs::read_to_string(&golden) {
+                    Err(_) => Outcome::Missing(format!("{}/{}.txt", GOLDEN_DIR, test.name)),
+                    Ok(expected) => compare(&expected, &screen),
+                },
+            },
+        };
+        results.push((test.name, outcome));
+    }
+    Ok(results)
+}
+
+// Passes when the screens are the same, or else lists the rows that differ.
+fn compare(expected: &str, actual: &str) -> Outcome {
+    if expected.trim_end() == actual.trim_end() {
+        return Outcome::Pass;
+    }
+    let (expected, actual): (Vec<&str>, Vec<&str>) = (expected.lines().collect(), actual.lines().collect());
+    if expected.len() != actual.len() || expected.first().map(|row| row.len()) != actual.first().map(|row| row.len()) {
+        return Outcome::Fail("the screen has a different size".to_string());
+    }
+    let mut reason = String::from("rows differ, expected then actual:");
+    for (row, (expected, actual)) in expected.iter().zip(actual.iter()).enumerate() {
+        if expected != actual {
+            reason += &format!("\n{:>3} {}\n    {}", row, expected, actual);
+        }
+    }
+    Outcome::Fail(reason)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_manifest() {
+        let tests = parse_manifest("# comment\n\nkeypad keypad.ch8 SuperChip 60 20 10+5 15-A\n").unwrap();
+        assert_eq!(
+            tests,
+            vec![TestRom {
+                name: "keypad".to_string(),
+                rom: "keypad.ch8".to_string(),
+                platform: Platform::SuperChip,
+                frames: 60,
+                cycles: 20,
+                input: vec![KeyEvent { frame: 10, key: 5, pressed: true }, KeyEvent { frame: 15, key: 0xA, pressed: false }],
+            }]
+        );
+        assert!(parse_manifest("logo logo.ch8 Chip8 60").is_err());
+        assert!(parse_manifest("logo logo.ch8 Atari 60 20").is_err());
+        assert!(parse_manifest("logo logo.ch8 Chip8 60 20 10*5").is_err());
+        assert!(parse_manifest("logo logo.ch8 Chip8 60 20 10+G").is_err());
+    }
+
+    #[test]
+    fn test_compare() {
+        assert_eq!(compare("#.\n..\n", "#.\n..\n"), Outcome::Pass);
+        assert_eq!(compare("#.\n..\n", "#.\n.#\n"), Outcome::Fail("rows differ, expected then actual:\n  1 ..\n    .#".to_string()));
+        assert_eq!(compare("#.\n", "#.\n..\n"), Outcome::Fail("the screen has a different size".to_string()));
+    }
+}
@@ -0,0 +1,83 @@
+//! Symbols of an assembled program: labels and the source line of each
+//! instruction, for listings and coverage reports.
+//!
+//! The text format has one symbol per line, an address followed either by a
+//! label or by `file:line`. Blank lines and lines starting with `#` are
+//! ignored:
+//!
+//! ```text
+//! 0x200 main
+//! 0x200 game.8o:12
+//! 0x202 game.8o:13
+//! ```
+
+use std::collections::BTreeMap;
+
+/// Where an instruction came from.
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub struct SourceLine {
+    pub file: String,
+    pub line: u32,
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Symbols {
+    labels: BTreeMap<u16, String>,
+    lines: BTreeMap<u16, SourceLine>,
+}
+
+impl Symbols {
+    pub fn parse(text: &str) -> Result<Symbols, String> {
+        let mut symbols = Symbols::default();
+        for (number, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let error = || format!("bad symbol on line {}: {}", number + 1, line);
+            let (addr, symbol) = line.split_once(char::is_whitespace).ok_or_else(error)?;
+            let addr = u16::from_str_radix(addr.trim_start_matches("0x"), 16).map_err(|_| error())?;
+            let symbol = symbol.trim();
+            match symbol.rsplit_once(':') {
+                Some((file, line)) if line.parse::<u32>().is_ok() => {
+                    let line = line.parse().unwrap();
+                    symbols.lines.insert(addr, SourceLine { file: file.to_string(), line });
+                }
+                _ => {
+                    symbols.labels.insert(addr, symbol.to_string());
+                }
+            }
+        }
+        Ok(symbols)
+    }
+
+    pub fn label(&self, addr: u16) -> Option<&str> {
+        self.labels.get(&addr).map(String::as_str)
+    }
+
+    pub fn source_line(&self, addr: u16) -> Option<&SourceLine> {
+        self.lines.get(&addr)
+    }
+
+    /// Addresses of the instructions with a source line, in order.
+    pub fn lines(&self) -> impl Iterator<Item = (u16, &SourceLine)> {
+        self.lines.iter().map(|(&addr, line)| (addr, line))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        let symbols = Symbols::parse("# game\n0x200 main\n0x200 game.8o:12\n\n202 lib/draw.8o:3\n").unwrap();
+        assert_eq!(symbols.label(0x200), Some("main"));
+        assert_eq!(symbols.label(0x202), None);
+        assert_eq!(symbols.source_line(0x202), Some(&SourceLine { file: "lib/draw.8o".to_string(), line: 3 }));
+        assert_eq!(symbols.lines().count(), 2);
+
+        assert_eq!(Symbols::parse("main").unwrap_err(), "bad symbol on line 1: main");
+        assert!(Symbols::parse("0xZZ main").is_err());
+    }
+}
@@ -9,4 +9,30 @@ pub fn get_random_buf() -> Result<[u8; 1], getrandom::Error> {
     let mut buf = [0u8; 1];
     getrandom::getrandom(&mut buf)?;
     Ok(buf)
-}
\ No newline at end of file
+}
+
+/// xorshift64*, for random numbers that can be replayed.
+pub struct Rng(u64);
+
+impl Rng {
+    pub fn new(seed: u64) -> Rng {
+        // The state must not be zero.
+        Rng((seed ^ 0x9E37_79B9_7F4A_7C15).max(1))
+    }
+
+    /// Continues the sequence of a generator whose `state` was saved.
+    pub fn restore(state: u64) -> Rng {
+        Rng(state.max(1))
+    }
+
+    pub fn state(&self) -> u64 {
+        self.0
+    }
+
+    pub fn next_byte(&mut self) -> u8 {
+        self.0 ^= self.0 >> 12;
+        self.0 ^= self.0 << 25;
+        self.0 ^= self.0 >> 27;
+        (self.0.wrapping_mul(0x2545_F491_4F6C_DD1D) >> 56) as u8
+    }
+}
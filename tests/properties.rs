@@ -0,0 +1,129 @@
+//! Property tests feeding arbitrary programs, CPU states and save states to
+//! the CPU. `fuzz/` has libFuzzer targets for the same properties.
+
+use chip8::differential::{Config, KeyEvent};
+use chip8::invariants::{self, Run};
+use chip8::{ColourAttributes, Engine, Platform, Quirks, State, CPU};
+use proptest::prelude::*;
+
+// With the `megachip` feature, saving and comparing MEGA-CHIP's 16 MiB of
+// memory every frame makes each case too slow, so only the fuzz targets run it.
+fn platform() -> impl Strategy<Value = Platform> {
+    let platforms = Platform::ALL.iter().copied();
+    let platforms = platforms.filter(|&platform| !cfg!(feature = "megachip") || platform != Platform::MegaChip);
+    prop::sample::select(platforms.collect::<Vec<_>>())
+}
+
+fn config() -> impl Strategy<Value = Config> {
+    (any::<bool>(), any::<[bool; 10]>()).prop_map(|(cached, quirks)| Config {
+        engine: if cached { Engine::CachedBlocks } else { Engine::Interpreter },
+        quirks: Quirks {
+            wrap_sprites: quirks[0],
+            count_collided_rows: quirks[1],
+            key_wait_release: quirks[2],
+            hires: quirks[3],
+            shift: quirks[4],
+            memory_leave_i_unchanged: quirks[5],
+            memory_increment_by_x: quirks[6],
+            jump: quirks[7],
+            logic: quirks[8],
+            vblank: quirks[9],
+        },
+    })
+}
+
+// Mostly opcodes that do something, since few random words are EXNN, FXNN or 00NN.
+fn opcode() -> impl Strategy<Value = u16> {
+    let low = vec![0x9E, 0xA1, 0xF2, 0xF5, 0x07, 0x0A, 0x15, 0x18, 0x1E, 0x29, 0x33, 0x55, 0x65, 0xF8, 0xFB];
+    let system = vec![0x00E0, 0x00EE, 0x00FB, 0x00FC, 0x00FD, 0x00FE, 0x00FF, 0x0230, 0x02A0, 0x0010, 0x0011];
+    prop_oneof![
+        any::<u16>(),
+        (0xE..=0xFu16, 0..16u16, prop::sample::select(low)).prop_map(|(op, x, nn)| op << 12 | x << 8 | nn),
+        prop::sample::select(system),
+        (0..16u16).prop_map(|n| 0x00C0 | n),
+    ]
+}
+
+fn program() -> impl Strategy<Value = Vec<u8>> {
+    prop::collection::vec(opcode(), 0..256).prop_map(|opcodes| opcodes.iter().flat_map(|op| op.to_be_bytes()).collect())
+}
+
+fn input(frames: usize) -> impl Strategy<Value = Vec<KeyEvent>> {
+    let event = (0..frames, 0..16u8, any::<bool>()).prop_map(|(frame, key, pressed)| KeyEvent { frame, key, pressed });
+    prop::collection::vec(event, 0..16)
+}
+
+// A valid state from a fresh CPU, with registers, stack and memory replaced.
+fn state() -> impl Strategy<Value = State> {
+    let registers = (any::<[u8; 16]>(), 0..=0xFFFFu32, prop::collection::vec(0..0x1000u16, 0..=16));
+    let memory = (0..0x1000u16, prop::collection::vec(any::<u8>(), 0..0x1000), any::<(u8, u8)>());
+    (platform(), config(), registers, memory).prop_map(|(platform, config, (v, i, stack), (pc, memory, timers))| {
+        let mut state = CPU::with_platform(platform).save_state().unwrap();
+        state.quirks = config.quirks;
+        state.v = v;
+        state.i = i;
+        state.stack = stack;
+        state.pc = pc;
+        state.memory = memory;
+        (state.delay_timer, state.sound_timer) = timers;
+        state
+    })
+}
+
+proptest! {
+    #[test]
+    fn arbitrary_programs_keep_the_invariants(
+        program in program(),
+        platform in platform(),
+        config in config(),
+        cycles in 1..24usize,
+        input in input(30),
+    ) {
+        let run = Run { program, platform, config, seed: 0, cycles, frames: 30, input };
+        prop_assert_eq!(run.check(), Ok(()));
+    }
+
+    #[test]
+    fn any_instruction_in_any_state_keeps_the_invariants(state in state(), opcode in opcode()) {
+        let mut cpu = CPU::new();
+        cpu.load_state(&state).unwrap();
+        cpu.execute(opcode);
+        prop_assert_eq!(invariants::check(&cpu), Ok(()));
+        prop_assert_eq!(invariants::check_round_trip(&mut cpu), Ok(()));
+    }
+
+    #[test]
+    fn loading_a_damaged_state_fails_or_keeps_the_invariants(
+        state in state(),
+        pc in any::<u16>(),
+        i in any::<u32>(),
+        stack in prop::collection::vec(any::<u16>(), 0..20),
+        key_wait in any::<Option<(u8, Option<u8>)>>(),
+        size in any::<(u8, u8, u16)>(),
+        colours in any::<(Option<(u8, u8)>, u8)>(),
+    ) {
+        let (width, height, pixels) = size;
+        // Colours for the damaged screen size, or for another one.
+        let (colour_size, backgrounds) = colours;
+        let (colour_width, colour_height) = colour_size.unwrap_or((width, height));
+        let mut colours = ColourAttributes::new(colour_width as usize, colour_height as usize);
+        for _ in 0..backgrounds % 4 {
+            colours.cycle_background();
+        }
+        let damaged = State {
+            pc,
+            i,
+            stack,
+            key_wait,
+            width: width as usize,
+            height: height as usize,
+            pixels: vec![0xFF; pixels as usize],
+            colours,
+            ..state
+        };
+        let mut cpu = CPU::new();
+        if cpu.load_state(&damaged).is_ok() {
+            prop_assert_eq!(invariants::check(&cpu), Ok(()));
+        }
+    }
+}
@@ -0,0 +1,966 @@
+//! `BRIX` recompiled from CHIP-8 to Rust by `chip8-cli recompile`.
+
+use chip8::{Bus, Display, Platform, CPU};
+
+pub const PLATFORM: Platform = Platform::Chip8;
+const LOAD_ADDRESS: usize = 0x200;
+
+pub const PROGRAM: [u8; 280] = [
+    0x6E, 0x05, 0x65, 0x00, 0x6B, 0x06, 0x6A, 0x00, 0xA3, 0x0C, 0xDA, 0xB1,
+    0x7A, 0x04, 0x3A, 0x40, 0x12, 0x08, 0x7B, 0x02, 0x3B, 0x12, 0x12, 0x06,
+    0x6C, 0x20, 0x6D, 0x1F, 0xA3, 0x10, 0xDC, 0xD1, 0x22, 0xF6, 0x60, 0x00,
+    0x61, 0x00, 0xA3, 0x12, 0xD0, 0x11, 0x70, 0x08, 0xA3, 0x0E, 0xD0, 0x11,
+    0x60, 0x40, 0xF0, 0x15, 0xF0, 0x07, 0x30, 0x00, 0x12, 0x34, 0xC6, 0x0F,
+    0x67, 0x1E, 0x68, 0x01, 0x69, 0xFF, 0xA3, 0x0E, 0xD6, 0x71, 0xA3, 0x10,
+    0xDC, 0xD1, 0x60, 0x04, 0xE0, 0xA1, 0x7C, 0xFE, 0x60, 0x06, 0xE0, 0xA1,
+    0x7C, 0x02, 0x60, 0x3F, 0x8C, 0x02, 0xDC, 0xD1, 0xA3, 0x0E, 0xD6, 0x71,
+    0x86, 0x84, 0x87, 0x94, 0x60, 0x3F, 0x86, 0x02, 0x61, 0x1F, 0x87, 0x12,
+    0x47, 0x1F, 0x12, 0xAC, 0x46, 0x00, 0x68, 0x01, 0x46, 0x3F, 0x68, 0xFF,
+    0x47, 0x00, 0x69, 0x01, 0xD6, 0x71, 0x3F, 0x01, 0x12, 0xAA, 0x47, 0x1F,
+    0x12, 0xAA, 0x60, 0x05, 0x80, 0x75, 0x3F, 0x00, 0x12, 0xAA, 0x60, 0x01,
+    0xF0, 0x18, 0x80, 0x60, 0x61, 0xFC, 0x80, 0x12, 0xA3, 0x0C, 0xD0, 0x71,
+    0x60, 0xFE, 0x89, 0x03, 0x22, 0xF6, 0x75, 0x01, 0x22, 0xF6, 0x45, 0x60,
+    0x12, 0xDE, 0x12, 0x46, 0x69, 0xFF, 0x80, 0x60, 0x80, 0xC5, 0x3F, 0x01,
+    0x12, 0xCA, 0x61, 0x02, 0x80, 0x15, 0x3F, 0x01, 0x12, 0xE0, 0x80, 0x15,
+    0x3F, 0x01, 0x12, 0xEE, 0x80, 0x15, 0x3F, 0x01, 0x12, 0xE8, 0x60, 0x20,
+    0xF0, 0x18, 0xA3, 0x0E, 0x7E, 0xFF, 0x80, 0xE0, 0x80, 0x04, 0x61, 0x00,
+    0xD0, 0x11, 0x3E, 0x00, 0x12, 0x30, 0x12, 0xDE, 0x78, 0xFF, 0x48, 0xFE,
+    0x68, 0xFF, 0x12, 0xEE, 0x78, 0x01, 0x48, 0x02, 0x68, 0x01, 0x60, 0x04,
+    0xF0, 0x18, 0x69, 0xFF, 0x12, 0x70, 0xA3, 0x14, 0xF5, 0x33, 0xF2, 0x65,
+    0xF1, 0x29, 0x63, 0x37, 0x64, 0x00, 0xD3, 0x45, 0x73, 0x05, 0xF2, 0x29,
+    0xD3, 0x45, 0x00, 0xEE, 0xE0, 0x00, 0x80, 0x00, 0xFC, 0x00, 0xAA, 0x00,
+    0x00, 0x00, 0x00, 0x00,
+];
+
+/// Runs one frame like `CPU::run_frame`, with the recompiled blocks wherever
+/// the code in memory is still the original.
+pub fn run_frame<D: Display, B: Bus>(cpu: &mut CPU<D, B>, mut cycles: usize) {
+    while cycles > 0 {
+        if !cpu.is_waiting_for_key() && run_block(cpu, &mut cycles) {
+            continue;
+        }
+        cycles -= 1;
+        if cpu.step() {
+            break;
+        }
+    }
+    cpu.end_frame();
+}
+
+/// Runs the recompiled program and the interpreter side by side for `frames`
+/// frames of `cycles` cycles, with the same random numbers, and returns the
+/// first frame after which their screens or registers differ.
+pub fn verify(frames: usize, cycles: usize) -> Result<(), usize> {
+    let mut recompiled = CPU::with_platform(PLATFORM);
+    let mut interpreted = CPU::with_platform(PLATFORM);
+    for cpu in [&mut recompiled, &mut interpreted] {
+        cpu.load_program(&PROGRAM).unwrap();
+        cpu.seed_random(0);
+    }
+    for frame in 0..frames {
+        run_frame(&mut recompiled, cycles);
+        interpreted.run_frame(cycles);
+        let same = recompiled.pc() == interpreted.pc()
+            && recompiled.i() == interpreted.i()
+            && recompiled.registers() == interpreted.registers()
+            && recompiled.get_screen().pixels() == interpreted.get_screen().pixels();
+        if !same {
+            return Err(frame);
+        }
+    }
+    Ok(())
+}
+
+// Whether memory from `start` to `end` still holds the program.
+fn is_original<D: Display, B: Bus>(cpu: &CPU<D, B>, start: usize, end: usize) -> bool {
+    cpu.bus().bytes().get(start..end) == PROGRAM.get(start - LOAD_ADDRESS..end - LOAD_ADDRESS)
+}
+
+// Runs the block at PC if there is one and the frame has cycles left for all
+// of it.
+fn run_block<D: Display, B: Bus>(cpu: &mut CPU<D, B>, cycles: &mut usize) -> bool {
+    match cpu.pc() {
+        0x200 if *cycles >= 3 && is_original(cpu, 0x200, 0x206) => block_0200(cpu, cycles),
+        0x206 if *cycles >= 1 && is_original(cpu, 0x206, 0x208) => block_0206(cpu, cycles),
+        0x208 if *cycles >= 4 && is_original(cpu, 0x208, 0x210) => block_0208(cpu, cycles),
+        0x210 if *cycles >= 1 && is_original(cpu, 0x210, 0x212) => block_0210(cpu, cycles),
+        0x212 if *cycles >= 2 && is_original(cpu, 0x212, 0x216) => block_0212(cpu, cycles),
+        0x216 if *cycles >= 1 && is_original(cpu, 0x216, 0x218) => block_0216(cpu, cycles),
+        0x218 if *cycles >= 5 && is_original(cpu, 0x218, 0x222) => block_0218(cpu, cycles),
+        0x222 if *cycles >= 7 && is_original(cpu, 0x222, 0x230) => block_0222(cpu, cycles),
+        0x230 if *cycles >= 2 && is_original(cpu, 0x230, 0x234) => block_0230(cpu, cycles),
+        0x234 if *cycles >= 2 && is_original(cpu, 0x234, 0x238) => block_0234(cpu, cycles),
+        0x238 if *cycles >= 1 && is_original(cpu, 0x238, 0x23A) => block_0238(cpu, cycles),
+        0x23A if *cycles >= 6 && is_original(cpu, 0x23A, 0x246) => block_023a(cpu, cycles),
+        0x246 if *cycles >= 4 && is_original(cpu, 0x246, 0x24E) => block_0246(cpu, cycles),
+        0x24E if *cycles >= 1 && is_original(cpu, 0x24E, 0x250) => block_024e(cpu, cycles),
+        0x250 if *cycles >= 2 && is_original(cpu, 0x250, 0x254) => block_0250(cpu, cycles),
+        0x254 if *cycles >= 1 && is_original(cpu, 0x254, 0x256) => block_0254(cpu, cycles),
+        0x256 if *cycles >= 12 && is_original(cpu, 0x256, 0x26E) => block_0256(cpu, cycles),
+        0x26E if *cycles >= 1 && is_original(cpu, 0x26E, 0x270) => block_026e(cpu, cycles),
+        0x270 if *cycles >= 1 && is_original(cpu, 0x270, 0x272) => block_0270(cpu, cycles),
+        0x272 if *cycles >= 1 && is_original(cpu, 0x272, 0x274) => block_0272(cpu, cycles),
+        0x274 if *cycles >= 1 && is_original(cpu, 0x274, 0x276) => block_0274(cpu, cycles),
+        0x276 if *cycles >= 1 && is_original(cpu, 0x276, 0x278) => block_0276(cpu, cycles),
+        0x278 if *cycles >= 1 && is_original(cpu, 0x278, 0x27A) => block_0278(cpu, cycles),
+        0x27A if *cycles >= 1 && is_original(cpu, 0x27A, 0x27C) => block_027a(cpu, cycles),
+        0x27C if *cycles >= 2 && is_original(cpu, 0x27C, 0x280) => block_027c(cpu, cycles),
+        0x280 if *cycles >= 1 && is_original(cpu, 0x280, 0x282) => block_0280(cpu, cycles),
+        0x282 if *cycles >= 1 && is_original(cpu, 0x282, 0x284) => block_0282(cpu, cycles),
+        0x284 if *cycles >= 1 && is_original(cpu, 0x284, 0x286) => block_0284(cpu, cycles),
+        0x286 if *cycles >= 3 && is_original(cpu, 0x286, 0x28C) => block_0286(cpu, cycles),
+        0x28C if *cycles >= 1 && is_original(cpu, 0x28C, 0x28E) => block_028c(cpu, cycles),
+        0x28E if *cycles >= 10 && is_original(cpu, 0x28E, 0x2A2) => block_028e(cpu, cycles),
+        0x2A2 if *cycles >= 2 && is_original(cpu, 0x2A2, 0x2A6) => block_02a2(cpu, cycles),
+        0x2A6 if *cycles >= 1 && is_original(cpu, 0x2A6, 0x2A8) => block_02a6(cpu, cycles),
+        0x2A8 if *cycles >= 1 && is_original(cpu, 0x2A8, 0x2AA) => block_02a8(cpu, cycles),
+        0x2AA if *cycles >= 1 && is_original(cpu, 0x2AA, 0x2AC) => block_02aa(cpu, cycles),
+        0x2AC if *cycles >= 4 && is_original(cpu, 0x2AC, 0x2B4) => block_02ac(cpu, cycles),
+        0x2B4 if *cycles >= 1 && is_original(cpu, 0x2B4, 0x2B6) => block_02b4(cpu, cycles),
+        0x2B6 if *cycles >= 3 && is_original(cpu, 0x2B6, 0x2BC) => block_02b6(cpu, cycles),
+        0x2BC if *cycles >= 1 && is_original(cpu, 0x2BC, 0x2BE) => block_02bc(cpu, cycles),
+        0x2BE if *cycles >= 2 && is_original(cpu, 0x2BE, 0x2C2) => block_02be(cpu, cycles),
+        0x2C2 if *cycles >= 1 && is_original(cpu, 0x2C2, 0x2C4) => block_02c2(cpu, cycles),
+        0x2C4 if *cycles >= 2 && is_original(cpu, 0x2C4, 0x2C8) => block_02c4(cpu, cycles),
+        0x2C8 if *cycles >= 1 && is_original(cpu, 0x2C8, 0x2CA) => block_02c8(cpu, cycles),
+        0x2CA if *cycles >= 9 && is_original(cpu, 0x2CA, 0x2DC) => block_02ca(cpu, cycles),
+        0x2DC if *cycles >= 1 && is_original(cpu, 0x2DC, 0x2DE) => block_02dc(cpu, cycles),
+        0x2DE if *cycles >= 1 && is_original(cpu, 0x2DE, 0x2E0) => block_02de(cpu, cycles),
+        0x2E0 if *cycles >= 2 && is_original(cpu, 0x2E0, 0x2E4) => block_02e0(cpu, cycles),
+        0x2E4 if *cycles >= 1 && is_original(cpu, 0x2E4, 0x2E6) => block_02e4(cpu, cycles),
+        0x2E6 if *cycles >= 1 && is_original(cpu, 0x2E6, 0x2E8) => block_02e6(cpu, cycles),
+        0x2E8 if *cycles >= 2 && is_original(cpu, 0x2E8, 0x2EC) => block_02e8(cpu, cycles),
+        0x2EC if *cycles >= 1 && is_original(cpu, 0x2EC, 0x2EE) => block_02ec(cpu, cycles),
+        0x2EE if *cycles >= 4 && is_original(cpu, 0x2EE, 0x2F6) => block_02ee(cpu, cycles),
+        0x2F6 if *cycles >= 11 && is_original(cpu, 0x2F6, 0x30C) => block_02f6(cpu, cycles),
+        _ => return false,
+    }
+    true
+}
+
+fn block_0200<D: Display, B: Bus>(cpu: &mut CPU<D, B>, cycles: &mut usize) {
+    *cycles -= 3;
+    // 0x200  LD VE, 0x05
+    {
+        let v = cpu.registers_mut();
+        v[0xE] = 0x05;
+    }
+    // 0x202  LD V5, 0x00
+    {
+        let v = cpu.registers_mut();
+        v[0x5] = 0x00;
+    }
+    // 0x204  LD VB, 0x06
+    {
+        let v = cpu.registers_mut();
+        v[0xB] = 0x06;
+    }
+    cpu.set_pc(0x206);
+}
+
+fn block_0206<D: Display, B: Bus>(cpu: &mut CPU<D, B>, cycles: &mut usize) {
+    *cycles -= 1;
+    // 0x206  LD VA, 0x00
+    {
+        let v = cpu.registers_mut();
+        v[0xA] = 0x00;
+    }
+    cpu.set_pc(0x208);
+}
+
+fn block_0208<D: Display, B: Bus>(cpu: &mut CPU<D, B>, cycles: &mut usize) {
+    *cycles -= 4;
+    // 0x208  LD I, 0x30C
+    cpu.set_i(0x30C);
+    // 0x20A  DRW VA, VB, 1
+    cpu.set_pc(0x20A);
+    if cpu.step() {
+        *cycles = 0;
+        return;
+    }
+    // 0x20C  ADD VA, 0x04
+    {
+        let v = cpu.registers_mut();
+        v[0xA] = v[0xA].wrapping_add(0x04);
+    }
+    // 0x20E  SE VA, 0x40
+    let skip = cpu.registers()[0xA] == 0x40;
+    cpu.set_pc(if skip { 0x212 } else { 0x210 });
+}
+
+fn block_0210<D: Display, B: Bus>(cpu: &mut CPU<D, B>, cycles: &mut usize) {
+    *cycles -= 1;
+    // 0x210  JP 0x208
+    cpu.set_pc(0x208);
+}
+
+fn block_0212<D: Display, B: Bus>(cpu: &mut CPU<D, B>, cycles: &mut usize) {
+    *cycles -= 2;
+    // 0x212  ADD VB, 0x02
+    {
+        let v = cpu.registers_mut();
+        v[0xB] = v[0xB].wrapping_add(0x02);
+    }
+    // 0x214  SE VB, 0x12
+    let skip = cpu.registers()[0xB] == 0x12;
+    cpu.set_pc(if skip { 0x218 } else { 0x216 });
+}
+
+fn block_0216<D: Display, B: Bus>(cpu: &mut CPU<D, B>, cycles: &mut usize) {
+    *cycles -= 1;
+    // 0x216  JP 0x206
+    cpu.set_pc(0x206);
+}
+
+fn block_0218<D: Display, B: Bus>(cpu: &mut CPU<D, B>, cycles: &mut usize) {
+    *cycles -= 5;
+    // 0x218  LD VC, 0x20
+    {
+        let v = cpu.registers_mut();
+        v[0xC] = 0x20;
+    }
+    // 0x21A  LD VD, 0x1F
+    {
+        let v = cpu.registers_mut();
+        v[0xD] = 0x1F;
+    }
+    // 0x21C  LD I, 0x310
+    cpu.set_i(0x310);
+    // 0x21E  DRW VC, VD, 1
+    cpu.set_pc(0x21E);
+    if cpu.step() {
+        *cycles = 0;
+        return;
+    }
+    // 0x220  CALL 0x2F6
+    cpu.push(0x222);
+    cpu.set_pc(0x2F6);
+}
+
+fn block_0222<D: Display, B: Bus>(cpu: &mut CPU<D, B>, cycles: &mut usize) {
+    *cycles -= 7;
+    // 0x222  LD V0, 0x00
+    {
+        let v = cpu.registers_mut();
+        v[0x0] = 0x00;
+    }
+    // 0x224  LD V1, 0x00
+    {
+        let v = cpu.registers_mut();
+        v[0x1] = 0x00;
+    }
+    // 0x226  LD I, 0x312
+    cpu.set_i(0x312);
+    // 0x228  DRW V0, V1, 1
+    cpu.set_pc(0x228);
+    if cpu.step() {
+        *cycles = 0;
+        return;
+    }
+    // 0x22A  ADD V0, 0x08
+    {
+        let v = cpu.registers_mut();
+        v[0x0] = v[0x0].wrapping_add(0x08);
+    }
+    // 0x22C  LD I, 0x30E
+    cpu.set_i(0x30E);
+    // 0x22E  DRW V0, V1, 1
+    cpu.set_pc(0x22E);
+    if cpu.step() {
+        *cycles = 0;
+    }
+}
+
+fn block_0230<D: Display, B: Bus>(cpu: &mut CPU<D, B>, cycles: &mut usize) {
+    *cycles -= 2;
+    // 0x230  LD V0, 0x40
+    {
+        let v = cpu.registers_mut();
+        v[0x0] = 0x40;
+    }
+    // 0x232  LD DT, V0
+    cpu.set_pc(0x232);
+    if cpu.step() {
+        *cycles = 0;
+    }
+}
+
+fn block_0234<D: Display, B: Bus>(cpu: &mut CPU<D, B>, cycles: &mut usize) {
+    *cycles -= 2;
+    // 0x234  LD V0, DT
+    cpu.set_pc(0x234);
+    if cpu.step() {
+        *cycles = 0;
+        return;
+    }
+    // 0x236  SE V0, 0x00
+    let skip = cpu.registers()[0x0] == 0x00;
+    cpu.set_pc(if skip { 0x23A } else { 0x238 });
+}
+
+fn block_0238<D: Display, B: Bus>(cpu: &mut CPU<D, B>, cycles: &mut usize) {
+    *cycles -= 1;
+    // 0x238  JP 0x234
+    cpu.set_pc(0x234);
+}
+
+fn block_023a<D: Display, B: Bus>(cpu: &mut CPU<D, B>, cycles: &mut usize) {
+    *cycles -= 6;
+    // 0x23A  RND V6, 0x0F
+    cpu.set_pc(0x23A);
+    if cpu.step() {
+        *cycles = 0;
+        return;
+    }
+    // 0x23C  LD V7, 0x1E
+    {
+        let v = cpu.registers_mut();
+        v[0x7] = 0x1E;
+    }
+    // 0x23E  LD V8, 0x01
+    {
+        let v = cpu.registers_mut();
+        v[0x8] = 0x01;
+    }
+    // 0x240  LD V9, 0xFF
+    {
+        let v = cpu.registers_mut();
+        v[0x9] = 0xFF;
+    }
+    // 0x242  LD I, 0x30E
+    cpu.set_i(0x30E);
+    // 0x244  DRW V6, V7, 1
+    cpu.set_pc(0x244);
+    if cpu.step() {
+        *cycles = 0;
+    }
+}
+
+fn block_0246<D: Display, B: Bus>(cpu: &mut CPU<D, B>, cycles: &mut usize) {
+    *cycles -= 4;
+    // 0x246  LD I, 0x310
+    cpu.set_i(0x310);
+    // 0x248  DRW VC, VD, 1
+    cpu.set_pc(0x248);
+    if cpu.step() {
+        *cycles = 0;
+        return;
+    }
+    // 0x24A  LD V0, 0x04
+    {
+        let v = cpu.registers_mut();
+        v[0x0] = 0x04;
+    }
+    // 0x24C  SKNP V0
+    cpu.set_pc(0x24C);
+    if cpu.step() {
+        *cycles = 0;
+    }
+}
+
+fn block_024e<D: Display, B: Bus>(cpu: &mut CPU<D, B>, cycles: &mut usize) {
+    *cycles -= 1;
+    // 0x24E  ADD VC, 0xFE
+    {
+        let v = cpu.registers_mut();
+        v[0xC] = v[0xC].wrapping_add(0xFE);
+    }
+    cpu.set_pc(0x250);
+}
+
+fn block_0250<D: Display, B: Bus>(cpu: &mut CPU<D, B>, cycles: &mut usize) {
+    *cycles -= 2;
+    // 0x250  LD V0, 0x06
+    {
+        let v = cpu.registers_mut();
+        v[0x0] = 0x06;
+    }
+    // 0x252  SKNP V0
+    cpu.set_pc(0x252);
+    if cpu.step() {
+        *cycles = 0;
+    }
+}
+
+fn block_0254<D: Display, B: Bus>(cpu: &mut CPU<D, B>, cycles: &mut usize) {
+    *cycles -= 1;
+    // 0x254  ADD VC, 0x02
+    {
+        let v = cpu.registers_mut();
+        v[0xC] = v[0xC].wrapping_add(0x02);
+    }
+    cpu.set_pc(0x256);
+}
+
+fn block_0256<D: Display, B: Bus>(cpu: &mut CPU<D, B>, cycles: &mut usize) {
+    *cycles -= 12;
+    let quirks = cpu.quirks();
+    // 0x256  LD V0, 0x3F
+    {
+        let v = cpu.registers_mut();
+        v[0x0] = 0x3F;
+    }
+    // 0x258  AND VC, V0
+    {
+        let v = cpu.registers_mut();
+        v[0xC] &= v[0x0];
+        if quirks.logic {
+            v[0xF] = 0;
+        }
+    }
+    // 0x25A  DRW VC, VD, 1
+    cpu.set_pc(0x25A);
+    if cpu.step() {
+        *cycles = 0;
+        return;
+    }
+    // 0x25C  LD I, 0x30E
+    cpu.set_i(0x30E);
+    // 0x25E  DRW V6, V7, 1
+    cpu.set_pc(0x25E);
+    if cpu.step() {
+        *cycles = 0;
+        return;
+    }
+    // 0x260  ADD V6, V8
+    {
+        let v = cpu.registers_mut();
+        let (result, carry) = v[0x6].overflowing_add(v[0x8]);
+        v[0x6] = result;
+        v[0xF] = carry as u8;
+    }
+    // 0x262  ADD V7, V9
+    {
+        let v = cpu.registers_mut();
+        let (result, carry) = v[0x7].overflowing_add(v[0x9]);
+        v[0x7] = result;
+        v[0xF] = carry as u8;
+    }
+    // 0x264  LD V0, 0x3F
+    {
+        let v = cpu.registers_mut();
+        v[0x0] = 0x3F;
+    }
+    // 0x266  AND V6, V0
+    {
+        let v = cpu.registers_mut();
+        v[0x6] &= v[0x0];
+        if quirks.logic {
+            v[0xF] = 0;
+        }
+    }
+    // 0x268  LD V1, 0x1F
+    {
+        let v = cpu.registers_mut();
+        v[0x1] = 0x1F;
+    }
+    // 0x26A  AND V7, V1
+    {
+        let v = cpu.registers_mut();
+        v[0x7] &= v[0x1];
+        if quirks.logic {
+            v[0xF] = 0;
+        }
+    }
+    // 0x26C  SNE V7, 0x1F
+    let skip = cpu.registers()[0x7] != 0x1F;
+    cpu.set_pc(if skip { 0x270 } else { 0x26E });
+}
+
+fn block_026e<D: Display, B: Bus>(cpu: &mut CPU<D, B>, cycles: &mut usize) {
+    *cycles -= 1;
+    // 0x26E  JP 0x2AC
+    cpu.set_pc(0x2AC);
+}
+
+fn block_0270<D: Display, B: Bus>(cpu: &mut CPU<D, B>, cycles: &mut usize) {
+    *cycles -= 1;
+    // 0x270  SNE V6, 0x00
+    let skip = cpu.registers()[0x6] != 0x00;
+    cpu.set_pc(if skip { 0x274 } else { 0x272 });
+}
+
+fn block_0272<D: Display, B: Bus>(cpu: &mut CPU<D, B>, cycles: &mut usize) {
+    *cycles -= 1;
+    // 0x272  LD V8, 0x01
+    {
+        let v = cpu.registers_mut();
+        v[0x8] = 0x01;
+    }
+    cpu.set_pc(0x274);
+}
+
+fn block_0274<D: Display, B: Bus>(cpu: &mut CPU<D, B>, cycles: &mut usize) {
+    *cycles -= 1;
+    // 0x274  SNE V6, 0x3F
+    let skip = cpu.registers()[0x6] != 0x3F;
+    cpu.set_pc(if skip { 0x278 } else { 0x276 });
+}
+
+fn block_0276<D: Display, B: Bus>(cpu: &mut CPU<D, B>, cycles: &mut usize) {
+    *cycles -= 1;
+    // 0x276  LD V8, 0xFF
+    {
+        let v = cpu.registers_mut();
+        v[0x8] = 0xFF;
+    }
+    cpu.set_pc(0x278);
+}
+
+fn block_0278<D: Display, B: Bus>(cpu: &mut CPU<D, B>, cycles: &mut usize) {
+    *cycles -= 1;
+    // 0x278  SNE V7, 0x00
+    let skip = cpu.registers()[0x7] != 0x00;
+    cpu.set_pc(if skip { 0x27C } else { 0x27A });
+}
+
+fn block_027a<D: Display, B: Bus>(cpu: &mut CPU<D, B>, cycles: &mut usize) {
+    *cycles -= 1;
+    // 0x27A  LD V9, 0x01
+    {
+        let v = cpu.registers_mut();
+        v[0x9] = 0x01;
+    }
+    cpu.set_pc(0x27C);
+}
+
+fn block_027c<D: Display, B: Bus>(cpu: &mut CPU<D, B>, cycles: &mut usize) {
+    *cycles -= 2;
+    // 0x27C  DRW V6, V7, 1
+    cpu.set_pc(0x27C);
+    if cpu.step() {
+        *cycles = 0;
+        return;
+    }
+    // 0x27E  SE VF, 0x01
+    let skip = cpu.registers()[0xF] == 0x01;
+    cpu.set_pc(if skip { 0x282 } else { 0x280 });
+}
+
+fn block_0280<D: Display, B: Bus>(cpu: &mut CPU<D, B>, cycles: &mut usize) {
+    *cycles -= 1;
+    // 0x280  JP 0x2AA
+    cpu.set_pc(0x2AA);
+}
+
+fn block_0282<D: Display, B: Bus>(cpu: &mut CPU<D, B>, cycles: &mut usize) {
+    *cycles -= 1;
+    // 0x282  SNE V7, 0x1F
+    let skip = cpu.registers()[0x7] != 0x1F;
+    cpu.set_pc(if skip { 0x286 } else { 0x284 });
+}
+
+fn block_0284<D: Display, B: Bus>(cpu: &mut CPU<D, B>, cycles: &mut usize) {
+    *cycles -= 1;
+    // 0x284  JP 0x2AA
+    cpu.set_pc(0x2AA);
+}
+
+fn block_0286<D: Display, B: Bus>(cpu: &mut CPU<D, B>, cycles: &mut usize) {
+    *cycles -= 3;
+    // 0x286  LD V0, 0x05
+    {
+        let v = cpu.registers_mut();
+        v[0x0] = 0x05;
+    }
+    // 0x288  SUB V0, V7
+    {
+        let v = cpu.registers_mut();
+        let (result, borrow) = v[0x0].overflowing_sub(v[0x7]);
+        v[0x0] = result;
+        v[0xF] = !borrow as u8;
+    }
+    // 0x28A  SE VF, 0x00
+    let skip = cpu.registers()[0xF] == 0x00;
+    cpu.set_pc(if skip { 0x28E } else { 0x28C });
+}
+
+fn block_028c<D: Display, B: Bus>(cpu: &mut CPU<D, B>, cycles: &mut usize) {
+    *cycles -= 1;
+    // 0x28C  JP 0x2AA
+    cpu.set_pc(0x2AA);
+}
+
+fn block_028e<D: Display, B: Bus>(cpu: &mut CPU<D, B>, cycles: &mut usize) {
+    *cycles -= 10;
+    let quirks = cpu.quirks();
+    // 0x28E  LD V0, 0x01
+    {
+        let v = cpu.registers_mut();
+        v[0x0] = 0x01;
+    }
+    // 0x290  LD ST, V0
+    cpu.set_pc(0x290);
+    if cpu.step() {
+        *cycles = 0;
+        return;
+    }
+    // 0x292  LD V0, V6
+    {
+        let v = cpu.registers_mut();
+        v[0x0] = v[0x6];
+    }
+    // 0x294  LD V1, 0xFC
+    {
+        let v = cpu.registers_mut();
+        v[0x1] = 0xFC;
+    }
+    // 0x296  AND V0, V1
+    {
+        let v = cpu.registers_mut();
+        v[0x0] &= v[0x1];
+        if quirks.logic {
+            v[0xF] = 0;
+        }
+    }
+    // 0x298  LD I, 0x30C
+    cpu.set_i(0x30C);
+    // 0x29A  DRW V0, V7, 1
+    cpu.set_pc(0x29A);
+    if cpu.step() {
+        *cycles = 0;
+        return;
+    }
+    // 0x29C  LD V0, 0xFE
+    {
+        let v = cpu.registers_mut();
+        v[0x0] = 0xFE;
+    }
+    // 0x29E  XOR V9, V0
+    {
+        let v = cpu.registers_mut();
+        v[0x9] ^= v[0x0];
+        if quirks.logic {
+            v[0xF] = 0;
+        }
+    }
+    // 0x2A0  CALL 0x2F6
+    cpu.push(0x2A2);
+    cpu.set_pc(0x2F6);
+}
+
+fn block_02a2<D: Display, B: Bus>(cpu: &mut CPU<D, B>, cycles: &mut usize) {
+    *cycles -= 2;
+    // 0x2A2  ADD V5, 0x01
+    {
+        let v = cpu.registers_mut();
+        v[0x5] = v[0x5].wrapping_add(0x01);
+    }
+    // 0x2A4  CALL 0x2F6
+    cpu.push(0x2A6);
+    cpu.set_pc(0x2F6);
+}
+
+fn block_02a6<D: Display, B: Bus>(cpu: &mut CPU<D, B>, cycles: &mut usize) {
+    *cycles -= 1;
+    // 0x2A6  SNE V5, 0x60
+    let skip = cpu.registers()[0x5] != 0x60;
+    cpu.set_pc(if skip { 0x2AA } else { 0x2A8 });
+}
+
+fn block_02a8<D: Display, B: Bus>(cpu: &mut CPU<D, B>, cycles: &mut usize) {
+    *cycles -= 1;
+    // 0x2A8  JP 0x2DE
+    cpu.set_pc(0x2DE);
+}
+
+fn block_02aa<D: Display, B: Bus>(cpu: &mut CPU<D, B>, cycles: &mut usize) {
+    *cycles -= 1;
+    // 0x2AA  JP 0x246
+    cpu.set_pc(0x246);
+}
+
+fn block_02ac<D: Display, B: Bus>(cpu: &mut CPU<D, B>, cycles: &mut usize) {
+    *cycles -= 4;
+    // 0x2AC  LD V9, 0xFF
+    {
+        let v = cpu.registers_mut();
+        v[0x9] = 0xFF;
+    }
+    // 0x2AE  LD V0, V6
+    {
+        let v = cpu.registers_mut();
+        v[0x0] = v[0x6];
+    }
+    // 0x2B0  SUB V0, VC
+    {
+        let v = cpu.registers_mut();
+        let (result, borrow) = v[0x0].overflowing_sub(v[0xC]);
+        v[0x0] = result;
+        v[0xF] = !borrow as u8;
+    }
+    // 0x2B2  SE VF, 0x01
+    let skip = cpu.registers()[0xF] == 0x01;
+    cpu.set_pc(if skip { 0x2B6 } else { 0x2B4 });
+}
+
+fn block_02b4<D: Display, B: Bus>(cpu: &mut CPU<D, B>, cycles: &mut usize) {
+    *cycles -= 1;
+    // 0x2B4  JP 0x2CA
+    cpu.set_pc(0x2CA);
+}
+
+fn block_02b6<D: Display, B: Bus>(cpu: &mut CPU<D, B>, cycles: &mut usize) {
+    *cycles -= 3;
+    // 0x2B6  LD V1, 0x02
+    {
+        let v = cpu.registers_mut();
+        v[0x1] = 0x02;
+    }
+    // 0x2B8  SUB V0, V1
+    {
+        let v = cpu.registers_mut();
+        let (result, borrow) = v[0x0].overflowing_sub(v[0x1]);
+        v[0x0] = result;
+        v[0xF] = !borrow as u8;
+    }
+    // 0x2BA  SE VF, 0x01
+    let skip = cpu.registers()[0xF] == 0x01;
+    cpu.set_pc(if skip { 0x2BE } else { 0x2BC });
+}
+
+fn block_02bc<D: Display, B: Bus>(cpu: &mut CPU<D, B>, cycles: &mut usize) {
+    *cycles -= 1;
+    // 0x2BC  JP 0x2E0
+    cpu.set_pc(0x2E0);
+}
+
+fn block_02be<D: Display, B: Bus>(cpu: &mut CPU<D, B>, cycles: &mut usize) {
+    *cycles -= 2;
+    // 0x2BE  SUB V0, V1
+    {
+        let v = cpu.registers_mut();
+        let (result, borrow) = v[0x0].overflowing_sub(v[0x1]);
+        v[0x0] = result;
+        v[0xF] = !borrow as u8;
+    }
+    // 0x2C0  SE VF, 0x01
+    let skip = cpu.registers()[0xF] == 0x01;
+    cpu.set_pc(if skip { 0x2C4 } else { 0x2C2 });
+}
+
+fn block_02c2<D: Display, B: Bus>(cpu: &mut CPU<D, B>, cycles: &mut usize) {
+    *cycles -= 1;
+    // 0x2C2  JP 0x2EE
+    cpu.set_pc(0x2EE);
+}
+
+fn block_02c4<D: Display, B: Bus>(cpu: &mut CPU<D, B>, cycles: &mut usize) {
+    *cycles -= 2;
+    // 0x2C4  SUB V0, V1
+    {
+        let v = cpu.registers_mut();
+        let (result, borrow) = v[0x0].overflowing_sub(v[0x1]);
+        v[0x0] = result;
+        v[0xF] = !borrow as u8;
+    }
+    // 0x2C6  SE VF, 0x01
+    let skip = cpu.registers()[0xF] == 0x01;
+    cpu.set_pc(if skip { 0x2CA } else { 0x2C8 });
+}
+
+fn block_02c8<D: Display, B: Bus>(cpu: &mut CPU<D, B>, cycles: &mut usize) {
+    *cycles -= 1;
+    // 0x2C8  JP 0x2E8
+    cpu.set_pc(0x2E8);
+}
+
+fn block_02ca<D: Display, B: Bus>(cpu: &mut CPU<D, B>, cycles: &mut usize) {
+    *cycles -= 9;
+    // 0x2CA  LD V0, 0x20
+    {
+        let v = cpu.registers_mut();
+        v[0x0] = 0x20;
+    }
+    // 0x2CC  LD ST, V0
+    cpu.set_pc(0x2CC);
+    if cpu.step() {
+        *cycles = 0;
+        return;
+    }
+    // 0x2CE  LD I, 0x30E
+    cpu.set_i(0x30E);
+    // 0x2D0  ADD VE, 0xFF
+    {
+        let v = cpu.registers_mut();
+        v[0xE] = v[0xE].wrapping_add(0xFF);
+    }
+    // 0x2D2  LD V0, VE
+    {
+        let v = cpu.registers_mut();
+        v[0x0] = v[0xE];
+    }
+    // 0x2D4  ADD V0, V0
+    {
+        let v = cpu.registers_mut();
+        let (result, carry) = v[0x0].overflowing_add(v[0x0]);
+        v[0x0] = result;
+        v[0xF] = carry as u8;
+    }
+    // 0x2D6  LD V1, 0x00
+    {
+        let v = cpu.registers_mut();
+        v[0x1] = 0x00;
+    }
+    // 0x2D8  DRW V0, V1, 1
+    cpu.set_pc(0x2D8);
+    if cpu.step() {
+        *cycles = 0;
+        return;
+    }
+    // 0x2DA  SE VE, 0x00
+    let skip = cpu.registers()[0xE] == 0x00;
+    cpu.set_pc(if skip { 0x2DE } else { 0x2DC });
+}
+
+fn block_02dc<D: Display, B: Bus>(cpu: &mut CPU<D, B>, cycles: &mut usize) {
+    *cycles -= 1;
+    // 0x2DC  JP 0x230
+    cpu.set_pc(0x230);
+}
+
+fn block_02de<D: Display, B: Bus>(cpu: &mut CPU<D, B>, cycles: &mut usize) {
+    *cycles -= 1;
+    // 0x2DE  JP 0x2DE
+    cpu.set_pc(0x2DE);
+}
+
+fn block_02e0<D: Display, B: Bus>(cpu: &mut CPU<D, B>, cycles: &mut usize) {
+    *cycles -= 2;
+    // 0x2E0  ADD V8, 0xFF
+    {
+        let v = cpu.registers_mut();
+        v[0x8] = v[0x8].wrapping_add(0xFF);
+    }
+    // 0x2E2  SNE V8, 0xFE
+    let skip = cpu.registers()[0x8] != 0xFE;
+    cpu.set_pc(if skip { 0x2E6 } else { 0x2E4 });
+}
+
+fn block_02e4<D: Display, B: Bus>(cpu: &mut CPU<D, B>, cycles: &mut usize) {
+    *cycles -= 1;
+    // 0x2E4  LD V8, 0xFF
+    {
+        let v = cpu.registers_mut();
+        v[0x8] = 0xFF;
+    }
+    cpu.set_pc(0x2E6);
+}
+
+fn block_02e6<D: Display, B: Bus>(cpu: &mut CPU<D, B>, cycles: &mut usize) {
+    *cycles -= 1;
+    // 0x2E6  JP 0x2EE
+    cpu.set_pc(0x2EE);
+}
+
+fn block_02e8<D: Display, B: Bus>(cpu: &mut CPU<D, B>, cycles: &mut usize) {
+    *cycles -= 2;
+    // 0x2E8  ADD V8, 0x01
+    {
+        let v = cpu.registers_mut();
+        v[0x8] = v[0x8].wrapping_add(0x01);
+    }
+    // 0x2EA  SNE V8, 0x02
+    let skip = cpu.registers()[0x8] != 0x02;
+    cpu.set_pc(if skip { 0x2EE } else { 0x2EC });
+}
+
+fn block_02ec<D: Display, B: Bus>(cpu: &mut CPU<D, B>, cycles: &mut usize) {
+    *cycles -= 1;
+    // 0x2EC  LD V8, 0x01
+    {
+        let v = cpu.registers_mut();
+        v[0x8] = 0x01;
+    }
+    cpu.set_pc(0x2EE);
+}
+
+fn block_02ee<D: Display, B: Bus>(cpu: &mut CPU<D, B>, cycles: &mut usize) {
+    *cycles -= 4;
+    // 0x2EE  LD V0, 0x04
+    {
+        let v = cpu.registers_mut();
+        v[0x0] = 0x04;
+    }
+    // 0x2F0  LD ST, V0
+    cpu.set_pc(0x2F0);
+    if cpu.step() {
+        *cycles = 0;
+        return;
+    }
+    // 0x2F2  LD V9, 0xFF
+    {
+        let v = cpu.registers_mut();
+        v[0x9] = 0xFF;
+    }
+    // 0x2F4  JP 0x270
+    cpu.set_pc(0x270);
+}
+
+fn block_02f6<D: Display, B: Bus>(cpu: &mut CPU<D, B>, cycles: &mut usize) {
+    *cycles -= 11;
+    // 0x2F6  LD I, 0x314
+    cpu.set_i(0x314);
+    // 0x2F8  LD B, V5
+    cpu.set_pc(0x2F8);
+    if cpu.step() {
+        *cycles = 0;
+        return;
+    }
+    // 0x2FA  LD V2, [I]
+    cpu.set_pc(0x2FA);
+    if cpu.step() {
+        *cycles = 0;
+        return;
+    }
+    // 0x2FC  LD F, V1
+    cpu.set_pc(0x2FC);
+    if cpu.step() {
+        *cycles = 0;
+        return;
+    }
+    // 0x2FE  LD V3, 0x37
+    {
+        let v = cpu.registers_mut();
+        v[0x3] = 0x37;
+    }
+    // 0x300  LD V4, 0x00
+    {
+        let v = cpu.registers_mut();
+        v[0x4] = 0x00;
+    }
+    // 0x302  DRW V3, V4, 5
+    cpu.set_pc(0x302);
+    if cpu.step() {
+        *cycles = 0;
+        return;
+    }
+    // 0x304  ADD V3, 0x05
+    {
+        let v = cpu.registers_mut();
+        v[0x3] = v[0x3].wrapping_add(0x05);
+    }
+    // 0x306  LD F, V2
+    cpu.set_pc(0x306);
+    if cpu.step() {
+        *cycles = 0;
+        return;
+    }
+    // 0x308  DRW V3, V4, 5
+    cpu.set_pc(0x308);
+    if cpu.step() {
+        *cycles = 0;
+        return;
+    }
+    // 0x30A  RET
+    let pc = cpu.pop();
+    cpu.set_pc(pc);
+}
+
+#[cfg(test)]
+mod tests {
+    #[test]
+    fn test_matches_interpreter() {
+        assert_eq!(super::verify(600, 8), Ok(()));
+    }
+}
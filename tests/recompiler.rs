@@ -0,0 +1,4 @@
+//! Runs the checked-in recompiled games against the interpreter.
+
+#[path = "recompiled/brix.rs"]
+mod brix;
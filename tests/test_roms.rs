@@ -0,0 +1,20 @@
+//! Runs the test ROMs under `tests/fixtures/roms` against their golden screens.
+
+use chip8::suite::{self, Outcome};
+use std::path::Path;
+
+#[test]
+fn test_rom_suite() {
+    let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/roms");
+    let results = suite::run_suite(&dir, false).unwrap();
+    let mut failures = Vec::new();
+    for (name, outcome) in results.iter() {
+        println!("{:<16} {}", name, outcome);
+        if *outcome != Outcome::Pass {
+            failures.push(format!("{}: {}", name, outcome));
+        }
+    }
+    assert!(failures.is_empty(), "{}", failures.join("\n"));
+    let passed = results.iter().filter(|(_, outcome)| *outcome == Outcome::Pass).count();
+    assert!(passed >= 6, "only {} tests passed", passed);
+}
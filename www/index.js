@@ -1,56 +1,52 @@
-import { Chip8 } from "chip8-wasm";
+import { Chip8, Engine, Layout, PersistenceMode } from "chip8-wasm";
 import { memory } from "chip8-wasm/chip8_bg";
 
 const SCALE = 5;
-const SPEED = 8;
 
 // wasm component
 const chip8 = Chip8.new();
-const width = chip8.width();
-const height = chip8.height();
+chip8.set_scale(SCALE);
+chip8.set_palette(0xFFFFFFFF, 0x000000FF);
+chip8.set_persistence_mode(PersistenceMode.Decay);
 
 // UI component
 const canvas = document.getElementById("chip8-canvas");
-canvas.width = width * SCALE;
-canvas.height = height * SCALE;
+canvas.width = chip8.framebuffer_width();
+canvas.height = chip8.framebuffer_height();
 const ctx = canvas.getContext("2d");
 const playBtn = document.getElementById("play-btn");
 const rom = document.getElementById("rom");
+const layout = document.getElementById("layout");
+const builtin = document.getElementById("builtin");
+const controls = document.getElementById("controls");
+const heatmap = document.getElementById("heatmap");
+const heatmapCanvas = document.getElementById("heatmap-canvas");
+const heatmapCtx = heatmapCanvas.getContext("2d");
 
-const keymap = {
-  49: 0x1, // 1
-  50: 0x2, // 2
-  51: 0x3, // 3
-  52: 0xc, // 4
-  81: 0x4, // Q
-  87: 0x5, // W
-  69: 0x6, // E
-  82: 0xd, // R
-  65: 0x7, // A
-  83: 0x8, // S
-  68: 0x9, // D
-  70: 0xe, // F
-  90: 0xa, // Z
-  88: 0x0, // X
-  67: 0xb, // C
-  86: 0xf // V
-};
-
-const getIndex = (row, col) => {
-    return row * width + col;
-}
+// One pixel per byte of the first 4K of memory, 64 bytes to a row.
+const HEATMAP_WIDTH = 64;
+const HEATMAP_SCALE = 4;
+heatmapCanvas.width = HEATMAP_WIDTH * HEATMAP_SCALE;
+heatmapCanvas.height = HEATMAP_WIDTH * HEATMAP_SCALE;
+heatmapCanvas.style.imageRendering = "pixelated";
 
 const updateScreen = () => {
-    ctx.clearRect(0, 0, canvas.width, canvas.height);
-    const pixels = new Uint8Array(memory.buffer, chip8.get_screen_memory(), width * height);
-    for (var row = 0; row < height; row++) {
-        for (var col = 0; col < width; col++) {
-            const idx = getIndex(row, col);
-            if (pixels[idx]) {
-                ctx.fillStyle = "#000000";
-                ctx.fillRect(col * SCALE, row * SCALE, SCALE, SCALE);
-            }
-        }
+    const rect = chip8.dirty_frame_rect();
+    chip8.take_dirty_rects();
+    if (!chip8.update_framebuffer()) {
+        return;
+    }
+    if (canvas.width !== chip8.framebuffer_width() || canvas.height !== chip8.framebuffer_height()) {
+        canvas.width = chip8.framebuffer_width();
+        canvas.height = chip8.framebuffer_height();
+    }
+    const pixels = new Uint8ClampedArray(memory.buffer, chip8.framebuffer_ptr(), chip8.framebuffer_len());
+    const image = new ImageData(pixels, canvas.width, canvas.height);
+    if (rect.length && chip8.persistence_mode() === PersistenceMode.Off) {
+        const [x, y, w, h] = rect;
+        ctx.putImageData(image, 0, 0, x * SCALE, y * SCALE, w * SCALE, h * SCALE);
+    } else {
+        ctx.putImageData(image, 0, 0);
     }
 }
 
@@ -58,31 +54,97 @@ rom.onchange = function () {
     var file = this.files[0]
     var reader = new FileReader();
     reader.onload = function(e) {
-        chip8.reset();
-        chip8.load_program(new Uint8Array(e.target.result));
+        const bytes = new Uint8Array(e.target.result);
+        chip8.set_gamepad_profile(file.name.split(".")[0]);
+        try {
+            chip8.load_rom(file.name, bytes);
+        } catch (err) {
+            alert(err);
+        }
+        const info = chip8.rom_info();
+        document.title = info ? JSON.parse(info).title : file.name;
     }
     reader.readAsArrayBuffer(file);
     updateScreen();
 }
 
+// Only available when the package is built with the builtin-roms feature.
+const builtinRoms = Chip8.list_builtin_roms ? JSON.parse(Chip8.list_builtin_roms()) : [];
+builtin.hidden = builtinRoms.length === 0;
+builtin.add(new Option("Bundled games", ""));
+builtinRoms.forEach(game => builtin.add(new Option(game.name, game.name)));
+
+builtin.onchange = function () {
+    const game = builtinRoms.find(game => game.name === this.value);
+    if (game && chip8.load_builtin(game.name)) {
+        controls.textContent = game.controls;
+        updateScreen();
+    }
+}
+
 document.addEventListener("keydown", event => {
-    chip8.key_down(keymap[event.keyCode]);
+    if (chip8.host_key_down(event.code)) {
+        event.preventDefault();
+    }
 });
 
 document.addEventListener("keyup", event => {
-    chip8.key_up(keymap[event.keyCode]);
+    if (chip8.host_key_up(event.code)) {
+        event.preventDefault();
+    }
 });
 
+const cachedBlocks = document.getElementById("cached-blocks");
+cachedBlocks.onchange = function () {
+    chip8.set_engine(this.checked ? Engine.CachedBlocks : Engine.Interpreter);
+}
+
+heatmap.onchange = function () {
+    chip8.set_profiling(this.checked);
+    heatmapCanvas.hidden = !this.checked;
+}
+
+// Fetches in green, reads in blue and writes in red, on a log scale.
+const updateHeatmap = () => {
+    const channels = [chip8.profile_writes(), chip8.profile_fetches(), chip8.profile_reads()];
+    const size = HEATMAP_WIDTH * HEATMAP_WIDTH;
+    const max = Math.log1p(Math.max(1, ...channels.map(counts => Math.max(0, ...counts.subarray(0, size)))));
+    const image = heatmapCtx.createImageData(HEATMAP_WIDTH, HEATMAP_WIDTH);
+    for (let addr = 0; addr < size; addr++) {
+        channels.forEach((counts, channel) => {
+            image.data[addr * 4 + channel] = 255 * Math.log1p(counts[addr] || 0) / max;
+        });
+        image.data[addr * 4 + 3] = 255;
+    }
+    heatmapCtx.putImageData(image, 0, 0);
+    heatmapCtx.drawImage(heatmapCanvas, 0, 0, HEATMAP_WIDTH, HEATMAP_WIDTH,
+        0, 0, heatmapCanvas.width, heatmapCanvas.height);
+}
+
+layout.onchange = function () {
+    chip8.set_keyboard_layout(Layout[this.value]);
+}
+
 var paused = true
 
+const pollGamepad = () => {
+    const gamepad = Array.from(navigator.getGamepads ? navigator.getGamepads() : []).find(gp => gp);
+    if (gamepad) {
+        chip8.update_gamepad(
+            Float32Array.from(gamepad.buttons, button => button.value),
+            Float32Array.from(gamepad.axes));
+    }
+}
+
 const renderLoop = () => {
     if (!paused) {
-        for (var i = 0; i < SPEED; i++) {
-            chip8.execute_next();
-        }
-        chip8.update_timer();
+        pollGamepad();
+        chip8.run_frame(chip8.tick_rate());
     }
     updateScreen();
+    if (heatmap.checked) {
+        updateHeatmap();
+    }
     requestAnimationFrame(renderLoop);
 }
 